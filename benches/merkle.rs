@@ -0,0 +1,36 @@
+//! Times `MerkleTree::new` against `MerkleTree::new_parallel` on a 1M-leaf tree.
+//! Run with `cargo bench --features parallel`.
+use std::time::{Duration, Instant};
+use num_bigint::BigInt;
+use zk_mock_rust::hash_functions::ModSquareHash;
+use zk_mock_rust::merkle::MerkleTree;
+
+const LEAVES: usize = 1 << 20;
+const RUNS: u32 = 3;
+
+/// Builds a tree `RUNS` times with `build` and returns the fastest run along with the root it computed.
+fn fastest<F: Fn(Vec<BigInt>) -> BigInt>(leaves: &[BigInt], build: F) -> (Duration, BigInt) {
+    let mut best = None;
+    let mut root = BigInt::from(0);
+    for _ in 0..RUNS {
+        let leaves = leaves.to_vec();
+        let start = Instant::now();
+        root = build(leaves);
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+    }
+    (best.expect("at least one run"), root)
+}
+
+fn main() {
+    let leaves: Vec<BigInt> = (0..LEAVES as u64).map(|leaf| BigInt::from(leaf * 7 + 3)).collect();
+
+    let (serial, serial_root) = fastest(&leaves, |leaves| MerkleTree::new(leaves, ModSquareHash::default()).root);
+    let (parallel, parallel_root) = fastest(&leaves, |leaves| MerkleTree::new_parallel(leaves, ModSquareHash::default()).root);
+    assert_eq!(serial_root, parallel_root, "parallel construction computed a different root");
+
+    println!("merkle tree of {} leaves, fastest of {} runs", LEAVES, RUNS);
+    println!("  new:          {:>10.1?}", serial);
+    println!("  new_parallel: {:>10.1?}", parallel);
+    println!("  speedup:      {:>10.2}x", serial.as_secs_f64() / parallel.as_secs_f64());
+}
@@ -0,0 +1,131 @@
+use num_bigint::BigInt;
+use crate::circuit::{Circuit, Gate};
+use crate::hash_functions::HashFunction;
+
+/// Handle to a wire of a circuit under construction, returned by every `CircuitBuilder` operation.
+/// Carries the wire's index and the value computed for it, so intermediate results can be inspected while building.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wire {
+  index: usize,
+  value: BigInt
+}
+
+impl Wire {
+  /// Returns the wire's index in the built circuit.
+  pub fn index(&self) -> usize {
+    self.index
+  }
+
+  /// Returns the value the wire holds in the witness.
+  pub fn value(&self) -> &BigInt {
+    &self.value
+  }
+}
+
+/**
+ * Builds a circuit by chaining operations on wire handles instead of bookkeeping wire indices by hand:
+ *
+ * let mut builder = CircuitBuilder::new(None);
+ * let (x, y, z) = (builder.input(BigInt::from(2)), builder.input(BigInt::from(3)), builder.public_input(BigInt::from(4)));
+ * let sum = builder.add(&x, &y);
+ * let product = builder.mul(&sum, &z);
+ * let circuit = builder.build();
+ *
+ * Every operation adds its gate together with a fresh wire for the result, computed on the spot
+ * (see `Circuit::add_gate_with_output`), so the built circuit already holds a satisfying witness.
+ */
+pub struct CircuitBuilder {
+  circuit: Circuit
+}
+
+impl CircuitBuilder {
+  /// Starts an empty circuit with an optional hash function, needed for `hash`.
+  pub fn new(hash_function: Option<Box<dyn HashFunction>>) -> Self {
+    CircuitBuilder { circuit: Circuit::new(hash_function) }
+  }
+
+  /// Adds a private input holding `value`.
+  pub fn input(&mut self, value: BigInt) -> Wire {
+    let index = self.circuit.add_input(value.clone());
+    Wire { index, value }
+  }
+
+  /// Adds a public input holding `value`, known to the verifier.
+  pub fn public_input(&mut self, value: BigInt) -> Wire {
+    let index = self.circuit.add_public_input(value.clone());
+    Wire { index, value }
+  }
+
+  /// Adds a wire pinned to `value` by a Const gate.
+  pub fn constant(&mut self, value: BigInt) -> Wire {
+    self.gate(|output| Gate::Const(output, value))
+  }
+
+  /// Returns a wire holding a + b.
+  pub fn add(&mut self, a: &Wire, b: &Wire) -> Wire {
+    self.gate(|output| Gate::Add(a.index, b.index, output))
+  }
+
+  /// Returns a wire holding a - b.
+  pub fn sub(&mut self, a: &Wire, b: &Wire) -> Wire {
+    self.gate(|output| Gate::Sub(a.index, b.index, output))
+  }
+
+  /// Returns a wire holding a * b.
+  pub fn mul(&mut self, a: &Wire, b: &Wire) -> Wire {
+    self.gate(|output| Gate::Mul(a.index, b.index, output))
+  }
+
+  /// Returns a wire holding hash(a, b). Panics if the builder has no hash function.
+  pub fn hash(&mut self, a: &Wire, b: &Wire) -> Wire {
+    self.gate(|output| Gate::Hash(a.index, b.index, output))
+  }
+
+  /// Asserts that a and b hold the same value.
+  pub fn assert_equal(&mut self, a: &Wire, b: &Wire) {
+    self.circuit.add_gate(Gate::AssertEqual(a.index, b.index));
+  }
+
+  /// Publishes the value of `wire` in the proof under `name`.
+  pub fn output(&mut self, name: &str, wire: &Wire) {
+    self.circuit.add_named_output(name, wire.index);
+  }
+
+  /// Finishes building, returning the circuit; its inputs hold the computed witness.
+  pub fn build(self) -> Circuit {
+    self.circuit
+  }
+
+  /// Adds the gate `gate` builds around a fresh output wire and returns the handle to that wire.
+  fn gate<F: FnOnce(usize) -> Gate>(&mut self, gate: F) -> Wire {
+    let index = self.circuit.add_gate_with_output(gate);
+    let value = self.circuit.get_input(index).expect("the output wire was just added").clone();
+    Wire { index, value }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sum_times_product_built_fluently_verifies() {
+    let mut builder = CircuitBuilder::new(None);
+    let (x, y, z) = (builder.input(BigInt::from(2)), builder.input(BigInt::from(3)), builder.public_input(BigInt::from(4)));
+    let sum = builder.add(&x, &y);
+    let product = builder.mul(&sum, &z);
+    assert_eq!(sum.value(), &BigInt::from(5));
+    assert_eq!(product.value(), &BigInt::from(20));
+    builder.output("product", &product);
+    let circuit = builder.build();
+    assert_eq!(circuit.gates(), &[Gate::Add(x.index(), y.index(), sum.index()), Gate::Mul(sum.index(), z.index(), product.index())]);
+
+    let file = std::env::temp_dir().join(format!("zk-mock-builder-{}.bin", std::process::id()))
+      .to_str().expect("temporary directory path is valid UTF-8").to_string();
+    let proof = circuit.generate_proof(&file);
+    let verified = circuit.verify_proof(&file);
+    let _ = std::fs::remove_file(&file);
+    assert!(proof.unwrap().valid);
+    assert!(verified.unwrap());
+  }
+}
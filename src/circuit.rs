@@ -1,25 +1,378 @@
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::BufRead;
 use num_bigint::BigInt;
-use crate::hash_functions::HashFunction;
-use crate::r1cs::{Operation, R1CS, Variable};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::error::ZkMockError;
+use crate::hash_functions::{self, HashFunction};
+use crate::merkle::MerkleTree;
+use crate::proof::{Proof, VerificationCache, VerificationReport};
+use crate::r1cs::{self, ConstraintSynthesizer, Operation, R1CS, Variable};
+use crate::transcript::Transcript;
 
 /**
  * @note
  * zk-proof operations happens through the usage of gates
  */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Gate{
   Add(usize, usize, usize), // Add: input-A, input-B, output
   Mul(usize, usize, usize), // Mul: input-B, input-B, output
-  Hash(usize, usize, usize) // Hash: input-A, input-B, output
+  Hash(usize, usize, usize), // Hash: input-A, input-B, output
+  Commit(usize, usize, usize), // Commit: value, randomness, commitment (hiding commitment opened as hash(value, randomness))
+  Const(usize, BigInt), // Const: wire, the constant the wire is pinned to
+  IsZero(usize, usize, usize), // IsZero: input-X, output (1 if X == 0 else 0), witness-inverse of X
+  Mux(usize, Vec<usize>, usize), // Mux: index, options, output (= options[index])
+  /// Term i raises its own input wire to the power i, so a polynomial in one wire x repeats it in every position:
+  /// `Poly(vec![x, x, x], vec![c0, c1, c2], out)` is c0 + c1 * x + c2 * x^2. Term 0 is the constant c0 whatever its wire holds.
+  Poly(Vec<usize>, Vec<BigInt>, usize), // Poly: inputs, coefficients, output (= Σ coeffs[i] * inputs[i]^i)
+  Sub(usize, usize, usize), // Sub: input-A, input-B, output (= A - B)
+  LessThanOrEqual(usize, usize), // LessThanOrEqual: input-A, input-B, asserts A <= B (no output wire)
+  Assert(usize), // Assert: wire, asserts it holds 1, e.g. the output of a comparison or IsZero gadget (no output wire)
+  Linear(Vec<(usize, BigInt)>, usize), // Linear: (input, coefficient) terms, output (= Σ coeff_i * input_i)
+  AssertEqual(usize, usize), // AssertEqual: input-A, input-B, asserts A == B, e.g. a hash output against a public commitment (no output wire)
+  LessThan(usize, usize), // LessThan: input-A, input-B, asserts A < B with both in [0, 2^bits), see `Circuit::set_comparison_bits` (no output wire)
+  Bool(usize) // Bool: wire, asserts it holds 0 or 1, e.g. a selector or a bit of a range check (no output wire)
 }
 
+/// Default bit width of comparison gates: LessThanOrEqual only holds when 0 <= B - A < 2^64.
+pub const DEFAULT_COMPARISON_BITS: usize = 64;
+
+/// Widest comparison `Circuit::from_bytes` accepts; 2^bits is materialized when checking and lowering comparisons.
+pub const MAX_COMPARISON_BITS: usize = 4096;
+
+/// A revealed wire of a witness: its index, its value and its authentication path in the witness tree, see `Circuit::witness_path`.
+pub type WireOpening = (usize, BigInt, Vec<(BigInt, bool)>);
+
+impl Gate {
+  /// Returns every wire index the gate reads from or writes to.
+  pub fn wires(&self) -> Vec<usize> {
+    match self {
+      Gate::Add(a, b, output) | Gate::Sub(a, b, output) | Gate::Mul(a, b, output) | Gate::Hash(a, b, output) | Gate::Commit(a, b, output) => vec![*a, *b, *output],
+      Gate::Const(wire, _) => vec![*wire],
+      Gate::IsZero(x, output, inv) => vec![*x, *output, *inv],
+      Gate::Mux(index, options, output) => [*index].into_iter().chain(options.iter().copied()).chain([*output]).collect(),
+      Gate::Poly(inputs, _, output) => inputs.iter().copied().chain([*output]).collect(),
+      Gate::LessThanOrEqual(a, b) => vec![*a, *b],
+      Gate::Assert(wire) => vec![*wire],
+      Gate::Linear(terms, output) => terms.iter().map(|(input, _)| *input).chain([*output]).collect(),
+      Gate::AssertEqual(a, b) | Gate::LessThan(a, b) => vec![*a, *b],
+      Gate::Bool(wire) => vec![*wire]
+    }
+  }
+
+  /// Returns the wire the gate writes its result to, or None for gates that only assert a relation.
+  pub fn output(&self) -> Option<usize> {
+    match self {
+      Gate::Add(_, _, output) | Gate::Sub(_, _, output) | Gate::Mul(_, _, output) | Gate::Hash(_, _, output) | Gate::Commit(_, _, output) => Some(*output),
+      Gate::Const(wire, _) => Some(*wire),
+      Gate::IsZero(_, output, _) | Gate::Mux(_, _, output) | Gate::Poly(_, _, output) | Gate::Linear(_, output) => Some(*output),
+      Gate::LessThanOrEqual(..) | Gate::Assert(..) | Gate::AssertEqual(..) | Gate::LessThan(..) | Gate::Bool(..) => None
+    }
+  }
+
+  /// Returns the wires the gate assigns when computing the witness: its output, plus the inverse wire of an IsZero gate.
+  fn written_wires(&self) -> Vec<usize> {
+    match self {
+      Gate::IsZero(_, output, inv) => vec![*output, *inv],
+      other => other.output().into_iter().collect()
+    }
+  }
+
+  /// Returns the gate with the operands of commutative gates (and Linear terms) in ascending order, so equivalent gates compare equal.
+  pub fn canonical(&self) -> Gate {
+    match self {
+      Gate::Add(a, b, output) => Gate::Add(*a.min(b), *a.max(b), *output),
+      Gate::Mul(a, b, output) => Gate::Mul(*a.min(b), *a.max(b), *output),
+      Gate::AssertEqual(a, b) => Gate::AssertEqual(*a.min(b), *a.max(b)),
+      Gate::Linear(terms, output) => {
+        let mut terms = terms.clone();
+        terms.sort();
+        Gate::Linear(terms, *output)
+      }
+      other => other.clone()
+    }
+  }
+
+  /// Returns the name of the gate's variant, e.g. "Add" or "LessThanOrEqual".
+  pub fn kind(&self) -> &'static str {
+    match self {
+      Gate::Add(..) => "Add",
+      Gate::Mul(..) => "Mul",
+      Gate::Hash(..) => "Hash",
+      Gate::Commit(..) => "Commit",
+      Gate::Const(..) => "Const",
+      Gate::IsZero(..) => "IsZero",
+      Gate::Mux(..) => "Mux",
+      Gate::Poly(..) => "Poly",
+      Gate::Sub(..) => "Sub",
+      Gate::LessThanOrEqual(..) => "LessThanOrEqual",
+      Gate::Assert(..) => "Assert",
+      Gate::Linear(..) => "Linear",
+      Gate::AssertEqual(..) => "AssertEqual",
+      Gate::LessThan(..) => "LessThan",
+      Gate::Bool(..) => "Bool"
+    }
+  }
+
+  /// Rewrites every wire index of the gate through `map`.
+  fn remap_wires<F: Fn(usize) -> usize>(&mut self, map: F) {
+    match self {
+      Gate::Add(a, b, output) | Gate::Sub(a, b, output) | Gate::Mul(a, b, output) | Gate::Hash(a, b, output) | Gate::Commit(a, b, output) => {
+        *a = map(*a);
+        *b = map(*b);
+        *output = map(*output);
+      }
+      Gate::Const(wire, _) => *wire = map(*wire),
+      Gate::IsZero(x, output, inv) => {
+        *x = map(*x);
+        *output = map(*output);
+        *inv = map(*inv);
+      }
+      Gate::Mux(index, options, output) => {
+        *index = map(*index);
+        for option in options.iter_mut() {
+          *option = map(*option);
+        }
+        *output = map(*output);
+      }
+      Gate::Poly(inputs, _, output) => {
+        for input in inputs.iter_mut() {
+          *input = map(*input);
+        }
+        *output = map(*output);
+      }
+      Gate::LessThanOrEqual(a, b) | Gate::AssertEqual(a, b) | Gate::LessThan(a, b) => {
+        *a = map(*a);
+        *b = map(*b);
+      }
+      Gate::Assert(wire) | Gate::Bool(wire) => *wire = map(*wire),
+      Gate::Linear(terms, output) => {
+        for (input, _) in terms.iter_mut() {
+          *input = map(*input);
+        }
+        *output = map(*output);
+      }
+    }
+  }
+}
+
+/// Summary of the changes made by `Circuit::optimize`, counted in R1CS constraints (see `Circuit::to_r1cs`).
+#[derive(Debug, Default, PartialEq)]
+pub struct OptimizationReport {
+  pub constraints_removed: usize,
+  pub constraints_added: usize,
+  pub inputs_pruned: usize
+}
+
+/// One output wire checked by `Circuit::dry_run`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputCheck {
+  pub wire: usize,
+  pub computed: BigInt, // value the circuit computes for the wire from its inputs
+  pub expected: Option<BigInt> // value declared with `add_output`, if one was declared for this wire
+}
+
+impl OutputCheck {
+  /// Returns true unless a declared value disagrees with the computed one.
+  pub fn matches(&self) -> bool {
+    self.expected.as_ref().is_none_or(|expected| *expected == self.computed)
+  }
+}
+
+/// Result of `Circuit::dry_run`: computed versus declared values of the circuit's output wires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunReport {
+  pub outputs: Vec<OutputCheck>,
+  pub unmatched: Vec<BigInt> // declared outputs beyond the circuit's output wires
+}
+
+impl DryRunReport {
+  /// Returns the output wires whose declared value differs from the computed one.
+  pub fn mismatches(&self) -> Vec<&OutputCheck> {
+    self.outputs.iter().filter(|check| !check.matches()).collect()
+  }
+
+  /// Returns true if every declared output has a wire and matches it.
+  pub fn is_consistent(&self) -> bool {
+    self.unmatched.is_empty() && self.outputs.iter().all(OutputCheck::matches)
+  }
+}
+
+impl fmt::Display for DryRunReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for check in &self.outputs {
+      match &check.expected {
+        Some(expected) if !check.matches() => writeln!(f, "wire {}: computed {}, declared {} -> MISMATCH", check.wire, check.computed, expected)?,
+        Some(expected) => writeln!(f, "wire {}: computed {}, declared {} -> ok", check.wire, check.computed, expected)?,
+        None => writeln!(f, "wire {}: computed {} (no declared output)", check.wire, check.computed)?
+      }
+    }
+    for value in &self.unmatched {
+      writeln!(f, "declared output {} has no output wire -> MISMATCH", value)?;
+    }
+    Ok(())
+  }
+}
+
+/// Structural metrics of a circuit, see `Circuit::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitStats {
+  pub gate_counts: BTreeMap<&'static str, usize>, // number of gates of each kind, keyed by `Gate::kind`
+  pub inputs: usize,
+  pub public_inputs: usize,
+  pub outputs: usize, // declared output values
+  pub depth: usize // gates in the longest chain where each gate reads a wire the previous one writes
+}
+
+/**
+ * JSON layout accepted by `Circuit::from_json` and written by `Circuit::to_json`.
+ *
+ * {
+ *   "hash_function": "simple_add",      // optional, see `hash_functions::by_name`
+ *   "inputs": [5, 4, "20"],             // numbers, or decimal strings for values beyond i64
+ *   "public_inputs": [2],               // optional, indices into inputs
+ *   "gates": [{ "Mul": [0, 1, 2] }],    // one entry per gate, tagged by the gate name
+ *   "outputs": [20]                     // optional
+ * }
+ */
+#[derive(Serialize, Deserialize)]
+struct CircuitSpec {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  hash_function: Option<String>,
+  inputs: Vec<Value>,
+  #[serde(default)]
+  public_inputs: Vec<usize>,
+  gates: Vec<Gate>,
+  #[serde(default)]
+  outputs: Vec<Value>
+}
+
+/// Parses a spec value given either as a JSON integer or a decimal string.
+fn parse_spec_value(value: &Value) -> Result<BigInt, ZkMockError> {
+  match value {
+    Value::Number(number) => number.as_i64().map(BigInt::from)
+      .ok_or_else(|| ZkMockError::InvalidSpec(format!("{} is not an integer", number))),
+    Value::String(text) => text.parse::<BigInt>()
+      .map_err(|_| ZkMockError::InvalidSpec(format!("'{}' is not a decimal integer", text))),
+    other => Err(ZkMockError::InvalidSpec(format!("expected an integer, found {}", other)))
+  }
+}
+
+/// Writes a spec value as a JSON integer, or as a decimal string if it does not fit in an i64.
+fn spec_value(value: &BigInt) -> Value {
+  match value.to_i64() {
+    Some(number) => Value::from(number),
+    None => Value::String(value.to_string())
+  }
+}
+
+/// Upper bounds on a circuit's size, enforced as inputs and gates are added (see `Circuit::set_budget`).
+/// Guards services that build circuits from untrusted specs against resource exhaustion.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBudget {
+  pub max_inputs: usize, // wires: the inputs together with the intermediate wires gates write
+  pub max_gates: usize
+}
+
+/// One recorded call on the circuit-building API, see `Circuit::enable_replay_log`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CircuitCall {
+  AddInput(BigInt),
+  AddPublicInput(BigInt),
+  SetPublicInputCommitment(bool),
+  AddGate(Gate),
+  AddOutput(BigInt),
+  AddNamedOutput(String, usize),
+  SetModulus(Option<BigInt>),
+  SetInputs(Vec<BigInt>),
+  SetStrict(bool),
+  SetIncrementalCheck(bool),
+  SetBudget(Option<CircuitBudget>),
+  Optimize,
+  ComputeWitness,
+  SetComparisonBits(usize),
+  AddNamedInput(String, BigInt)
+}
+
+/// Returns the public variable holding `value`, appending it to the R1CS the first time the constant is used.
+fn constant_variable(r1cs: &mut R1CS, constants: &mut HashMap<BigInt, usize>, value: &BigInt) -> usize {
+  *constants.entry(value.clone()).or_insert_with(|| r1cs.append_public_input(value.clone()))
+}
+
+/**
+ * Appends `bits` boolean witness variables holding the binary digits of `value` (each enforced by bit * bit = bit)
+ * and returns the terms Σ 2^i * bit_i that rebuild it, for the caller to constrain against the value's wires.
+ *
+ * `value` must lie in `[0, 2^bits)`; the caller reduces it there, so a value out of range yields a witness
+ * that does not rebuild it and the caller's constraint fails.
+ */
+fn decompose_bits(r1cs: &mut R1CS, value: &BigInt, bits: usize) -> Vec<(usize, BigInt)> {
+  (0..bits).map(|position| {
+    let bit = r1cs.append_variable(Some((value >> position) & BigInt::from(1)));
+    r1cs.add_constraints(
+      vec![(bit, BigInt::from(1))],
+      vec![(bit, BigInt::from(1))],
+      vec![(bit, BigInt::from(1))],
+      Operation::Mul
+    );
+    (bit, BigInt::from(1) << position)
+  }).collect()
+}
+
+/// Reads one input value per non-empty line (decimal, surrounding whitespace ignored), e.g. from piped stdin.
+pub fn read_input_values<R: BufRead>(reader: R) -> Result<Vec<BigInt>, ZkMockError> {
+  let mut values = Vec::new();
+  for line in reader.lines() {
+    let line = line?;
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+    values.push(line.parse::<BigInt>().map_err(|_| ZkMockError::InvalidSpec(format!("'{}' is not a decimal integer", line)))?);
+  }
+  Ok(values)
+}
+
+/**
+ * The whole verify pipeline in one call: parses the circuit spec (see `Circuit::from_json`), rebuilds its R1CS,
+ * loads the proof at `proof_path`, checks the proof's circuit hash against the parsed circuit and re-runs satisfaction.
+ *
+ * Returns Ok(false) for a proof of a different circuit or an unsatisfied one, and Err if the spec or proof cannot be read.
+ */
+pub fn verify_circuit_proof(circuit_json: &str, proof_path: &str) -> Result<bool, ZkMockError> {
+  let circuit = Circuit::from_json(circuit_json)?;
+  Ok(circuit.verify_proof_detailed(proof_path)?.valid)
+}
+
+/// A circuit is `Send + Sync` (its hash function must be too), so one instance can be shared read-only,
+/// e.g. as an `Arc<Circuit>`, by threads verifying proofs against it concurrently.
 pub struct Circuit{
   hash_function: Option<Box<dyn HashFunction>>,
   inputs: Vec<BigInt>,
+  public_inputs: Vec<usize>, // indices of the inputs known to the verifier
+  commit_public_inputs: bool, // bind a Merkle root of the public inputs into the proof
   gates: Vec<Gate>,
-  outputs: Vec<BigInt>
+  outputs: Vec<BigInt>,
+  named_outputs: Vec<(String, usize)>, // (name, wire) pairs exposed in the proof
+  input_names: Vec<(String, usize)>, // (name, wire) pairs of the inputs added by name
+  modulus: Option<BigInt>, // prime field the constraints are checked in, plain integers when None
+  strict: bool, // refuse to prove likely-mistaken circuits instead of warning
+  incremental_check: bool, // check each gate against the current inputs as it is added
+  budget: Option<CircuitBudget>, // size limits enforced by try_add_input / try_add_gate
+  comparison_bits: usize, // bit width of the values comparison gates work on
+  replay_log: Option<Vec<CircuitCall>> // every building call in order, when recording is enabled
 }
 
+// Compile-time guard for the thread-safety promised above
+const _: fn() = || {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Circuit>();
+};
+
 impl Circuit {
   /// Creates a new Circuit with an optional hash function.
   /// Initializes empty inputs, gates, and outputs.
@@ -27,16 +380,375 @@ impl Circuit {
     Circuit{
       hash_function,
       inputs: Vec::new(),
+      public_inputs: Vec::new(),
+      commit_public_inputs: false,
       gates: Vec::new(),
-      outputs: Vec::new()
+      outputs: Vec::new(),
+      named_outputs: Vec::new(),
+      input_names: Vec::new(),
+      modulus: None,
+      strict: false,
+      incremental_check: false,
+      budget: None,
+      comparison_bits: DEFAULT_COMPARISON_BITS,
+      replay_log: None
+    }
+  }
+
+  /// Starts recording every circuit-building call so the construction can be replayed later.
+  /// Calls made before enabling are not recorded.
+  pub fn enable_replay_log(&mut self) {
+    if self.replay_log.is_none() {
+      self.replay_log = Some(Vec::new());
+    }
+  }
+
+  /// Returns the recorded calls as JSON, or None if recording was never enabled.
+  pub fn replay_log(&self) -> Option<String> {
+    self.replay_log.as_ref().map(|log| serde_json::to_string(log).expect("Unable to serialize replay log"))
+  }
+
+  /// Writes the recorded calls to `log_file` as JSON. Does nothing if recording was never enabled.
+  pub fn save_replay_log(&self, log_file: &str) {
+    if let Some(log) = self.replay_log() {
+      std::fs::write(log_file, log).expect("Unable to write replay log");
+    }
+  }
+
+  /**
+   * Rebuilds a circuit by re-running the calls of a replay log, in order.
+   *
+   * The hash function is not part of the log (it is a trait object), reattach it with
+   * `set_hash_function`. The rebuilt circuit keeps recording, so its log matches the original.
+   */
+  pub fn replay(log: &str) -> Result<Circuit, ZkMockError> {
+    let calls: Vec<CircuitCall> = serde_json::from_str(log).map_err(|e| ZkMockError::InvalidReplayLog(e.to_string()))?;
+
+    let mut circuit = Circuit::new(None);
+    circuit.enable_replay_log();
+    for call in calls {
+      match call {
+        CircuitCall::AddInput(input) => { circuit.try_add_input(input)?; }
+        CircuitCall::AddPublicInput(input) => { circuit.try_add_public_input(input)?; }
+        CircuitCall::SetPublicInputCommitment(enabled) => circuit.set_public_input_commitment(enabled),
+        CircuitCall::AddGate(gate) => circuit.try_add_gate(gate)?,
+        CircuitCall::AddOutput(output) => circuit.add_output(output),
+        CircuitCall::AddNamedOutput(name, wire) => circuit.add_named_output(&name, wire),
+        CircuitCall::SetModulus(modulus) => circuit.set_modulus(modulus),
+        CircuitCall::SetInputs(values) => circuit.set_inputs_from(&values)?,
+        CircuitCall::SetStrict(strict) => circuit.set_strict(strict),
+        CircuitCall::SetIncrementalCheck(enabled) => circuit.set_incremental_check(enabled),
+        CircuitCall::SetBudget(budget) => circuit.set_budget(budget),
+        CircuitCall::Optimize => { circuit.optimize(); }
+        CircuitCall::ComputeWitness => circuit.compute_witness(),
+        CircuitCall::SetComparisonBits(bits) => circuit.set_comparison_bits(bits),
+        CircuitCall::AddNamedInput(name, input) => { circuit.try_add_named_input(&name, input)?; }
+      }
+    }
+    Ok(circuit)
+  }
+
+  /// Sets the prime modulus the constraints are checked in, or None to check them over the integers.
+  pub fn set_modulus(&mut self, modulus: Option<BigInt>) {
+    self.record(CircuitCall::SetModulus(modulus.clone()));
+    self.modulus = modulus;
+  }
+
+  /// Enables or disables strict mode: when on, proving a circuit without gates is refused rather than warned about (see `Proof::warnings`),
+  /// and so is proving a circuit whose witness does not satisfy its constraints, instead of emitting an invalid proof.
+  pub fn set_strict(&mut self, strict: bool) {
+    self.record(CircuitCall::SetStrict(strict));
+    self.strict = strict;
+  }
+
+  /// Enables or disables incremental checking: when on, every gate is checked against the current inputs as it is added,
+  /// so a wrong output wire is reported by `try_add_gate` (or panics in `add_gate`) instead of surfacing at proof time.
+  pub fn set_incremental_check(&mut self, enabled: bool) {
+    self.record(CircuitCall::SetIncrementalCheck(enabled));
+    self.incremental_check = enabled;
+  }
+
+  /// Sets the size limits `try_add_input`, `try_add_public_input` and `try_add_gate` enforce, or None for no limits.
+  /// Inputs and gates already added are kept even if they exceed the new budget.
+  pub fn set_budget(&mut self, budget: Option<CircuitBudget>) {
+    self.record(CircuitCall::SetBudget(budget));
+    self.budget = budget;
+  }
+
+  /**
+   * Sets the bit width comparison gates work on (`DEFAULT_COMPARISON_BITS` by default).
+   *
+   * A LessThan gate only holds when both inputs lie in `[0, 2^bits)`; a LessThanOrEqual gate decomposes
+   * B - A into `bits` bits. Each comparison costs a few constraints per bit, so narrow widths are cheaper;
+   * with a modulus set it must stay well above 2^bits.
+   */
+  pub fn set_comparison_bits(&mut self, bits: usize) {
+    self.record(CircuitCall::SetComparisonBits(bits));
+    self.comparison_bits = bits;
+  }
+
+  /// Returns true if `a < b` with both in `[0, 2^comparison_bits)`, what a LessThan gate asserts.
+  fn less_than_holds(&self, a: &BigInt, b: &BigInt) -> bool {
+    let range = BigInt::from(1) << self.comparison_bits;
+    let in_range = |value: &BigInt| value.sign() != num_bigint::Sign::Minus && value < &range;
+    let (a, b) = (self.reduce(a), self.reduce(b));
+    in_range(&a) && in_range(&b) && a < b
+  }
+
+  /// Checks that a new input can be added: its wire, the one after the existing inputs, must not already be an
+  /// intermediate wire a gate writes (`InputAfterIntermediate`), and the budget must allow one more wire (`BudgetExceeded`).
+  fn check_new_input(&self) -> Result<(), ZkMockError> {
+    let next = self.next_unallocated_wire();
+    if next > self.inputs.len() {
+      return Err(ZkMockError::InputAfterIntermediate { wire: self.inputs.len() });
+    }
+    match self.budget {
+      Some(budget) if next >= budget.max_inputs => Err(ZkMockError::BudgetExceeded { resource: "inputs", limit: budget.max_inputs }),
+      _ => Ok(())
+    }
+  }
+
+  /// Replaces the circuit's hash function.
+  pub fn set_hash_function(&mut self, hash_function: Option<Box<dyn HashFunction>>) {
+    self.hash_function = hash_function;
+  }
+
+  /// Appends `call` to the replay log if recording is enabled.
+  fn record(&mut self, call: CircuitCall) {
+    if let Some(log) = self.replay_log.as_mut() {
+      log.push(call);
+    }
+  }
+
+  /// Parses a circuit from its JSON spec (see `CircuitSpec` for the layout).
+  pub fn from_json(spec: &str) -> Result<Circuit, ZkMockError> {
+    Circuit::build_from_json(spec, None)
+  }
+
+  /**
+   * Writes the circuit's inputs, public inputs, gates and outputs as a JSON spec that `from_json` reads back.
+   *
+   * The hash function is a trait object the circuit cannot name, so it is left out of the spec:
+   * restore it with `set_hash_function` after loading, or add a `"hash_function"` entry by hand.
+   * Settings such as the modulus, strict mode and named outputs are not part of the spec either.
+   */
+  pub fn to_json(&self) -> String {
+    let spec = CircuitSpec {
+      hash_function: None,
+      inputs: self.inputs.iter().map(spec_value).collect(),
+      public_inputs: self.public_inputs.clone(),
+      gates: self.gates.clone(),
+      outputs: self.outputs.iter().map(spec_value).collect()
+    };
+    serde_json::to_string_pretty(&spec).expect("Unable to serialize circuit spec")
+  }
+
+  /// Parses a circuit from an untrusted JSON spec, failing with `BudgetExceeded` as soon as it outgrows `budget`.
+  /// The returned circuit keeps enforcing the budget.
+  pub fn from_json_with_budget(spec: &str, budget: CircuitBudget) -> Result<Circuit, ZkMockError> {
+    Circuit::build_from_json(spec, Some(budget))
+  }
+
+  /// Shared body of `from_json` and `from_json_with_budget`.
+  fn build_from_json(spec: &str, budget: Option<CircuitBudget>) -> Result<Circuit, ZkMockError> {
+    let spec: CircuitSpec = serde_json::from_str(spec).map_err(|e| ZkMockError::InvalidSpec(e.to_string()))?;
+
+    let hash_function = match spec.hash_function {
+      Some(name) => Some(hash_functions::by_name(&name).ok_or(ZkMockError::UnknownHashFunction(name))?),
+      None => None
+    };
+
+    let mut circuit = Circuit::new(hash_function);
+    circuit.budget = budget;
+    for input in &spec.inputs {
+      circuit.try_add_input(parse_spec_value(input)?)?;
+    }
+    for index in spec.public_inputs {
+      if index >= circuit.inputs.len() {
+        return Err(ZkMockError::InvalidSpec(format!("public input {} does not exist", index)));
+      }
+      if circuit.public_inputs.contains(&index) {
+        return Err(ZkMockError::InvalidSpec(format!("public input {} is listed twice", index)));
+      }
+      circuit.public_inputs.push(index);
+    }
+    for gate in spec.gates {
+      circuit.try_add_gate(gate)?;
     }
+    for output in &spec.outputs {
+      circuit.add_output(parse_spec_value(output)?);
+    }
+    Ok(circuit)
   }
 
   /// Adds an input value to the circuit and returns its index.
+  /// Panics if the circuit's budget allows no more inputs, see `try_add_input`.
   pub fn add_input(&mut self, input: BigInt) -> usize {
+    self.try_add_input(input).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `add_input`: fails with `ZkMockError::BudgetExceeded` instead of exceeding the budget,
+  /// and with `ZkMockError::InputAfterIntermediate` once a gate writes an intermediate wire (add inputs before such gates).
+  pub fn try_add_input(&mut self, input: BigInt) -> Result<usize, ZkMockError> {
+    self.check_new_input()?;
+    self.record(CircuitCall::AddInput(input.clone()));
+    let index = self.inputs.len();
+    self.inputs.push(input);
+    Ok(index)
+  }
+
+  /// Adds an input under `name`, so it can be looked up (`input_index`, `get_input_by_name`) and wired
+  /// into gates (`add_gate_by_names`) by name, and returns its index.
+  /// Panics if the name is taken or the budget allows no more inputs, see `try_add_named_input`.
+  pub fn add_named_input(&mut self, name: &str, input: BigInt) -> usize {
+    self.try_add_named_input(name, input).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `add_named_input`.
+  ///
+  /// # Errors
+  /// - `ZkMockError::DuplicateInputName` if an input was already added under `name`;
+  /// - `ZkMockError::BudgetExceeded` if the budget allows no more inputs;
+  /// - `ZkMockError::InputAfterIntermediate` once a gate writes an intermediate wire.
+  pub fn try_add_named_input(&mut self, name: &str, input: BigInt) -> Result<usize, ZkMockError> {
+    if self.input_index(name).is_some() {
+      return Err(ZkMockError::DuplicateInputName(name.to_string()));
+    }
+    self.check_new_input()?;
+    self.record(CircuitCall::AddNamedInput(name.to_string(), input.clone()));
+    let index = self.inputs.len();
+    self.inputs.push(input);
+    self.input_names.push((name.to_string(), index));
+    Ok(index)
+  }
+
+  /// Returns the index of the input added under `name`, if any.
+  pub fn input_index(&self, name: &str) -> Option<usize> {
+    self.input_names.iter().find(|(input_name, _)| input_name == name).map(|(_, wire)| *wire)
+  }
+
+  /// Retrieves a reference to the value of the input added under `name`, if it exists.
+  pub fn get_input_by_name(&self, name: &str) -> Option<&BigInt> {
+    self.input_index(name).and_then(|index| self.get_input(index))
+  }
+
+  /// Adds a private input, part of the witness only the prover knows, and returns its index.
+  /// The same as `add_input`, spelled out for circuits mixing public and private inputs.
+  pub fn add_private_input(&mut self, input: BigInt) -> usize {
+    self.add_input(input)
+  }
+
+  /// Adds an input the verifier is allowed to see and returns its index.
+  /// Panics if the circuit's budget allows no more inputs, see `try_add_public_input`.
+  pub fn add_public_input(&mut self, input: BigInt) -> usize {
+    self.try_add_public_input(input).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `add_public_input`: fails like `try_add_input`.
+  pub fn try_add_public_input(&mut self, input: BigInt) -> Result<usize, ZkMockError> {
+    self.check_new_input()?;
+    self.record(CircuitCall::AddPublicInput(input.clone()));
     let index = self.inputs.len();
     self.inputs.push(input);
-    index
+    self.public_inputs.push(index);
+    Ok(index)
+  }
+
+  /// Returns the indices of the public inputs, in the order they were added.
+  pub fn public_inputs(&self) -> &[usize] {
+    &self.public_inputs
+  }
+
+  /// Returns the values of the public inputs, in the order they were added: the public input vector
+  /// generated proofs carry and the verifier checks against its own.
+  pub fn public_input_values(&self) -> Vec<BigInt> {
+    self.public_inputs.iter().map(|&index| self.inputs[index].clone()).collect()
+  }
+
+  /// Enables or disables binding a Merkle root of the public inputs into generated proofs instead of the public
+  /// input vector: proofs then carry only the root, see `verify_proof_with_public_inputs`.
+  pub fn set_public_input_commitment(&mut self, enabled: bool) {
+    self.record(CircuitCall::SetPublicInputCommitment(enabled));
+    self.commit_public_inputs = enabled;
+  }
+
+  /// Builds a Merkle tree over the public input values (in `public_inputs` order) using the circuit's hash function.
+  /// Returns None when the circuit has no public inputs, panics if no hash function is defined.
+  fn public_inputs_tree(&self) -> Option<MerkleTree<&dyn HashFunction>> {
+    if self.public_inputs.is_empty() {
+      return None;
+    }
+    let hash_function = self.hash_function.as_deref().expect("Hash function not defined for this circuit");
+    Some(MerkleTree::new(self.public_input_values(), hash_function))
+  }
+
+  /// Returns the Merkle root committing to the public input values, if there are any.
+  pub fn public_inputs_root(&self) -> Option<BigInt> {
+    self.public_inputs_tree().map(|tree| tree.root)
+  }
+
+  /// Returns the authentication path for the `position`-th public input, to hand to the verifier alongside its value.
+  pub fn public_input_path(&self, position: usize) -> Vec<(BigInt, bool)> {
+    self.public_inputs_tree().map(|tree| tree.merkle_path(position)).unwrap_or_default()
+  }
+
+  /// Builds a Merkle tree over the wire values `wires` (in wire index order) using the circuit's hash function.
+  /// Returns None when there are no wires or the circuit has no hash function.
+  fn witness_tree(&self, wires: &[BigInt]) -> Option<MerkleTree<&dyn HashFunction>> {
+    if wires.is_empty() {
+      return None;
+    }
+    let hash_function = self.hash_function.as_deref()?;
+    Some(MerkleTree::new(wires.to_vec(), hash_function))
+  }
+
+  /**
+   * Returns the Merkle root committing to the complete witness, every wire value (intermediate wires included)
+   * in wire index order.
+   *
+   * Generated proofs carry this root, so a verifier handed some wire values with their paths
+   * (see `witness_path`) can confirm they are part of the witness the proof was made for.
+   * Panics if the circuit has no wires or no hash function is defined.
+   */
+  pub fn witness_commitment(&self) -> BigInt {
+    assert!(self.hash_function.is_some(), "Hash function not defined for this circuit");
+    self.witness_tree(&self.with_intermediates(&self.inputs)).map(|tree| tree.root).expect("Cannot commit to the witness of a circuit without wires")
+  }
+
+  /// Returns the authentication path for the value of `wire` in the witness commitment.
+  pub fn witness_path(&self, wire: usize) -> Vec<(BigInt, bool)> {
+    self.witness_tree(&self.with_intermediates(&self.inputs)).map(|tree| tree.merkle_path(wire)).unwrap_or_default()
+  }
+
+  /**
+   * Builds the canonical "I know the preimage" circuit: a single Hash gate enforcing hash(a, b) == hash_output.
+   *
+   * `a` and `b` are private inputs (the preimage), `hash_output` is the only public input.
+   * Proving succeeds only when `a` and `b` really hash to `hash_output` under `hash_function`.
+   */
+  pub fn preimage_proof(hash_function: Box<dyn HashFunction>, hash_output: BigInt, a: BigInt, b: BigInt) -> Self {
+    let mut circuit = Circuit::new(Some(hash_function));
+    let a = circuit.add_input(a);
+    let b = circuit.add_input(b);
+    let output = circuit.add_public_input(hash_output.clone());
+    circuit.add_gate(Gate::Hash(a, b, output));
+    circuit.add_output(hash_output);
+    circuit
+  }
+
+  /**
+   * Replaces the values of all declared inputs at once, keeping the circuit structure.
+   *
+   * Lets a circuit loaded from JSON be proven over a witness supplied separately (see `read_input_values`).
+   * Fails without changing anything if `values` does not have exactly one value per declared input.
+   */
+  pub fn set_inputs_from(&mut self, values: &[BigInt]) -> Result<(), ZkMockError> {
+    if values.len() != self.inputs.len() {
+      return Err(ZkMockError::InputCountMismatch { expected: self.inputs.len(), found: values.len() });
+    }
+    self.record(CircuitCall::SetInputs(values.to_vec()));
+    self.inputs = values.to_vec();
+    Ok(())
   }
 
   /// Retrieves a reference to the input value at the given index, if it exists.
@@ -46,105 +758,3027 @@ impl Circuit {
   
 
   /// Adds a gate (operation) to the circuit.
+  /// Panics if the budget allows no more gates or incremental checking rejects the gate, see `try_add_gate`.
   pub fn add_gate(&mut self, gate: Gate) {
-    self.gates.push(gate);
+    self.try_add_gate(gate).unwrap_or_else(|error| panic!("{}", error));
   }
 
-  /// Adds an output value to the circuit.
-  pub fn add_output(&mut self, output: BigInt) {
-    self.outputs.push(output);
+  /// Adds the gate `gate` builds from the indices of the named inputs `names`, in the same order, e.g.
+  /// `circuit.add_gate_by_names(&["a", "b", "sum"], |wires| Gate::Add(wires[0], wires[1], wires[2]))`.
+  /// Panics if a name is unknown or the gate is rejected, see `try_add_gate_by_names`.
+  pub fn add_gate_by_names<F: FnOnce(&[usize]) -> Gate>(&mut self, names: &[&str], gate: F) {
+    self.try_add_gate_by_names(names, gate).unwrap_or_else(|error| panic!("{}", error));
   }
 
-  /// Applies the circuit's hash function to two BigInt values.
-  /// Panics if no hash function is defined.
-  pub fn apply_hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
-    if let Some(ref hash_function) = self.hash_function {
-      hash_function.hash(a, b)
-    } else {
-      // the error handling
-      panic!("Hash function not defined for this circuit");
+  /// Fallible counterpart of `add_gate_by_names`: fails with `ZkMockError::UnknownInputName` for the first
+  /// name no input was added under, or with the `try_add_gate` errors.
+  pub fn try_add_gate_by_names<F: FnOnce(&[usize]) -> Gate>(&mut self, names: &[&str], gate: F) -> Result<(), ZkMockError> {
+    let wires = names.iter()
+      .map(|name| self.input_index(name).ok_or_else(|| ZkMockError::UnknownInputName(name.to_string())))
+      .collect::<Result<Vec<_>, _>>()?;
+    self.try_add_gate(gate(&wires))
+  }
+
+  /**
+   * Adds a gate writing to a freshly allocated wire and returns that wire's index.
+   *
+   * `gate` builds the gate from the new wire's index, e.g. `circuit.add_gate_with_output(|out| Gate::Add(a, b, out))`.
+   * The new wire is the next unallocated one, past every input and intermediate wire, so the prover never supplies
+   * it by hand: while no gate writes an intermediate wire it is added as an input holding the value the gate computes,
+   * afterwards it is an intermediate wire computed when proving.
+   * Panics like `add_gate`, or if the gate has no output wire or its output is not the new wire.
+   */
+  pub fn add_gate_with_output<F: FnOnce(usize) -> Gate>(&mut self, gate: F) -> usize {
+    let output = self.next_unallocated_wire();
+    let gate = gate(output);
+    assert!(gate.output() == Some(output), "add_gate_with_output needs a gate writing to the new wire {}", output);
+
+    if output == self.inputs.len() {
+      let mut wires = self.inputs.clone();
+      wires.push(BigInt::from(0));
+      let value = self.compute_gate(&gate, &wires);
+      self.add_input(value);
     }
+    self.add_gate(gate);
+    output
   }
 
   /**
-   * Converts the circuit's gates and inputs into R1CS constraints and prepares for proof-generation.
+   * Adds a gate to the circuit, checking the budget and, when incremental checking is enabled, the gate itself.
    *
-   * Steps:
-   * 1. Initializes a new R1CS instance.
-   * 2. Converts all circuit inputs into R1CS variables with unique-indices.
-   * 3. Iterates over each gate in the circuit and translates it into an R1CS constraint:
-   *    - Add gate: Enforces input[a] + input[b] = input[output].
-   *    - Mul gate: Enforces input[a] * input[b] = input[output].
-   *    - Hash gate: Computes hash(input[a], input[b]) and enforces it equals output.
-   * 4. Each constraint is added to the R1CS system for later proof generation or verification.
+   * The check computes the gate over the current input values and compares the result with its output wire
+   * (assertion gates such as LessThanOrEqual must hold), trading some building time for immediate feedback.
+   * A rejected gate is not added.
    *
-   * Arguments:
-   * - proof_file: Path to the file where the proof (or R1CS data) will be stored (not implemented in this snippet).
-   * 
-   * order of the gates in self.gates is synonymous to the opcodes system in EVM and thus it mimics how the entire program was written in DSL(let's say circom) during the compilation-phase
-   * 
-   * @todo >1-degree polyomials handling doesn't function yet for this method as it doesn't handle the intermediate variables for the operations as of yet
+   * # Errors
+   * - `ZkMockError::BudgetExceeded` if the circuit already has as many gates as its budget allows, or if the gate
+   *   writes a new intermediate wire past the budget's `max_inputs`, which bounds inputs and intermediate wires together;
+   * - `ZkMockError::UnsatisfiedGate` if the output wire does not hold the computed value;
+   * - `ZkMockError::AssertionFailed` if an assertion gate (LessThanOrEqual, Assert, AssertEqual, LessThan, Bool) does not hold;
+   * - `ZkMockError::MissingHashFunction` for a Hash or Commit gate in a circuit without a hash function;
+   * - `ZkMockError::UndefinedWire` if the gate reads an intermediate wire no earlier gate writes, and
+   *   `ZkMockError::UnallocatedWire` if it writes past the next unallocated wire, both checked whether
+   *   or not incremental checking is enabled (add the inputs first).
    */
-  pub fn generate_proof(&self, proof_file: &str) {
-    let mut r1cs = R1CS::new();
-    r1cs.variables = self.inputs.iter().enumerate().map(|(i, input)| Variable { index: i, value: input.clone() }).collect();
-
-    // Creating the R1CS constraints based on the gates defined in the circuit
-    for gate in &self.gates {
-      match gate {
-        // For an Add gate, create a constraint enforcing input[a] + input[b] = input[output]
-        Gate::Add(a, b, output) => {
-          r1cs.add_constraints(
-            vec![(Variable { index: *a, value: self.inputs[*a].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *b, value: self.inputs[*b].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *output, value: self.inputs[*output].clone() }, BigInt::from(1))],
-            Operation::Add
-          );
-        }
-        // For a Mul gate, create a constraint enforcing input[a] * input[b] = input[output]
-        Gate::Mul(a, b, output) => {
-          r1cs.add_constraints(
-            vec![(Variable { index: *a, value: self.inputs[*a].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *b, value: self.inputs[*b].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *output, value: self.inputs[*output].clone() }, BigInt::from(1))],
-            Operation::Mul
-          );
+  pub fn try_add_gate(&mut self, gate: Gate) -> Result<(), ZkMockError> {
+    if let Some(budget) = self.budget
+      && self.gates.len() >= budget.max_gates {
+      return Err(ZkMockError::BudgetExceeded { resource: "gates", limit: budget.max_gates });
+    }
+    let allocated = self.next_unallocated_wire();
+    let next = self.check_gate_wiring(self.gates.len(), &gate, allocated)?;
+    if let Some(budget) = self.budget
+      && next > allocated && next > budget.max_inputs {
+      return Err(ZkMockError::BudgetExceeded { resource: "inputs", limit: budget.max_inputs });
+    }
+    if self.incremental_check {
+      let position = self.gates.len();
+      if matches!(gate, Gate::Hash(..) | Gate::Commit(..)) && self.hash_function.is_none() {
+        return Err(ZkMockError::MissingHashFunction { gate: position });
+      }
+      let wires = self.with_intermediates(&self.inputs);
+      let computed = self.compute_gate(&gate, &wires);
+      match gate.output() {
+        // A gate writing a fresh intermediate wire defines its value rather than checking it
+        Some(output) if output >= wires.len() => {}
+        Some(output) => {
+          let found = self.reduce(&wires[output]);
+          if computed != found {
+            return Err(ZkMockError::UnsatisfiedGate { gate: position, expected: computed, found });
+          }
         }
-        // For a Hash gate, compute hash(input[a], input[b]) and enforce it equals output
-        Gate::Hash(a, b, output) => {
-          let hash_result = self.apply_hash(&self.inputs[*a], &self.inputs[*b]);
-          r1cs.add_constraints(
-            vec![(Variable { index: *a, value: self.inputs[*a].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *b, value: self.inputs[*b].clone() }, BigInt::from(1))],
-            vec![(Variable { index: *output, value: hash_result.clone() }, BigInt::from(1))],
-            Operation::Hash
-          );
-
-          println!("Hash-gate applied: hash({:?}, {:?}) = {:?}", self.inputs[*a], self.inputs[*b], hash_result);
+        None if computed != BigInt::from(1) => {
+          return Err(ZkMockError::AssertionFailed { gate: position, assertion: self.describe_assertion(&gate, &wires) });
         }
+        None => {}
       }
     }
 
-    // Checking for whether the witness would be satisfying the constraint that has been defined in the circuits defined in R1CS
+    self.record(CircuitCall::AddGate(gate.clone()));
+    self.gates.push(gate);
+    Ok(())
+  }
 
-    let is_valid = r1cs.is_satisfied(|a, b| {
-      if let Some(ref hash_function) = self.hash_function {
-        hash_function.hash(a, b) // the return value would be in terms of BigInt
-      } else {
-        panic!("Hash function not defined for this circuit");
-      }
-    });
+  /// Adds an output value to the circuit.
+  pub fn add_output(&mut self, output: BigInt) {
+    self.record(CircuitCall::AddOutput(output.clone()));
+    self.outputs.push(output);
+  }
 
-    let mut file = std::fs::File::create(proof_file).expect("Unable to create proof file");
-    file.write_all(&[is_valid as u8]).expect("Unable to write proof data"); // writing whether the proof is valid as a single byte (1 for valid, 0 for invalid one)
-    println!("Proof-generation completed. Proof is valid: {}", is_valid);
+  /// Labels `wire` as a named output; its value is published in the proof under `name`.
+  pub fn add_named_output(&mut self, name: &str, wire: usize) {
+    self.record(CircuitCall::AddNamedOutput(name.to_string(), wire));
+    self.named_outputs.push((name.to_string(), wire));
   }
 
-  pub fn verify_proof(&self, proof_file: &str) -> bool {
-    let proof_data = std::fs::read(proof_file).expect("Unable to read proof-file");
-    if proof_data.len() != 1 {
-      panic!("Invalid-proof data format");
-    }
-    proof_data[0] == 1 // returns true if the proof is valid (1), false otherwise (0)
+  /// Returns the gates in the order they were added.
+  pub fn gates(&self) -> &[Gate] {
+    &self.gates
   }
-}
\ No newline at end of file
+
+  /// Returns the number of gates, i.e. the number of constraints `generate_proof` emits.
+  pub fn gate_count(&self) -> usize {
+    self.gates.len()
+  }
+
+  /// Reports the circuit's structural metrics: gate counts per kind, input and output counts, and depth.
+  pub fn stats(&self) -> CircuitStats {
+    let mut gate_counts = BTreeMap::new();
+    for gate in &self.gates {
+      *gate_counts.entry(gate.kind()).or_insert(0) += 1;
+    }
+    CircuitStats {
+      gate_counts,
+      inputs: self.inputs.len(),
+      public_inputs: self.public_inputs.len(),
+      outputs: self.outputs.len(),
+      depth: self.depth()
+    }
+  }
+
+  /**
+   * Computes the length of the longest dependency chain between gates, walking them in topological order.
+   *
+   * A gate depends on every other gate writing a wire it reads, wherever either sits in the gate list, and its depth
+   * is one more than the deepest of those (one for a gate reading only inputs). Gates on a dependency cycle are
+   * never reached by the walk and do not count.
+   */
+  fn depth(&self) -> usize {
+    let mut producers: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (position, gate) in self.gates.iter().enumerate() {
+      if let Some(output) = gate.output() {
+        producers.entry(output).or_default().push(position);
+      }
+    }
+
+    let mut dependents = vec![Vec::new(); self.gates.len()];
+    let mut pending = vec![0; self.gates.len()]; // dependencies of each gate not walked yet
+    for (position, gate) in self.gates.iter().enumerate() {
+      let mut read = gate.wires();
+      read.retain(|&wire| Some(wire) != gate.output());
+      read.sort_unstable();
+      read.dedup();
+      for producer in read.iter().filter_map(|wire| producers.get(wire)).flatten() {
+        if *producer != position {
+          dependents[*producer].push(position);
+          pending[position] += 1;
+        }
+      }
+    }
+
+    let mut depths = vec![1; self.gates.len()];
+    let mut ready: Vec<usize> = (0..self.gates.len()).filter(|&position| pending[position] == 0).collect();
+    let mut depth = 0;
+    while let Some(position) = ready.pop() {
+      depth = depth.max(depths[position]);
+      for &dependent in &dependents[position] {
+        depths[dependent] = depths[dependent].max(depths[position] + 1);
+        pending[dependent] -= 1;
+        if pending[dependent] == 0 {
+          ready.push(dependent);
+        }
+      }
+    }
+    depth
+  }
+
+  /**
+   * Runs the optimization passes over the circuit and reports what changed.
+   *
+   * Order of the passes:
+   * 1. Constant folding - a gate whose operands are all pinned by Const gates becomes a Const gate holding its value,
+   *    and an assertion that holds on constants is dropped (see `fold_constants`).
+   * 2. Duplicate-constraint removal - a gate equal to an earlier one up to operand order (see `Gate::canonical`),
+   *    e.g. AssertEqual(b, a) after AssertEqual(a, b), enforces nothing new. Only gates writing no wire can repeat,
+   *    since a wire has a single writer.
+   * 3. Unused-input pruning - inputs no gate touches are dropped and the remaining wires re-indexed.
+   * 4. High-degree expansion - Poly gates above degree 2 are split into Mul and Linear gates (see `expand_high_degree_gates`).
+   *
+   * Folding runs first so the gates it turns into constants can be de-duplicated and the inputs only they read pruned;
+   * pruning runs after de-duplication so wires only referenced by a removed duplicate are pruned too. Expansion runs
+   * last as it allocates wires, which would otherwise be renumbered again by pruning.
+   */
+  pub fn optimize(&mut self) -> OptimizationReport {
+    self.record(CircuitCall::Optimize);
+    let mut report = OptimizationReport::default();
+    let mut counted = self.constraint_count();
+    let mut tally = |circuit: &Circuit, report: &mut OptimizationReport| {
+      let count = circuit.constraint_count();
+      report.constraints_removed += counted.saturating_sub(count);
+      report.constraints_added += count.saturating_sub(counted);
+      counted = count;
+    };
+
+    self.fold_constants();
+    tally(self, &mut report);
+    self.remove_duplicate_gates();
+    tally(self, &mut report);
+    report.inputs_pruned = self.prune_unused_inputs();
+    self.expand_high_degree_gates();
+    tally(self, &mut report);
+    report
+  }
+
+  /// Returns the number of constraints `to_r1cs` produces, which does not depend on the wire values.
+  fn constraint_count(&self) -> usize {
+    self.r1cs_for(&vec![BigInt::from(0); self.wire_count()]).constraints.len()
+  }
+
+  /**
+   * Replaces every gate whose operands are all pinned by Const gates with a Const gate holding the value it computes,
+   * and drops every assertion gate that holds on constant operands. A Const gate pins its wire wherever it sits, and
+   * a folded gate's output is pinned in turn, so constants propagate through the gates in order.
+   *
+   * Hash and Commit gates are kept, as the circuit may have no hash function yet, and so are IsZero gates, whose
+   * witness inverse wire a Const gate could not write.
+   */
+  fn fold_constants(&mut self) {
+    let mut known = vec![false; self.wire_count()];
+    let mut values = vec![BigInt::from(0); self.wire_count()];
+    for gate in &self.gates {
+      if let Gate::Const(wire, value) = gate {
+        known[*wire] = true;
+        values[*wire] = self.reduce(value);
+      }
+    }
+
+    for gate in std::mem::take(&mut self.gates) {
+      let written = gate.written_wires();
+      let foldable = !matches!(gate, Gate::Const(..) | Gate::Hash(..) | Gate::Commit(..) | Gate::IsZero(..))
+        && gate.wires().into_iter().filter(|wire| !written.contains(wire)).all(|wire| known[wire]);
+      if foldable {
+        let value = self.compute_gate(&gate, &values);
+        match gate.output() {
+          Some(output) => {
+            known[output] = true;
+            values[output] = value.clone();
+            self.gates.push(Gate::Const(output, value));
+            continue;
+          }
+          None if value == BigInt::from(1) => continue,
+          None => {}
+        }
+      }
+      self.gates.push(gate);
+    }
+  }
+
+  /// Drops every gate whose canonical form matches an earlier gate's.
+  fn remove_duplicate_gates(&mut self) {
+    let mut seen = HashSet::with_capacity(self.gates.len());
+    self.gates.retain(|gate| seen.insert(bincode::serialize(&gate.canonical()).expect("Unable to serialize gate")));
+  }
+
+  /**
+   * Rewrites every Poly gate above degree 2 (three or more terms) into Mul gates chaining the powers of its inputs
+   * and a Linear gate summing the weighted powers, so every gate is at most degree 2. The powers of a wire are
+   * chained once and shared by every term and gate reading them, and the constant terms read one wire pinned to 1
+   * by a Const gate.
+   *
+   * The new wires are intermediate wires allocated in gate order, so the intermediate wires from the first
+   * expanded gate on are renumbered (named outputs follow them).
+   */
+  fn expand_high_degree_gates(&mut self) {
+    if !self.gates.iter().any(|gate| matches!(gate, Gate::Poly(inputs, _, _) if inputs.len() > 2)) {
+      return;
+    }
+
+    let first_intermediate = self.inputs.len();
+    let mut next = first_intermediate;
+    let mut renamed: HashMap<usize, usize> = HashMap::new(); // old intermediate wire -> new
+    let mut powers: HashMap<usize, Vec<usize>> = HashMap::new(); // wire -> wires holding its powers 2, 3, ...
+    let mut one = None;
+    let mut expanded = Vec::with_capacity(self.gates.len());
+    for mut gate in std::mem::take(&mut self.gates) {
+      if let Gate::Poly(inputs, coeffs, output) = &gate
+        && inputs.len() > 2 {
+        let mut terms = Vec::with_capacity(inputs.len());
+        for (power, (input, coeff)) in inputs.iter().zip(coeffs).enumerate() {
+          let input = renamed.get(input).copied().unwrap_or(*input);
+          let term = match power {
+            0 => *one.get_or_insert_with(|| {
+              expanded.push(Gate::Const(next, BigInt::from(1)));
+              next += 1;
+              next - 1
+            }),
+            1 => input,
+            _ => {
+              let chain = powers.entry(input).or_default();
+              while chain.len() < power - 1 {
+                expanded.push(Gate::Mul(chain.last().copied().unwrap_or(input), input, next));
+                chain.push(next);
+                next += 1;
+              }
+              chain[power - 2]
+            }
+          };
+          terms.push((term, coeff.clone()));
+        }
+        let output = if *output >= first_intermediate {
+          renamed.insert(*output, next);
+          next += 1;
+          next - 1
+        } else {
+          *output
+        };
+        expanded.push(Gate::Linear(terms, output));
+        continue;
+      }
+
+      let mut written = gate.written_wires();
+      written.sort_unstable();
+      for wire in written {
+        if wire >= first_intermediate && !renamed.contains_key(&wire) {
+          renamed.insert(wire, next);
+          next += 1;
+        }
+      }
+      gate.remap_wires(|wire| renamed.get(&wire).copied().unwrap_or(wire));
+      expanded.push(gate);
+    }
+    self.gates = expanded;
+    for (_, wire) in &mut self.named_outputs {
+      *wire = renamed.get(wire).copied().unwrap_or(*wire);
+    }
+  }
+
+  /// Removes inputs not referenced by any gate and re-indexes the gates, returning how many were removed.
+  /// Public inputs and named outputs are part of the statement and are always kept; a removed named input loses its name.
+  fn prune_unused_inputs(&mut self) -> usize {
+    let mut used = vec![false; self.inputs.len()];
+    for &index in self.public_inputs.iter().chain(self.named_outputs.iter().map(|(_, wire)| wire)) {
+      if let Some(flag) = used.get_mut(index) {
+        *flag = true;
+      }
+    }
+    for gate in &self.gates {
+      for wire in gate.wires() {
+        if let Some(flag) = used.get_mut(wire) {
+          *flag = true;
+        }
+      }
+    }
+
+    // old index -> new index for every input that survives
+    let mut remap: HashMap<usize, usize> = HashMap::new();
+    let mut kept = Vec::new();
+    for (index, input) in self.inputs.drain(..).enumerate() {
+      if used[index] {
+        remap.insert(index, kept.len());
+        kept.push(input);
+      }
+    }
+    let pruned = used.len() - kept.len();
+    self.inputs = kept;
+
+    // Intermediate wires follow the inputs, so they shift down by the number of inputs removed
+    let new_index = |wire: usize| remap.get(&wire).copied().unwrap_or_else(|| wire - pruned);
+    for gate in &mut self.gates {
+      gate.remap_wires(new_index);
+    }
+    for index in &mut self.public_inputs {
+      *index = remap[index];
+    }
+    for (_, wire) in &mut self.named_outputs {
+      *wire = new_index(*wire);
+    }
+    self.input_names.retain_mut(|(_, wire)| match remap.get(wire) {
+      Some(&new_wire) => {
+        *wire = new_wire;
+        true
+      }
+      None => false
+    });
+    pruned
+  }
+
+  /// Computes the value a gate produces from the given wire values, reduced into the field if a modulus is set.
+  /// Gates without an output wire produce 1 when their assertion holds and 0 otherwise.
+  /// Panics if a Hash gate is evaluated without a hash function.
+  fn compute_gate(&self, gate: &Gate, wires: &[BigInt]) -> BigInt {
+    let value = match gate {
+      Gate::Add(a, b, _) => &wires[*a] + &wires[*b],
+      Gate::Sub(a, b, _) => &wires[*a] - &wires[*b],
+      Gate::Mul(a, b, _) => &wires[*a] * &wires[*b],
+      Gate::Hash(a, b, _) | Gate::Commit(a, b, _) => self.apply_hash(&wires[*a], &wires[*b]),
+      Gate::Const(_, value) => value.clone(),
+      Gate::IsZero(x, _, _) => BigInt::from(self.reduce(&wires[*x]) == BigInt::from(0)),
+      Gate::Mux(index, options, _) => self.mux_position(&wires[*index], options.len())
+        .map(|position| wires[options[position]].clone())
+        .unwrap_or_else(|| BigInt::from(0)),
+      Gate::Poly(inputs, coeffs, _) => inputs.iter().zip(coeffs).enumerate()
+        .map(|(power, (input, coeff))| coeff * wires[*input].pow(power as u32))
+        .sum(),
+      Gate::LessThanOrEqual(a, b) => BigInt::from(self.reduce(&wires[*a]) <= self.reduce(&wires[*b])),
+      Gate::Assert(wire) => BigInt::from(self.reduce(&wires[*wire]) == BigInt::from(1)),
+      Gate::Bool(wire) => BigInt::from(matches!(self.reduce(&wires[*wire]).to_u8(), Some(0 | 1))),
+      Gate::Linear(terms, _) => terms.iter().map(|(input, coeff)| coeff * &wires[*input]).sum(),
+      Gate::AssertEqual(a, b) => BigInt::from(self.reduce(&wires[*a]) == self.reduce(&wires[*b])),
+      Gate::LessThan(a, b) => BigInt::from(self.less_than_holds(&wires[*a], &wires[*b]))
+    };
+    self.reduce(&value)
+  }
+
+  /// Returns the number of wires: the inputs followed by the intermediate wires gates write past the last input.
+  fn wire_count(&self) -> usize {
+    self.gates.iter().flat_map(Gate::wires).map(|wire| wire + 1).max().unwrap_or(0).max(self.inputs.len())
+  }
+
+  /**
+   * Extends `wires`, one value per input, with the values of the intermediate wires: wires past the last input
+   * that gates write, e.g. the a * b of (a * b) * c. They are computed by running the gates in order, so each one
+   * must be written before it is read (see `validate`), and become private witness variables of the R1CS.
+   */
+  fn with_intermediates(&self, wires: &[BigInt]) -> Vec<BigInt> {
+    self.hashable_intermediates(wires).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `with_intermediates`: fails with the hash function's `check_input` error, e.g.
+  /// `ZkMockError::FieldEncoding` for a negative wire under `Keccak256`, as soon as an input or a computed wire is
+  /// a value it cannot hash. Every wire may reach a Hash gate or the witness tree, where it would otherwise panic.
+  fn hashable_intermediates(&self, wires: &[BigInt]) -> Result<Vec<BigInt>, ZkMockError> {
+    let check = |value: &BigInt| self.hash_function.as_ref().map_or(Ok(()), |hash_function| hash_function.check_input(value));
+    wires.iter().try_for_each(check)?;
+    let mut extended = wires.to_vec();
+    extended.resize(self.wire_count().max(wires.len()), BigInt::from(0));
+    for gate in &self.gates {
+      if let Some(output) = gate.output()
+        && output >= wires.len() {
+        extended[output] = self.compute_gate(gate, &extended);
+        check(&extended[output])?;
+      }
+      if let Gate::IsZero(x, _, inv) = gate
+        && *inv >= wires.len() {
+        extended[*inv] = self.is_zero_inverse(&extended[*x]);
+        check(&extended[*inv])?;
+      }
+    }
+    Ok(extended)
+  }
+
+  /// Returns a wire past the inputs that `gate`, at `position`, reads although no earlier gate writes it.
+  fn undefined_read(&self, position: usize, gate: &Gate) -> Option<usize> {
+    let written = gate.written_wires();
+    gate.wires().into_iter()
+      .filter(|wire| *wire >= self.inputs.len() && !written.contains(wire))
+      .find(|wire| !self.gates[..position].iter().any(|earlier| earlier.written_wires().contains(wire)))
+  }
+
+  /// Returns the wire the next new intermediate gets: one past the inputs and every wire the gates write.
+  fn next_unallocated_wire(&self) -> usize {
+    self.gates.iter().flat_map(Gate::written_wires).map(|wire| wire.saturating_add(1)).max().unwrap_or(0).max(self.inputs.len())
+  }
+
+  /**
+   * Checks the wires of `gate`, at `position`, given `next`, the next unallocated wire before it: every wire it reads
+   * must be an input or written by an earlier gate, every wire it writes at most `next`, so intermediate wires
+   * are numbered without gaps, and written by no other gate. Returns the next unallocated wire after the gate.
+   *
+   * # Errors
+   * `ZkMockError::UndefinedWire`, `ZkMockError::UnallocatedWire` or `ZkMockError::WireWrittenTwice`, naming the gate
+   * and the offending wire.
+   */
+  fn check_gate_wiring(&self, position: usize, gate: &Gate, mut next: usize) -> Result<usize, ZkMockError> {
+    let mut written = gate.written_wires();
+    written.sort_unstable();
+    for (i, &wire) in written.iter().enumerate() {
+      if wire > next {
+        return Err(ZkMockError::UnallocatedWire { gate: position, wire, next });
+      }
+      if i > 0 && written[i - 1] == wire {
+        return Err(ZkMockError::WireWrittenTwice { gate: position, wire, earlier: position });
+      }
+      if let Some(earlier) = self.gates[..position.min(self.gates.len())].iter().position(|earlier| earlier.written_wires().contains(&wire)) {
+        return Err(ZkMockError::WireWrittenTwice { gate: position, wire, earlier });
+      }
+      next = next.max(wire.saturating_add(1));
+    }
+    if let Some(wire) = self.undefined_read(position, gate) {
+      return Err(ZkMockError::UndefinedWire { gate: position, wire });
+    }
+    Ok(next)
+  }
+
+  /// Runs `check_gate_wiring` over every gate in order.
+  fn check_wiring(&self) -> Result<(), ZkMockError> {
+    let mut next = self.inputs.len();
+    for (position, gate) in self.gates.iter().enumerate() {
+      next = self.check_gate_wiring(position, gate, next)?;
+    }
+    Ok(())
+  }
+
+  /// Describes what an assertion gate asserts, with the values of `wires`, e.g. "input[2] == 1 (input[2] = 0)".
+  /// Returns an empty string for gates with an output wire.
+  fn describe_assertion(&self, gate: &Gate, wires: &[BigInt]) -> String {
+    match gate {
+      Gate::LessThanOrEqual(a, b) => format!("input[{}] <= input[{}] ({} <= {})", a, b, wires[*a], wires[*b]),
+      Gate::Assert(wire) => format!("input[{}] == 1 (input[{}] = {})", wire, wire, wires[*wire]),
+      Gate::Bool(wire) => format!("input[{}] is 0 or 1 (input[{}] = {})", wire, wire, wires[*wire]),
+      Gate::AssertEqual(a, b) => format!("input[{}] == input[{}] ({} == {})", a, b, wires[*a], wires[*b]),
+      Gate::LessThan(a, b) => format!(
+        "input[{}] < input[{}] < 2^{} ({} < {})", a, b, self.comparison_bits, wires[*a], wires[*b]
+      ),
+      _ => String::new()
+    }
+  }
+
+  /// Returns the option a Mux index value selects, or None if it is out of range.
+  fn mux_position(&self, index: &BigInt, option_count: usize) -> Option<usize> {
+    self.reduce(index).to_usize().filter(|&position| position < option_count)
+  }
+
+  /// Reduces a value into the circuit's field, or returns it unchanged when no modulus is set.
+  fn reduce(&self, value: &BigInt) -> BigInt {
+    r1cs::reduce(value, self.modulus.as_ref())
+  }
+
+  /**
+   * Adds an IsZero gadget over wire `x`, allocating its output and inverse-witness wires.
+   *
+   * The output wire holds 1 if x is zero and 0 otherwise. The inverse witness is x^-1 in the field
+   * (computed as x^(p-2) mod p, so the modulus must be prime), or 0 when x is zero. Without a modulus
+   * only x in {-1, 0, 1} has an integer inverse, any other nonzero x makes the circuit unsatisfiable.
+   *
+   * Returns the index of the output wire.
+   */
+  pub fn add_is_zero(&mut self, x: usize) -> usize {
+    let is_zero = self.reduce(&self.inputs[x]) == BigInt::from(0);
+    let inverse = self.is_zero_inverse(&self.inputs[x]);
+
+    let output = self.add_input(BigInt::from(is_zero));
+    let inv = self.add_input(inverse);
+    self.add_gate(Gate::IsZero(x, output, inv));
+    output
+  }
+
+  /// The inverse witness of an IsZero gadget over `x`, see `add_is_zero`.
+  fn is_zero_inverse(&self, x: &BigInt) -> BigInt {
+    let value = self.reduce(x);
+    match &self.modulus {
+      _ if value == BigInt::from(0) => BigInt::from(0),
+      Some(modulus) => value.modpow(&(modulus - 2), modulus),
+      None => value
+    }
+  }
+
+  /**
+   * Fills in the witness: walks the gates in order and overwrites every output wire with the value its gate
+   * computes from the current wire values (refreshing IsZero inverse witnesses along the way).
+   *
+   * Only the wires the circuit reads as inputs need real values, the rest are derived, so a circuit whose inputs
+   * were changed (e.g. with `set_inputs_from`) can be made consistent again before proving.
+   * Panics if a Hash or Commit gate is evaluated without a hash function.
+   */
+  pub fn compute_witness(&mut self) {
+    self.record(CircuitCall::ComputeWitness);
+    let input_count = self.inputs.len();
+    self.inputs.resize(self.wire_count(), BigInt::from(0));
+    for position in 0..self.gates.len() {
+      let gate = &self.gates[position];
+      let value = self.compute_gate(gate, &self.inputs);
+      if let Gate::IsZero(x, _, inv) = gate {
+        let inverse = self.is_zero_inverse(&self.inputs[*x]);
+        self.inputs[*inv] = inverse;
+      }
+      if let Some(output) = self.gates[position].output() {
+        self.inputs[output] = value;
+      }
+    }
+    self.inputs.truncate(input_count);
+  }
+
+  /**
+   * Asserts that `wires` hold values sorted in ascending order (duplicates allowed), by adding a
+   * LessThanOrEqual gate between every pair of consecutive wires.
+   *
+   * An out-of-order list leaves exactly the gates of the offending pairs unsatisfied. Each comparison
+   * decomposes the difference of its pair into 64 boolean witness bits, so the values should be
+   * non-negative and less than 2^64 apart.
+   */
+  pub fn add_sorted_check(&mut self, wires: &[usize]) {
+    for pair in wires.windows(2) {
+      self.add_gate(Gate::LessThanOrEqual(pair[0], pair[1]));
+    }
+  }
+
+  /**
+   * Evaluates the circuit over a different set of input values, leaving the circuit untouched.
+   *
+   * `inputs` are assigned to the wires starting from index 0; wires past the end of `inputs`
+   * start at zero and are expected to be written by gates. Gates run in order, each one writing
+   * its computed value to its output wire so later gates see it.
+   *
+   * Returns the value computed by each gate, in gate order.
+   */
+  pub fn outputs_for(&self, inputs: &[BigInt]) -> Vec<BigInt> {
+    let mut wires = inputs.to_vec();
+    if wires.len() < self.wire_count() {
+      wires.resize(self.wire_count(), BigInt::from(0));
+    }
+
+    let mut outputs = Vec::with_capacity(self.gates.len());
+    for gate in &self.gates {
+      let value = self.compute_gate(gate, &wires);
+      if let Some(output) = gate.output() {
+        wires[output] = value.clone();
+      }
+      outputs.push(value);
+    }
+    outputs
+  }
+
+  /**
+   * Runs the gates in order over the circuit's inputs and returns the values of its output wires, without
+   * building a proof: the wires gates write that no later gate reads, in the order they are written (see `dry_run`).
+   *
+   * # Errors
+   * - `ZkMockError::MissingHashFunction` naming the first Hash or Commit gate of a circuit without a hash function;
+   * - `ZkMockError::UndefinedWire` for a gate reading a wire that is neither an input nor written by an earlier gate;
+   * - `ZkMockError::UnallocatedWire` for a gate writing past the next unallocated wire;
+   * - the hash function's `check_input` error for a wire it cannot hash, as for `validate`.
+   */
+  pub fn evaluate(&self) -> Result<Vec<BigInt>, ZkMockError> {
+    if self.hash_function.is_none()
+      && let Some(gate) = self.gates.iter().position(|gate| matches!(gate, Gate::Hash(..) | Gate::Commit(..))) {
+      return Err(ZkMockError::MissingHashFunction { gate });
+    }
+    self.check_wiring()?;
+    self.hashable_intermediates(&self.inputs)?;
+    Ok(self.dry_run().outputs.into_iter().map(|check| check.computed).collect())
+  }
+
+  /**
+   * Evaluates the circuit and compares every output wire with its declared output, without building a proof.
+   *
+   * The output wires are the wires gates write that no later gate reads, in the order they are written;
+   * the values declared with `add_output` are matched with them in that same order. A fast sanity check
+   * before proving: a mismatch means the declared outputs do not follow from the inputs.
+   */
+  pub fn dry_run(&self) -> DryRunReport {
+    let computed = self.outputs_for(&self.inputs);
+    let output_wires = self.gates.iter().enumerate().filter_map(|(position, gate)| {
+      let wire = gate.output()?;
+      let used_later = self.gates[position + 1..].iter().any(|later| later.wires().contains(&wire));
+      if used_later { None } else { Some((wire, computed[position].clone())) }
+    });
+
+    let mut declared = self.outputs.iter().map(|value| self.reduce(value));
+    let outputs = output_wires.map(|(wire, computed)| OutputCheck { wire, computed, expected: declared.next() }).collect();
+    DryRunReport { outputs, unmatched: declared.collect() }
+  }
+
+  /**
+   * Flags gates whose constraint can never hold, whatever witness is supplied.
+   *
+   * Works purely from the circuit structure, so it can run before any witness exists:
+   * - a Const gate pinning a wire to a different value than an earlier Const gate did;
+   * - an Add/Sub/Mul/Hash/Commit gate whose operands and output are all pinned by Const gates
+   *   to values that do not satisfy it (Hash/Commit only when a hash function is set);
+   * - a LessThanOrEqual gate whose operands are both pinned by Const gates, in the wrong order;
+   * - an Assert gate on a wire pinned by a Const gate to a value other than 1;
+   * - an AssertEqual gate whose operands are pinned by Const gates to different values;
+   * - a LessThan gate whose operands are both pinned by Const gates, in the wrong order or out of range;
+   * - a Bool gate on a wire pinned by a Const gate to a value other than 0 or 1.
+   *
+   * Returns the indices of the offending gates (which are also their constraint indices).
+   */
+  pub fn find_unsatisfiable(&self) -> Vec<usize> {
+    let mut constants: HashMap<usize, BigInt> = HashMap::new();
+    let mut unsatisfiable = Vec::new();
+
+    for (position, gate) in self.gates.iter().enumerate() {
+      if let Gate::Const(wire, value) = gate {
+        match constants.get(wire) {
+          Some(bound) if self.reduce(bound) != self.reduce(value) => unsatisfiable.push(position),
+          Some(_) => {}
+          None => { constants.insert(*wire, value.clone()); }
+        }
+      }
+    }
+
+    for (position, gate) in self.gates.iter().enumerate() {
+      if let Gate::LessThanOrEqual(a, b) = gate
+        && let (Some(a_value), Some(b_value)) = (constants.get(a), constants.get(b))
+        && self.reduce(a_value) > self.reduce(b_value) {
+        unsatisfiable.push(position);
+      }
+      if let Gate::Assert(wire) = gate
+        && let Some(value) = constants.get(wire)
+        && self.reduce(value) != BigInt::from(1) {
+        unsatisfiable.push(position);
+      }
+      if let Gate::AssertEqual(a, b) = gate
+        && let (Some(a_value), Some(b_value)) = (constants.get(a), constants.get(b))
+        && self.reduce(a_value) != self.reduce(b_value) {
+        unsatisfiable.push(position);
+      }
+      if let Gate::LessThan(a, b) = gate
+        && let (Some(a_value), Some(b_value)) = (constants.get(a), constants.get(b))
+        && !self.less_than_holds(a_value, b_value) {
+        unsatisfiable.push(position);
+      }
+      if let Gate::Bool(wire) = gate
+        && let Some(value) = constants.get(wire)
+        && !matches!(self.reduce(value).to_u8(), Some(0 | 1)) {
+        unsatisfiable.push(position);
+      }
+      let (a, b, output) = match gate {
+        Gate::Add(a, b, output) | Gate::Sub(a, b, output) | Gate::Mul(a, b, output) => (a, b, output),
+        Gate::Hash(a, b, output) | Gate::Commit(a, b, output) if self.hash_function.is_some() => (a, b, output),
+        _ => continue
+      };
+      if let (Some(a_value), Some(b_value), Some(output_value)) = (constants.get(a), constants.get(b), constants.get(output)) {
+        let computed = match gate {
+          Gate::Add(..) => a_value + b_value,
+          Gate::Sub(..) => a_value - b_value,
+          Gate::Mul(..) => a_value * b_value,
+          _ => self.apply_hash(a_value, b_value)
+        };
+        if self.reduce(&computed) != self.reduce(output_value) {
+          unsatisfiable.push(position);
+        }
+      }
+    }
+
+    unsatisfiable.sort_unstable();
+    unsatisfiable
+  }
+
+  /**
+   * Produces a human-readable walkthrough of why the proof is (or is not) valid.
+   *
+   * Lists every input (flagging the public ones), then walks the gates in order showing the values
+   * they combine, the value they compute and whether the constraint on the output wire holds.
+   * Meant for teaching, the wording is not a stable format.
+   */
+  pub fn explain_proof(&self) -> String {
+    let mut explanation = String::from("Inputs:\n");
+    for (index, input) in self.inputs.iter().enumerate() {
+      let visibility = if self.public_inputs.contains(&index) { "public" } else { "private" };
+      explanation.push_str(&format!("  input[{}] = {} ({})\n", index, input, visibility));
+    }
+
+    explanation.push_str("Gates:\n");
+    let wires = self.with_intermediates(&self.inputs);
+    let mut satisfied = 0;
+    for (position, gate) in self.gates.iter().enumerate() {
+      let computed = self.compute_gate(gate, &wires);
+      let (name, expression, output) = match gate {
+        Gate::Add(a, b, output) => ("Add", format!("{} + {}", wires[*a], wires[*b]), *output),
+        Gate::Sub(a, b, output) => ("Sub", format!("{} - {}", wires[*a], wires[*b]), *output),
+        Gate::Mul(a, b, output) => ("Mul", format!("{} * {}", wires[*a], wires[*b]), *output),
+        Gate::Hash(a, b, output) => ("Hash", format!("hash({}, {})", wires[*a], wires[*b]), *output),
+        Gate::Commit(value, randomness, commitment) => ("Commit", format!("hash({}, {})", wires[*value], wires[*randomness]), *commitment),
+        Gate::Const(wire, value) => ("Const", format!("const {}", value), *wire),
+        Gate::IsZero(x, output, _) => ("IsZero", format!("is_zero({})", wires[*x]), *output),
+        Gate::Mux(index, options, output) => ("Mux", format!("options[{}] of {} options", wires[*index], options.len()), *output),
+        Gate::Poly(inputs, coeffs, output) => ("Poly", inputs.iter().zip(coeffs).enumerate()
+          .map(|(power, (input, coeff))| format!("{} * {}^{}", coeff, wires[*input], power))
+          .collect::<Vec<_>>().join(" + "), *output),
+        Gate::Linear(terms, output) => ("Linear", terms.iter()
+          .map(|(input, coeff)| format!("{} * {}", coeff, wires[*input]))
+          .collect::<Vec<_>>().join(" + "), *output),
+        Gate::LessThanOrEqual(..) | Gate::Assert(..) | Gate::AssertEqual(..) | Gate::LessThan(..) | Gate::Bool(..) => {
+          // Assertion gates have no output wire to compare with, they must simply hold
+          let holds = computed == BigInt::from(1);
+          if holds {
+            satisfied += 1;
+          }
+          explanation.push_str(&format!(
+            "  gate {}: {} gate asserts {} -> constraint {}\n",
+            position, gate.kind(), self.describe_assertion(gate, &wires), if holds { "satisfied" } else { "NOT satisfied" }
+          ));
+          continue;
+        }
+      };
+      let holds = computed == self.reduce(&wires[output]);
+      if holds {
+        satisfied += 1;
+      }
+      explanation.push_str(&format!(
+        "  gate {}: {} gate computes {} = {}, expected input[{}] = {} -> constraint {}\n",
+        position, name, expression, computed, output, wires[output],
+        if holds { "satisfied" } else { "NOT satisfied" }
+      ));
+    }
+
+    explanation.push_str(&format!(
+      "Result: {} of {} constraints satisfied, the proof is {}\n",
+      satisfied, self.gates.len(), if satisfied == self.gates.len() { "valid" } else { "invalid" }
+    ));
+    explanation
+  }
+
+  /// Applies the circuit's hash function to two BigInt values.
+  /// Panics if no hash function is defined.
+  pub fn apply_hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    if let Some(ref hash_function) = self.hash_function {
+      hash_function.hash(a, b)
+    } else {
+      // the error handling
+      panic!("Hash function not defined for this circuit");
+    }
+  }
+
+  /// Applies the circuit's hash function to three BigInt values, through `HashFunction::hash3` so overrides are honoured.
+  /// Panics if no hash function is defined.
+  pub fn apply_hash3(&self, a: &BigInt, b: &BigInt, c: &BigInt) -> BigInt {
+    match self.hash_function {
+      Some(ref hash_function) => hash_function.hash3(a, b, c),
+      None => panic!("Hash function not defined for this circuit")
+    }
+  }
+
+  /**
+   * Converts the circuit's gates and inputs into R1CS constraints and prepares for proof-generation.
+   *
+   * Steps:
+   * 1. Initializes a new R1CS instance.
+   * 2. Converts all circuit inputs into R1CS variables with unique-indices, carrying over which ones are public.
+   * 3. Iterates over each gate in the circuit and translates it into an R1CS constraint:
+   *    - Add gate: Enforces input[a] + input[b] = input[output].
+   *    - Sub gate: Enforces input[a] - input[b] = input[output].
+   *    - Mul gate: Enforces input[a] * input[b] = input[output].
+   *    - Hash gate: Computes hash(input[a], input[b]) and enforces it equals input[output].
+   *    - Commit gate: Enforces hash(input[value], input[randomness]) = input[commitment], i.e. the commitment opens correctly.
+   *    - Const gate: Enforces input[wire] + 0 = constant, the constant being appended as a public R1CS variable.
+   *    - IsZero gate: Enforces input[x] * input[inv] = 1 - input[out] and input[x] * input[out] = 0,
+   *      which forces out = 1 when x = 0 and out = 0 otherwise (x must then be invertible via inv).
+   *    - Mux gate: One-hot decomposes the index into witness bits b_i (b_i * b_i = b_i, Σ b_i = 1, Σ i * b_i = input[index]),
+   *      then enforces input[out] = Σ b_i * input[options[i]]. An out-of-range index leaves no valid decomposition.
+   *    - Poly gate: Enforces input[out] = Σ coeffs[i] * input[inputs[i]]^i. Each power x^i (i >= 2) is built from x^(i-1)
+   *      by a Mul constraint on a witness variable, chained once per input wire and shared by the gate's terms, so a
+   *      polynomial in one wire with n terms costs n - 2 Mul constraints. The weighted powers are then summed in one Add constraint.
+   *    - LessThanOrEqual gate: Decomposes input[b] - input[a] into n witness bits d_i (d_i * d_i = d_i), n being the
+   *      comparison bit width (64 by default), then enforces input[a] + Σ 2^i * d_i = input[b]. A negative difference
+   *      (or one of 2^n or more) has no such decomposition, as long as a modulus, if set, is well above 2^n.
+   *    - Assert gate: Enforces input[wire] + 0 = 1, the 1 being a public R1CS variable like a Const gate's constant.
+   *    - Linear gate: Enforces Σ coeff_i * input[i] = input[out] in a single Add constraint with one weighted term per input.
+   *    - AssertEqual gate: Enforces input[a] - input[b] = 0, a Sub constraint with an empty output side.
+   *    - LessThan gate: Range-checks input[a] and input[b] by rebuilding each from n boolean witness bits, then
+   *      decomposes input[b] - input[a] - 1 into n more bits and enforces input[a] + 1 + Σ 2^i * d_i = input[b].
+   *    - Bool gate: Enforces input[wire] * input[wire] = input[wire], which only 0 and 1 satisfy.
+   * 4. Each constraint is added to the R1CS system for later proof generation or verification.
+   *
+   * Arguments:
+   * - proof_file: Path to the file where the proof (or R1CS data) will be stored (not implemented in this snippet).
+   * 
+   * order of the gates in self.gates is synonymous to the opcodes system in EVM and thus it mimics how the entire program was written in DSL(let's say circom) during the compilation-phase
+   * 
+   * A gate may write a wire past the last input, an intermediate wire that later gates read: (a * b) * c chains a Mul gate
+   * writing wire 3 into a Mul gate reading it. Its value is computed from the earlier gates and allocated as a fresh
+   * private R1CS variable after the inputs, so only the inputs have to be supplied. Add every input before such gates:
+   * an input takes the next index after the existing inputs, so adding one afterwards fails with `InputAfterIntermediate`.
+   *
+   * Returns the proof it wrote, whose `valid` flag tells whether the witness satisfied every constraint.
+   *
+   * # Errors
+   * - the `validate` errors, e.g. `ZkMockError::MissingHashFunction`;
+   * - `ZkMockError::UnsatisfiedCircuit` in strict mode, if the witness does not satisfy the constraints (no file is written);
+   * - `ZkMockError::Io` if the proof file cannot be written.
+   */
+  pub fn generate_proof(&self, proof_file: &str) -> Result<Proof, ZkMockError> {
+    let proof = self.try_prove()?;
+    proof.try_save(proof_file)?;
+    Ok(proof)
+  }
+
+  /// Like `generate_proof`, but binds the proof to `context` (e.g. "deposit_1") so it only verifies under that same context.
+  /// Prevents a valid proof from being replayed somewhere else; check it with `verify_proof_with_context`.
+  /// Returns the proof it wrote, and fails like `generate_proof`.
+  pub fn generate_proof_with_context(&self, proof_file: &str, context: &str) -> Result<Proof, ZkMockError> {
+    let proof = self.prove_in_context(Some(context), &self.inputs)?;
+    proof.try_save(proof_file)?;
+    Ok(proof)
+  }
+
+  /// Builds the proof in memory, `generate_proof` without writing it to a file.
+  /// Panics if the circuit fails `validate`, and in strict mode rather than returning an invalid proof.
+  /// Outside strict mode an empty circuit is proven with a warning in `Proof::warnings`, as its proof proves nothing.
+  pub fn prove(&self) -> Proof {
+    self.try_prove().unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `prove`: returns the `validate` error, or in strict mode `UnsatisfiedCircuit`, instead of panicking.
+  pub fn try_prove(&self) -> Result<Proof, ZkMockError> {
+    self.prove_in_context(None, &self.inputs)
+  }
+
+  /**
+   * Proves the circuit over a witness supplied at prove time, the way real proving systems take it.
+   *
+   * The circuit contributes its gate structure and public input values; `witness` holds the values of every
+   * other wire, in wire index order, and takes the place of whatever the circuit holds for them.
+   * The proof is valid only if the public inputs together with `witness` satisfy every constraint;
+   * the verifier re-checks the witness recorded in the proof, so the circuit's own private values never matter.
+   *
+   * # Errors
+   * - `ZkMockError::InvalidSpec` if the circuit marks more public inputs than it has inputs;
+   * - `ZkMockError::InputCountMismatch` if `witness` does not have exactly one value per private wire;
+   * - the `validate` errors, as for `try_prove`.
+   */
+  pub fn prove_with_witness(&self, witness: &[BigInt]) -> Result<Proof, ZkMockError> {
+    let private_count = self.inputs.len().checked_sub(self.public_inputs.len())
+      .ok_or_else(|| ZkMockError::InvalidSpec(format!("{} public inputs marked on {} inputs", self.public_inputs.len(), self.inputs.len())))?;
+    if witness.len() != private_count {
+      return Err(ZkMockError::InputCountMismatch { expected: private_count, found: witness.len() });
+    }
+
+    let mut witness = witness.iter();
+    let wires: Vec<BigInt> = self.inputs.iter().enumerate()
+      .map(|(index, value)| if self.public_inputs.contains(&index) { value.clone() } else { witness.next().expect("one witness value per private wire").clone() })
+      .collect();
+    self.prove_in_context(None, &wires)
+  }
+
+  /**
+   * Checks the circuit for misconfigurations that would otherwise only surface deep inside proof generation.
+   *
+   * # Errors
+   * - `ZkMockError::MissingHashFunction` naming the first Hash or Commit gate of a circuit without a hash function;
+   * - `ZkMockError::InvalidSpec` for a Poly gate whose input and coefficient counts differ;
+   * - `ZkMockError::UndefinedWire` for a gate reading an intermediate wire no earlier gate writes;
+   * - `ZkMockError::UnallocatedWire` for a gate writing past the next unallocated wire, e.g. wire 99 of a 3-input circuit;
+   * - the hash function's `check_input` error for a wire it cannot hash, e.g. `ZkMockError::FieldEncoding` for a negative
+   *   input of a `Keccak256` circuit;
+   * - `ZkMockError::EmptyCircuit` in strict mode, if the circuit has no gates (such a proof is vacuously valid).
+   */
+  pub fn validate(&self) -> Result<(), ZkMockError> {
+    for (position, gate) in self.gates.iter().enumerate() {
+      if let Gate::Poly(inputs, coeffs, _) = gate
+        && inputs.len() != coeffs.len() {
+        return Err(ZkMockError::InvalidSpec(format!("Poly gate {} has {} inputs but {} coefficients", position, inputs.len(), coeffs.len())));
+      }
+    }
+    if self.hash_function.is_none()
+      && let Some(gate) = self.gates.iter().position(|gate| matches!(gate, Gate::Hash(..) | Gate::Commit(..))) {
+      return Err(ZkMockError::MissingHashFunction { gate });
+    }
+    self.check_wiring()?;
+    self.hashable_intermediates(&self.inputs)?;
+    if self.gates.is_empty() && self.strict {
+      return Err(ZkMockError::EmptyCircuit);
+    }
+    Ok(())
+  }
+
+  /// Shared body of `try_prove`, `prove_with_witness` and `generate_proof_with_context`, proving over the wire values `wires`.
+  fn prove_in_context(&self, context: Option<&str>, wires: &[BigInt]) -> Result<Proof, ZkMockError> {
+    self.validate()?;
+    let wires = &self.hashable_intermediates(wires)?;
+    let mut warnings = Vec::new();
+    if self.gates.is_empty() {
+      warnings.push(ZkMockError::EmptyCircuit.to_string());
+    }
+
+    let r1cs = self.r1cs_for(wires);
+
+    // Checking for whether the witness would be satisfying the constraint that has been defined in the circuits defined in R1CS
+
+    let apply_hash = |a: &BigInt, b: &BigInt| self.apply_hash(a, b);
+    let apply_hash3 = |a: &BigInt, b: &BigInt, c: &BigInt| self.apply_hash3(a, b, c);
+    let is_valid = r1cs.is_satisfied_with_hash3(apply_hash, apply_hash3);
+    if !is_valid && self.strict {
+      let constraints = r1cs.constraints.iter().enumerate()
+        .filter(|(_, constraint)| constraint.check_with_hash3(&r1cs.variables, r1cs.modulus.as_ref(), &apply_hash, &apply_hash3).is_err())
+        .map(|(index, _)| index)
+        .collect();
+      return Err(ZkMockError::UnsatisfiedCircuit { constraints });
+    }
+
+    let mut proof = Proof {
+      valid: is_valid,
+      circuit_hash: self.context_hash(context),
+      challenge: BigInt::from(0), // squeezed below, from the rest of the proof
+      // with a commitment only the root is bound, the values stay out of the proof's public input vector
+      public_inputs: if self.commit_public_inputs { Vec::new() } else { self.public_inputs.iter().map(|&index| wires[index].clone()).collect() },
+      public_inputs_root: if self.commit_public_inputs { self.public_inputs_root() } else { None },
+      witness_root: self.witness_tree(wires).map(|tree| tree.root),
+      outputs: self.named_outputs.iter().map(|(name, wire)| (name.clone(), wires[*wire].clone())).collect(),
+      r1cs: r1cs.to_bytes(),
+      transcript: Transcript::new(),
+      warnings
+    };
+    (proof.transcript, proof.challenge) = proof.fiat_shamir_transcript();
+    Ok(proof)
+  }
+
+  /// Test helper: proves the circuit twice and panics unless both proofs serialize to identical bytes.
+  /// Catches nondeterminism such as a randomized hash function or unordered iteration leaking into the proof.
+  pub fn assert_deterministic(&self) {
+    let first = self.prove().to_bytes();
+    let second = self.prove().to_bytes();
+    assert!(first == second, "Proof generation is not deterministic: two runs produced different proof bytes");
+  }
+
+  /// Translates the circuit's inputs and gates into an R1CS instance (steps 1-4 of `generate_proof`).
+  pub fn to_r1cs(&self) -> R1CS {
+    self.r1cs_for(&self.with_intermediates(&self.inputs))
+  }
+
+  /// `to_r1cs` over the wire values `wires` instead of the circuit's own.
+  fn r1cs_for(&self, wires: &[BigInt]) -> R1CS {
+    let (mut r1cs, mut constants) = self.r1cs_inputs(wires);
+
+    // Creating the R1CS constraints based on the gates defined in the circuit
+    for gate in &self.gates {
+      self.lower_gate(gate, wires, &mut r1cs, &mut constants);
+    }
+
+    r1cs
+  }
+
+  /**
+   * Walks the circuit's constraint system gate by gate and hands it to `synthesizer`, to bridge to a real
+   * proving backend such as arkworks.
+   *
+   * Produces exactly the variables and constraints of `to_r1cs`, in the same order: first the circuit's inputs,
+   * then for each gate the variables it allocates (constants, witness bits, ...) followed by its constraints,
+   * tagged with the gate's index.
+   */
+  pub fn synthesize<S: ConstraintSynthesizer>(&self, synthesizer: &mut S) {
+    let wires = self.with_intermediates(&self.inputs);
+    let (mut r1cs, mut constants) = self.r1cs_inputs(&wires);
+    let mut allocated = 0;
+    let mut enforced = 0;
+    let mut flush = |r1cs: &R1CS, gate: Option<usize>, synthesizer: &mut S| {
+      for variable in &r1cs.variables[allocated..] {
+        synthesizer.alloc(variable, r1cs.is_public(variable.index));
+      }
+      allocated = r1cs.variables.len();
+      if let Some(gate) = gate {
+        for constraint in &r1cs.constraints[enforced..] {
+          synthesizer.enforce(gate, constraint);
+        }
+      }
+      enforced = r1cs.constraints.len();
+    };
+
+    flush(&r1cs, None, synthesizer);
+    for (position, gate) in self.gates.iter().enumerate() {
+      self.lower_gate(gate, &wires, &mut r1cs, &mut constants);
+      flush(&r1cs, Some(position), synthesizer);
+    }
+  }
+
+  /// Starts an R1CS instance holding the wire values `wires` as variables (step 2 of `generate_proof`), and the
+  /// constant -> public variable map the gate lowering fills.
+  fn r1cs_inputs(&self, wires: &[BigInt]) -> (R1CS, HashMap<BigInt, usize>) {
+    let mut r1cs = R1CS::new();
+    r1cs.variables = wires.iter().enumerate().map(|(i, input)| Variable { index: i, value: Some(input.clone()) }).collect();
+    r1cs.public = self.public_inputs.clone();
+    r1cs.modulus = self.modulus.clone();
+    (r1cs, HashMap::new()) // constant value -> public variable holding it
+  }
+
+  /// Appends the variables and constraints of one gate to `r1cs` (step 3 of `generate_proof`), reading
+  /// the values it needs (hash inputs, selectors, ...) from `wires`.
+  fn lower_gate(&self, gate: &Gate, wires: &[BigInt], r1cs: &mut R1CS, constants: &mut HashMap<BigInt, usize>) {
+    match gate {
+      // For an Add gate, create a constraint enforcing input[a] + input[b] = input[output]
+      Gate::Add(a, b, output) => {
+        r1cs.add_constraints(
+          vec![(*a, BigInt::from(1))],
+          vec![(*b, BigInt::from(1))],
+          vec![(*output, BigInt::from(1))],
+          Operation::Add
+        );
+      }
+      // For a Sub gate, create a constraint enforcing input[a] - input[b] = input[output]
+      Gate::Sub(a, b, output) => {
+        r1cs.add_constraints(
+          vec![(*a, BigInt::from(1))],
+          vec![(*b, BigInt::from(1))],
+          vec![(*output, BigInt::from(1))],
+          Operation::Sub
+        );
+      }
+      // For a Mul gate, create a constraint enforcing input[a] * input[b] = input[output]
+      Gate::Mul(a, b, output) => {
+        r1cs.add_constraints(
+          vec![(*a, BigInt::from(1))],
+          vec![(*b, BigInt::from(1))],
+          vec![(*output, BigInt::from(1))],
+          Operation::Mul
+        );
+      }
+      // For a Hash gate, compute hash(input[a], input[b]) and enforce it equals input[output]
+      Gate::Hash(a, b, output) => {
+        r1cs.add_constraints(
+          vec![(*a, BigInt::from(1))],
+          vec![(*b, BigInt::from(1))],
+          vec![(*output, BigInt::from(1))],
+          Operation::Hash
+        );
+      }
+      // For a Commit gate, the commitment must open to hash(input[value], input[randomness]); same check as a Hash gate
+      Gate::Commit(value, randomness, commitment) => {
+        r1cs.add_constraints(
+          vec![(*value, BigInt::from(1))],
+          vec![(*randomness, BigInt::from(1))],
+          vec![(*commitment, BigInt::from(1))],
+          Operation::Hash
+        );
+      }
+      // For a Const gate, the constant is public knowledge, so it becomes a public variable the wire must equal
+      Gate::Const(wire, value) => {
+        let constant = constant_variable(r1cs, constants, value);
+        r1cs.add_constraints(
+          vec![(*wire, BigInt::from(1))],
+          vec![],
+          vec![(constant, BigInt::from(1))],
+          Operation::Add
+        );
+      }
+      // For an IsZero gate, x * inv = 1 - out pins out to 0 for invertible x, and x * out = 0 pins it to 1 for x = 0
+      Gate::IsZero(x, output, inv) => {
+        let one = constant_variable(r1cs, constants, &BigInt::from(1));
+        r1cs.add_constraints(
+          vec![(*x, BigInt::from(1))],
+          vec![(*inv, BigInt::from(1))],
+          vec![(one, BigInt::from(1)), (*output, BigInt::from(-1))],
+          Operation::Mul
+        );
+        r1cs.add_constraints(
+          vec![(*x, BigInt::from(1))],
+          vec![(*output, BigInt::from(1))],
+          vec![],
+          Operation::Mul
+        );
+      }
+      // For a Mux gate, one-hot bits select the option: each bit is boolean, exactly one is set, and it sits at the index
+      Gate::Mux(index, options, output) => {
+        let one = constant_variable(r1cs, constants, &BigInt::from(1));
+        let selected = self.mux_position(&wires[*index], options.len());
+        let mut bits = Vec::with_capacity(options.len());
+        let mut products = Vec::with_capacity(options.len());
+        for (position, option) in options.iter().enumerate() {
+          let bit = BigInt::from(selected == Some(position));
+          let product = if selected == Some(position) { wires[*option].clone() } else { BigInt::from(0) };
+          let bit_variable = r1cs.append_variable(Some(bit));
+          let product_variable = r1cs.append_variable(Some(product));
+          r1cs.add_constraints(
+            vec![(bit_variable, BigInt::from(1))],
+            vec![(bit_variable, BigInt::from(1))],
+            vec![(bit_variable, BigInt::from(1))],
+            Operation::Mul
+          );
+          r1cs.add_constraints(
+            vec![(bit_variable, BigInt::from(1))],
+            vec![(*option, BigInt::from(1))],
+            vec![(product_variable, BigInt::from(1))],
+            Operation::Mul
+          );
+          bits.push((bit_variable, BigInt::from(1)));
+          products.push((product_variable, BigInt::from(1)));
+        }
+        let weighted_bits = bits.iter().enumerate().map(|(position, (bit, _))| (*bit, BigInt::from(position))).collect();
+        r1cs.add_constraints(bits, vec![], vec![(one, BigInt::from(1))], Operation::Add);
+        r1cs.add_constraints(weighted_bits, vec![], vec![(*index, BigInt::from(1))], Operation::Add);
+        r1cs.add_constraints(products, vec![], vec![(*output, BigInt::from(1))], Operation::Add);
+      }
+      // For a Poly gate, every power above 1 is chained from the one below it, once per input wire and shared by
+      // every term reading it, then the weighted powers are summed
+      Gate::Poly(inputs, coeffs, output) => {
+        let one = constant_variable(r1cs, constants, &BigInt::from(1));
+        let mut powers: HashMap<usize, Vec<(usize, BigInt)>> = HashMap::new(); // wire -> variables and values of its powers 2, 3, ...
+        let mut terms = Vec::with_capacity(inputs.len());
+        for (power, (input, coeff)) in inputs.iter().zip(coeffs).enumerate() {
+          let term = match power {
+            0 => one,
+            1 => *input,
+            _ => {
+              let chain = powers.entry(*input).or_default();
+              while chain.len() < power - 1 {
+                let (previous, previous_value) = chain.last().cloned().unwrap_or((*input, wires[*input].clone()));
+                let value = self.reduce(&(previous_value * &wires[*input]));
+                let next = r1cs.append_variable(Some(value.clone()));
+                r1cs.add_constraints(
+                  vec![(previous, BigInt::from(1))],
+                  vec![(*input, BigInt::from(1))],
+                  vec![(next, BigInt::from(1))],
+                  Operation::Mul
+                );
+                chain.push((next, value));
+              }
+              chain[power - 2].0
+            }
+          };
+          terms.push((term, coeff.clone()));
+        }
+        r1cs.add_constraints(terms, vec![], vec![(*output, BigInt::from(1))], Operation::Add);
+      }
+      // For a LessThanOrEqual gate, the difference b - a is rebuilt from boolean bits, which only works when it is non-negative
+      Gate::LessThanOrEqual(a, b) => {
+        let range = BigInt::from(1) << self.comparison_bits;
+        let difference = r1cs::reduce(&self.reduce(&(&wires[*b] - &wires[*a])), Some(&range));
+        let mut terms = vec![(*a, BigInt::from(1))];
+        terms.extend(decompose_bits(r1cs, &difference, self.comparison_bits));
+        r1cs.add_constraints(terms, vec![], vec![(*b, BigInt::from(1))], Operation::Add);
+      }
+      // For a LessThan gate, both inputs are range-checked and b - a - 1 is rebuilt from boolean bits
+      Gate::LessThan(a, b) => {
+        let range = BigInt::from(1) << self.comparison_bits;
+        for wire in [*a, *b] {
+          let value = r1cs::reduce(&self.reduce(&wires[wire]), Some(&range));
+          let terms = decompose_bits(r1cs, &value, self.comparison_bits);
+          r1cs.add_constraints(terms, vec![], vec![(wire, BigInt::from(1))], Operation::Add);
+        }
+        let difference = r1cs::reduce(&self.reduce(&(&wires[*b] - &wires[*a] - 1)), Some(&range));
+        let one = constant_variable(r1cs, constants, &BigInt::from(1));
+        let mut terms = vec![(*a, BigInt::from(1)), (one, BigInt::from(1))];
+        terms.extend(decompose_bits(r1cs, &difference, self.comparison_bits));
+        r1cs.add_constraints(terms, vec![], vec![(*b, BigInt::from(1))], Operation::Add);
+      }
+      // For an AssertEqual gate, the difference of the two wires must vanish
+      Gate::AssertEqual(a, b) => {
+        r1cs.add_constraints(
+          vec![(*a, BigInt::from(1))],
+          vec![(*b, BigInt::from(1))],
+          vec![],
+          Operation::Sub
+        );
+      }
+      // For a Linear gate, the weighted terms go into one side of a single Add constraint
+      Gate::Linear(terms, output) => {
+        r1cs.add_constraints(terms.clone(), vec![], vec![(*output, BigInt::from(1))], Operation::Add);
+      }
+      // For an Assert gate, the wire is pinned to the public constant 1
+      Gate::Assert(wire) => {
+        let one = constant_variable(r1cs, constants, &BigInt::from(1));
+        r1cs.add_constraints(
+          vec![(*wire, BigInt::from(1))],
+          vec![],
+          vec![(one, BigInt::from(1))],
+          Operation::Add
+        );
+      }
+      // For a Bool gate, the wire must equal its own square, i.e. be 0 or 1
+      Gate::Bool(wire) => {
+        r1cs.add_constraints(
+          vec![(*wire, BigInt::from(1))],
+          vec![(*wire, BigInt::from(1))],
+          vec![(*wire, BigInt::from(1))],
+          Operation::Mul
+        );
+      }
+    }
+  }
+
+  /**
+   * Hashes the circuit's structure: the number of wires, the gates with their wiring, which inputs are
+   * public, the modulus and the comparison bit width. Witness values are excluded, so changing an input value keeps the hash while
+   * adding, removing or rewiring a gate changes it. This is the value proofs carry as their circuit hash.
+   *
+   * The hash is taken over a canonical form so it does not depend on construction order: a verifier
+   * that rebuilds the same circuit independently gets the same hash. Canonicalization:
+   * - operands of commutative gates (Add, Mul) are ordered, so Add(a, b, c) and Add(b, a, c) match;
+   * - gates are sorted, since the constraint system they produce is a set;
+   * - public input indices are sorted;
+   * - wires are counted together, so a gate output added as an input (`add_gate_with_output`) and the same
+   *   wire left as an intermediate (a JSON spec) match.
+   *
+   * Wire numbering is kept as-is: the same circuit with its inputs added in another order is a different structure.
+   *
+   * The canonical form is serialized with bincode and hashed with SHA-256. The circuit's own hash function is
+   * only used inside the circuit (Hash gates and Merkle commitments): a mock hash folded over the serialization
+   * would let two different structures collide.
+   */
+  pub fn hash_structure(&self) -> BigInt {
+    let mut gates: Vec<Vec<u8>> = self.gates.iter()
+      .map(|gate| bincode::serialize(&gate.canonical()).expect("Unable to serialize gate"))
+      .collect();
+    gates.sort();
+    let mut public_inputs = self.public_inputs.clone();
+    public_inputs.sort_unstable();
+
+    let structure = bincode::serialize(&(self.wire_count(), &gates, &public_inputs, &self.modulus, self.comparison_bits))
+      .expect("Unable to serialize circuit structure");
+
+    BigInt::from_bytes_be(num_bigint::Sign::Plus, &Sha256::digest(&structure))
+  }
+
+  /// The circuit hash a proof carries: `hash_structure`, or with a context, SHA-256 over the structure hash and the context string.
+  /// Proofs without a context keep the plain structure hash, so they are unaffected by this binding.
+  fn context_hash(&self, context: Option<&str>) -> BigInt {
+    let structure_hash = self.hash_structure();
+    match context {
+      Some(context) => {
+        let data = bincode::serialize(&(&structure_hash, context)).expect("Unable to serialize proof context");
+        BigInt::from_bytes_be(num_bigint::Sign::Plus, &Sha256::digest(&data))
+      }
+      None => structure_hash
+    }
+  }
+
+  /**
+   * Verifies the proof in `proof_file` against this circuit, see `verify_proof_detailed` for what is checked.
+   *
+   * # Errors
+   * - `ZkMockError::Io` if the proof file cannot be read;
+   * - `ZkMockError::MalformedProof` if it does not decode as a proof (e.g. it is empty or lacks the proof header);
+   * - `ZkMockError::UnsupportedProofVersion` if it was written in another proof format version;
+   * - the `validate` errors, e.g. `ZkMockError::MissingHashFunction`.
+   */
+  pub fn verify_proof(&self, proof_file: &str) -> Result<bool, ZkMockError> {
+    Ok(self.verify_proof_detailed(proof_file)?.valid)
+  }
+
+  /**
+   * Verifies a proof and reports what was checked.
+   *
+   * Besides the prover's recorded verdict, the verifier:
+   * 1. compares the proof's circuit hash against its own circuit's hash;
+   * 2. decodes the R1CS stored in the proof and checks it has its own R1CS's constraints and public values,
+   *    and that every public variable it lists exists and holds a value (those make up the report's `public_inputs`);
+   * 3. compares the proof's public input vector with its own public input values, so a proof made for other public inputs fails,
+   *    or, if the circuit commits to its public inputs, requires an empty vector and its own public input root instead;
+   * 4. checks that the proof names exactly its own named outputs, each with the value its wire holds in the stored witness;
+   * 5. re-derives the Fiat-Shamir challenge from the proof's transcript and checks the proof carries it (see `Proof::fiat_shamir_transcript`);
+   * 6. re-checks every constraint of the stored R1CS over the witness it carries, recording the outcome of each one.
+   *
+   * The report is valid only if all of these hold. Fails if the proof file cannot be read or decoded, or the circuit fails `validate`.
+   */
+  pub fn verify_proof_detailed(&self, proof_file: &str) -> Result<VerificationReport, ZkMockError> {
+    self.verify_in_context(proof_file, None)
+  }
+
+  /// Verifies a proof written by `generate_proof_with_context`; returns false if it was bound to any other context (or to none).
+  /// Fails like `verify_proof`, e.g. with `ZkMockError::MalformedProof` for a file that is not a proof.
+  pub fn verify_proof_with_context(&self, proof_file: &str, context: &str) -> Result<bool, ZkMockError> {
+    Ok(self.verify_in_context(proof_file, Some(context))?.valid)
+  }
+
+  /// Shared body of `verify_proof_detailed` and `verify_proof_with_context`.
+  fn verify_in_context(&self, proof_file: &str, context: Option<&str>) -> Result<VerificationReport, ZkMockError> {
+    self.verify_loaded(&Proof::try_load(proof_file)?, context)
+  }
+
+  /// `verify_in_context` over an already loaded proof, for verifiers that go on to check more of it.
+  fn verify_loaded(&self, proof: &Proof, context: Option<&str>) -> Result<VerificationReport, ZkMockError> {
+    self.validate()?;
+    let r1cs = R1CS::from_bytes(&proof.r1cs).map_err(|error| ZkMockError::MalformedProof(error.to_string()))?;
+    let apply_hash = |a: &BigInt, b: &BigInt| self.apply_hash(a, b);
+    let apply_hash3 = |a: &BigInt, b: &BigInt, c: &BigInt| self.apply_hash3(a, b, c);
+    let constraint_results: Vec<_> = r1cs.constraints.iter()
+      .map(|constraint| constraint.check_with_hash3(&r1cs.variables, r1cs.modulus.as_ref(), &apply_hash, &apply_hash3))
+      .collect();
+
+    let circuit_hash_matches = proof.circuit_hash == self.context_hash(context);
+    let statement_matches = self.to_r1cs().same_statement(&r1cs);
+    // The root is the verifier's own, so it commits to the public values `same_statement` compares with the witness
+    let public_inputs_match = if self.commit_public_inputs {
+      proof.public_inputs.is_empty() && proof.public_inputs_root.is_some() && proof.public_inputs_root == self.public_inputs_root()
+    } else {
+      proof.public_inputs == self.public_input_values() && proof.public_inputs_root.is_none()
+    };
+    // Each named output must be the value its wire holds in the witness, and the proof may name no other outputs
+    let outputs_match = proof.outputs.len() == self.named_outputs.len()
+      && self.named_outputs.iter().all(|(name, wire)| {
+        proof.outputs.get(name).is_some_and(|value| r1cs.variables.get(*wire).and_then(|variable| variable.value.as_ref()) == Some(value))
+      });
+    // A public index past the variables, or one without a value, can only come from a crafted proof
+    let public_inputs: Vec<(usize, BigInt)> = r1cs.public.iter()
+      .filter_map(|&index| Some((index, r1cs.variables.get(index)?.value.clone()?)))
+      .collect();
+    let challenge_matches = proof.challenge_matches();
+    let valid = proof.valid && circuit_hash_matches && statement_matches && public_inputs_match && outputs_match && challenge_matches
+      && public_inputs.len() == r1cs.public.len()
+      && constraint_results.iter().all(|result| result.is_ok());
+    Ok(VerificationReport {
+      valid,
+      claimed_valid: proof.valid,
+      circuit_hash: proof.circuit_hash.clone(),
+      circuit_hash_matches,
+      statement_matches,
+      public_inputs_match,
+      outputs_match,
+      challenge_matches,
+      constraint_results,
+      public_inputs
+    })
+  }
+
+  /**
+   * Verifies a proof like `verify_proof_detailed`, reusing the result stored in `cache` when there is one.
+   *
+   * The cache key is SHA-256 over the proof file's bytes, this circuit's `to_bytes` encoding and its hash function,
+   * so a changed proof, a changed circuit (gates or input values) or another hash function misses and is verified afresh.
+   * The hash function enters as its `HashFunction::identity`, which also tells apart differently parameterized
+   * instances of one type, e.g. `ModSquareHash`es over different moduli.
+   */
+  pub fn verify_proof_cached(&self, proof_file: &str, cache: &VerificationCache) -> Result<bool, ZkMockError> {
+    let mut hasher = Sha256::new();
+    hasher.update(std::fs::read(proof_file)?);
+    hasher.update(self.to_bytes());
+    if let Some(hash_function) = &self.hash_function {
+      hasher.update(hash_function.identity());
+    }
+    let key = hasher.finalize();
+
+    if let Some(valid) = cache.get(&key) {
+      return Ok(valid);
+    }
+    let valid = self.verify_proof_detailed(proof_file)?.valid;
+    cache.insert(&key, valid)?;
+    Ok(valid)
+  }
+
+  /// Verifies the proof like `verify_proof_detailed` and, if it is valid, returns its named outputs keyed by name.
+  ///
+  /// # Errors
+  /// The `verify_proof` errors, e.g. `ZkMockError::Io` if the proof file cannot be read.
+  pub fn verify_proof_outputs(&self, proof_file: &str) -> Result<Option<BTreeMap<String, BigInt>>, ZkMockError> {
+    let proof = Proof::try_load(proof_file)?;
+    Ok(if self.verify_loaded(&proof, None)?.valid { Some(proof.outputs) } else { None })
+  }
+
+  /**
+   * Verifies a proof whose public inputs were committed to as a Merkle root.
+   *
+   * The verifier supplies every public input value of the circuit, in order, together with its authentication path
+   * (see `public_input_path`). The proof is accepted only if it passes all the checks of `verify_proof_detailed`,
+   * which bind its root to the public values of the statement, and each supplied value is the statement's value at
+   * its position and folds up to that root from there under the circuit's hash function. A circuit that does not commit
+   * to its public inputs, or has none, has no such root to check, so its proofs are rejected.
+   *
+   * # Errors
+   * The `verify_proof` errors, e.g. `ZkMockError::Io` if the proof file cannot be read.
+   */
+  pub fn verify_proof_with_public_inputs(&self, proof_file: &str, public_inputs: &[(BigInt, Vec<(BigInt, bool)>)]) -> Result<bool, ZkMockError> {
+    let proof = Proof::try_load(proof_file)?;
+    if !self.verify_loaded(&proof, None)?.valid || public_inputs.len() != self.public_inputs.len() {
+      return Ok(false);
+    }
+    let (Some(root), Some(hash_function)) = (&proof.public_inputs_root, self.hash_function.as_deref()) else {
+      return Ok(false);
+    };
+
+    let statement = self.public_input_values();
+    Ok(public_inputs.iter().zip(&statement).enumerate().all(|(position, ((value, path), expected))| {
+      value == expected && MerkleTree::verify_proof_at(root, value, position, public_inputs.len(), path, &hash_function).unwrap_or(false)
+    }))
+  }
+
+  /**
+   * Verifies a proof and checks that the supplied wire values are part of the witness it commits to.
+   *
+   * Each entry is a `WireOpening`: a wire index, its value and its authentication path in the witness tree;
+   * passing every wire checks the whole witness, passing a subset reveals only those wires. The proof is accepted
+   * only if it passes all the checks of `verify_proof_detailed`, carries a witness root equal to the root recomputed
+   * over the wire values of the witness it carries, and each value folds up to that root from its own wire's position,
+   * so the prover cannot commit to, and open, a witness other than the one the constraints were checked on. An empty `wires` reveals nothing and is rejected, and so is a proof of a
+   * circuit without a hash function, which has no witness root to check.
+   *
+   * # Errors
+   * The `verify_proof` errors, e.g. `ZkMockError::Io` if the proof file cannot be read.
+   */
+  pub fn verify_proof_with_witness(&self, proof_file: &str, wires: &[WireOpening]) -> Result<bool, ZkMockError> {
+    let proof = Proof::try_load(proof_file)?;
+    if !self.verify_loaded(&proof, None)?.valid || wires.is_empty() {
+      return Ok(false);
+    }
+    let (Some(root), Some(hash_function)) = (&proof.witness_root, self.hash_function.as_deref()) else {
+      return Ok(false);
+    };
+    // `verify_loaded` checked that the witness matches the statement, so it holds a value for every wire
+    let wire_count = self.wire_count();
+    let r1cs = R1CS::from_bytes(&proof.r1cs).map_err(|error| ZkMockError::MalformedProof(error.to_string()))?;
+    let Some(witness) = r1cs.variables.iter().take(wire_count).map(|variable| variable.value.clone()).collect::<Option<Vec<_>>>() else {
+      return Ok(false);
+    };
+    if witness.iter().any(|value| hash_function.check_input(value).is_err())
+      || self.witness_tree(&witness).map(|tree| tree.root).as_ref() != Some(root) {
+      return Ok(false);
+    }
+
+    Ok(wires.iter().all(|(wire, value, path)| {
+      MerkleTree::verify_proof_at(root, value, *wire, wire_count, path, &hash_function).unwrap_or(false)
+    }))
+  }
+}
+
+/// Appends `value` as an unsigned LEB128 varint: 7 bits per byte, high bit set on every byte but the last.
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+  while value >= 0x80 {
+    bytes.push((value as u8 & 0x7f) | 0x80);
+    value >>= 7;
+  }
+  bytes.push(value as u8);
+}
+
+/// Appends `value` as a varint byte length followed by its signed big-endian bytes.
+fn write_compact_bigint(bytes: &mut Vec<u8>, value: &BigInt) {
+  let encoded = value.to_signed_bytes_be();
+  write_varint(bytes, encoded.len());
+  bytes.extend_from_slice(&encoded);
+}
+
+/// Sequential reader over a circuit written by `Circuit::to_bytes`.
+struct CompactReader<'a> {
+  data: &'a [u8]
+}
+
+impl CompactReader<'_> {
+  fn take(&mut self, len: usize) -> Result<&[u8], ZkMockError> {
+    if self.data.len() < len {
+      return Err(ZkMockError::MalformedCircuit("encoded circuit is truncated".to_string()));
+    }
+    let (head, rest) = self.data.split_at(len);
+    self.data = rest;
+    Ok(head)
+  }
+
+  fn byte(&mut self) -> Result<u8, ZkMockError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn varint(&mut self) -> Result<usize, ZkMockError> {
+    let mut value: usize = 0;
+    for shift in (0..usize::BITS).step_by(7) {
+      let byte = self.byte()?;
+      let bits = usize::from(byte & 0x7f);
+      if (bits << shift) >> shift != bits {
+        return Err(ZkMockError::MalformedCircuit("varint overflows usize".to_string()));
+      }
+      value |= bits << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+    }
+    Err(ZkMockError::MalformedCircuit("varint overflows usize".to_string()))
+  }
+
+  fn bigint(&mut self) -> Result<BigInt, ZkMockError> {
+    let len = self.varint()?;
+    Ok(BigInt::from_signed_bytes_be(self.take(len)?))
+  }
+
+  fn string(&mut self) -> Result<String, ZkMockError> {
+    let len = self.varint()?;
+    String::from_utf8(self.take(len)?.to_vec()).map_err(|e| ZkMockError::MalformedCircuit(e.to_string()))
+  }
+
+  /// Reads a count and then that many items, without trusting the count for preallocation.
+  fn list<T, F>(&mut self, mut read: F) -> Result<Vec<T>, ZkMockError>
+  where F: FnMut(&mut Self) -> Result<T, ZkMockError>
+  {
+    let count = self.varint()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+      items.push(read(self)?);
+    }
+    Ok(items)
+  }
+}
+
+// Single-byte tags identifying each gate variant in the compact encoding
+const TAG_ADD: u8 = 0;
+const TAG_MUL: u8 = 1;
+const TAG_HASH: u8 = 2;
+const TAG_COMMIT: u8 = 3;
+const TAG_CONST: u8 = 4;
+const TAG_IS_ZERO: u8 = 5;
+const TAG_MUX: u8 = 6;
+const TAG_POLY: u8 = 7;
+const TAG_SUB: u8 = 8;
+const TAG_LESS_THAN_OR_EQUAL: u8 = 9;
+const TAG_ASSERT: u8 = 10;
+const TAG_LINEAR: u8 = 11;
+const TAG_ASSERT_EQUAL: u8 = 12;
+const TAG_LESS_THAN: u8 = 13;
+const TAG_BOOL: u8 = 14;
+
+// Bits of the flags byte in the compact encoding
+const FLAG_COMMIT_PUBLIC_INPUTS: u8 = 1;
+const FLAG_STRICT: u8 = 1 << 1;
+const FLAG_INCREMENTAL_CHECK: u8 = 1 << 2;
+const FLAG_MODULUS: u8 = 1 << 3;
+const FLAG_COMPARISON_BITS: u8 = 1 << 4;
+
+impl Circuit {
+  /**
+   * Encodes the circuit in a compact binary layout, much smaller than bincode for large circuits.
+   *
+   * Counts, wire indices and lengths are LEB128 varints, values are a varint byte length followed by
+   * their signed big-endian bytes. Layout:
+   * - inputs: count, then each value
+   * - public inputs: count, then each index
+   * - flags byte: commit public inputs, strict, incremental check, modulus present, non-default comparison bits (bits 0 to 4)
+   * - modulus, then comparison bits, each if its flag is set
+   * - gates: count, then per gate a tag byte (Add 0, Mul 1, Hash 2, Commit 3, Const 4, IsZero 5, Mux 6, Poly 7, Sub 8,
+   *   LessThanOrEqual 9, Assert 10, Linear 11, AssertEqual 12, LessThan 13, Bool 14) followed by its wires in declaration
+   *   order, except that:
+   *   - Const appends its value;
+   *   - Mux writes its index, its options as count then indices, then its output;
+   *   - Poly writes its inputs as count then indices, its coefficients as count then values, then its output;
+   *   - Linear writes its terms as count then (input index, coefficient value) pairs, then its output
+   * - outputs: count, then each value
+   * - named outputs: count, then per output the name (varint length, UTF-8 bytes) and the wire
+   * - named inputs: count, then per input the name and the wire, like named outputs
+   *
+   * Like the replay log, the hash function and the replay log itself are not encoded: reattach the hash function with `set_hash_function`.
+   */
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_varint(&mut bytes, self.inputs.len());
+    for input in &self.inputs {
+      write_compact_bigint(&mut bytes, input);
+    }
+    write_varint(&mut bytes, self.public_inputs.len());
+    for &index in &self.public_inputs {
+      write_varint(&mut bytes, index);
+    }
+
+    let mut flags = 0;
+    if self.commit_public_inputs { flags |= FLAG_COMMIT_PUBLIC_INPUTS; }
+    if self.strict { flags |= FLAG_STRICT; }
+    if self.incremental_check { flags |= FLAG_INCREMENTAL_CHECK; }
+    if self.modulus.is_some() { flags |= FLAG_MODULUS; }
+    if self.comparison_bits != DEFAULT_COMPARISON_BITS { flags |= FLAG_COMPARISON_BITS; }
+    bytes.push(flags);
+    if let Some(modulus) = &self.modulus {
+      write_compact_bigint(&mut bytes, modulus);
+    }
+    if self.comparison_bits != DEFAULT_COMPARISON_BITS {
+      write_varint(&mut bytes, self.comparison_bits);
+    }
+
+    write_varint(&mut bytes, self.gates.len());
+    for gate in &self.gates {
+      match gate {
+        Gate::Add(..) => bytes.push(TAG_ADD),
+        Gate::Mul(..) => bytes.push(TAG_MUL),
+        Gate::Hash(..) => bytes.push(TAG_HASH),
+        Gate::Commit(..) => bytes.push(TAG_COMMIT),
+        Gate::Const(..) => bytes.push(TAG_CONST),
+        Gate::IsZero(..) => bytes.push(TAG_IS_ZERO),
+        Gate::Mux(..) => bytes.push(TAG_MUX),
+        Gate::Poly(..) => bytes.push(TAG_POLY),
+        Gate::Sub(..) => bytes.push(TAG_SUB),
+        Gate::LessThanOrEqual(..) => bytes.push(TAG_LESS_THAN_OR_EQUAL),
+        Gate::Assert(..) => bytes.push(TAG_ASSERT),
+        Gate::Linear(..) => bytes.push(TAG_LINEAR),
+        Gate::AssertEqual(..) => bytes.push(TAG_ASSERT_EQUAL),
+        Gate::LessThan(..) => bytes.push(TAG_LESS_THAN),
+        Gate::Bool(..) => bytes.push(TAG_BOOL)
+      }
+      match gate {
+        Gate::Const(wire, value) => {
+          write_varint(&mut bytes, *wire);
+          write_compact_bigint(&mut bytes, value);
+        }
+        Gate::Mux(index, options, output) => {
+          write_varint(&mut bytes, *index);
+          write_varint(&mut bytes, options.len());
+          for &option in options {
+            write_varint(&mut bytes, option);
+          }
+          write_varint(&mut bytes, *output);
+        }
+        Gate::Poly(inputs, coeffs, output) => {
+          write_varint(&mut bytes, inputs.len());
+          for &input in inputs {
+            write_varint(&mut bytes, input);
+          }
+          write_varint(&mut bytes, coeffs.len());
+          for coeff in coeffs {
+            write_compact_bigint(&mut bytes, coeff);
+          }
+          write_varint(&mut bytes, *output);
+        }
+        Gate::Linear(terms, output) => {
+          write_varint(&mut bytes, terms.len());
+          for (input, coeff) in terms {
+            write_varint(&mut bytes, *input);
+            write_compact_bigint(&mut bytes, coeff);
+          }
+          write_varint(&mut bytes, *output);
+        }
+        _ => {
+          for wire in gate.wires() {
+            write_varint(&mut bytes, wire);
+          }
+        }
+      }
+    }
+
+    write_varint(&mut bytes, self.outputs.len());
+    for output in &self.outputs {
+      write_compact_bigint(&mut bytes, output);
+    }
+    write_varint(&mut bytes, self.named_outputs.len());
+    for (name, wire) in &self.named_outputs {
+      write_varint(&mut bytes, name.len());
+      bytes.extend_from_slice(name.as_bytes());
+      write_varint(&mut bytes, *wire);
+    }
+    write_varint(&mut bytes, self.input_names.len());
+    for (name, wire) in &self.input_names {
+      write_varint(&mut bytes, name.len());
+      bytes.extend_from_slice(name.as_bytes());
+      write_varint(&mut bytes, *wire);
+    }
+    bytes
+  }
+
+  /// Decodes a circuit written by `to_bytes`, without a hash function.
+  ///
+  /// # Errors
+  /// - `ZkMockError::MalformedCircuit` if the data is truncated, has an unknown gate tag or trailing bytes, a public input,
+  ///   named output or input name refers to a wire the circuit does not have, or the comparison width exceeds `MAX_COMPARISON_BITS`;
+  /// - `ZkMockError::UndefinedWire` or `ZkMockError::UnallocatedWire` if a gate is wired like `try_add_gate` refuses.
+  pub fn from_bytes(data: &[u8]) -> Result<Circuit, ZkMockError> {
+    let mut reader = CompactReader { data };
+    let mut circuit = Circuit::new(None);
+    circuit.inputs = reader.list(|reader| reader.bigint())?;
+    circuit.public_inputs = reader.list(|reader| reader.varint())?;
+
+    let flags = reader.byte()?;
+    circuit.commit_public_inputs = flags & FLAG_COMMIT_PUBLIC_INPUTS != 0;
+    circuit.strict = flags & FLAG_STRICT != 0;
+    circuit.incremental_check = flags & FLAG_INCREMENTAL_CHECK != 0;
+    if flags & FLAG_MODULUS != 0 {
+      circuit.modulus = Some(reader.bigint()?);
+    }
+    if flags & FLAG_COMPARISON_BITS != 0 {
+      circuit.comparison_bits = reader.varint()?;
+      if circuit.comparison_bits > MAX_COMPARISON_BITS {
+        return Err(ZkMockError::MalformedCircuit(format!("comparison width of {} bits exceeds {}", circuit.comparison_bits, MAX_COMPARISON_BITS)));
+      }
+    }
+
+    circuit.gates = reader.list(|reader| {
+      let tag = reader.byte()?;
+      Ok(match tag {
+        TAG_ADD => Gate::Add(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_MUL => Gate::Mul(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_HASH => Gate::Hash(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_COMMIT => Gate::Commit(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_CONST => Gate::Const(reader.varint()?, reader.bigint()?),
+        TAG_IS_ZERO => Gate::IsZero(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_MUX => Gate::Mux(reader.varint()?, reader.list(|reader| reader.varint())?, reader.varint()?),
+        TAG_SUB => Gate::Sub(reader.varint()?, reader.varint()?, reader.varint()?),
+        TAG_POLY => Gate::Poly(reader.list(|reader| reader.varint())?, reader.list(|reader| reader.bigint())?, reader.varint()?),
+        TAG_LESS_THAN_OR_EQUAL => Gate::LessThanOrEqual(reader.varint()?, reader.varint()?),
+        TAG_ASSERT => Gate::Assert(reader.varint()?),
+        TAG_ASSERT_EQUAL => Gate::AssertEqual(reader.varint()?, reader.varint()?),
+        TAG_LESS_THAN => Gate::LessThan(reader.varint()?, reader.varint()?),
+        TAG_BOOL => Gate::Bool(reader.varint()?),
+        TAG_LINEAR => Gate::Linear(reader.list(|reader| Ok((reader.varint()?, reader.bigint()?)))?, reader.varint()?),
+        unknown => return Err(ZkMockError::MalformedCircuit(format!("unknown gate tag {}", unknown)))
+      })
+    })?;
+
+    circuit.outputs = reader.list(|reader| reader.bigint())?;
+    circuit.named_outputs = reader.list(|reader| Ok((reader.string()?, reader.varint()?)))?;
+    circuit.input_names = reader.list(|reader| Ok((reader.string()?, reader.varint()?)))?;
+
+    if !reader.data.is_empty() {
+      return Err(ZkMockError::MalformedCircuit("trailing bytes after encoded circuit".to_string()));
+    }
+    circuit.check_wiring()?;
+
+    let out_of_range = |what: &str, wire: usize, count: usize| {
+      ZkMockError::MalformedCircuit(format!("{} refers to wire {}, but the circuit has {}", what, wire, count))
+    };
+    let inputs = circuit.inputs.len();
+    if let Some(&wire) = circuit.public_inputs.iter().find(|&&wire| wire >= inputs) {
+      return Err(out_of_range("a public input", wire, inputs));
+    }
+    if let Some((name, wire)) = circuit.input_names.iter().find(|(_, wire)| *wire >= inputs) {
+      return Err(out_of_range(&format!("input name '{}'", name), *wire, inputs));
+    }
+    let wires = circuit.wire_count();
+    if let Some((name, wire)) = circuit.named_outputs.iter().find(|(_, wire)| *wire >= wires) {
+      return Err(out_of_range(&format!("named output '{}'", name), *wire, wires));
+    }
+    Ok(circuit)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use super::*;
+  use crate::transcript::TranscriptEntry;
+
+  // Distinguishes the proof files of tests running concurrently
+  static PROOF_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+  /// Returns a fresh temporary proof file path, removed when the returned guard is dropped.
+  fn proof_file() -> TempProof {
+    TempProof(std::env::temp_dir().join(format!(
+      "zk-mock-circuit-test-{}-{}.bin", std::process::id(), PROOF_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )).to_str().expect("temporary directory path is valid UTF-8").to_string())
+  }
+
+  struct TempProof(String);
+
+  impl TempProof {
+    fn path(&self) -> &str {
+      &self.0
+    }
+  }
+
+  impl Drop for TempProof {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_file(&self.0);
+    }
+  }
+
+  fn int(value: i64) -> BigInt {
+    BigInt::from(value)
+  }
+
+  #[test]
+  fn optimize_removes_redundant_constraints_and_keeps_verifying() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_input(int(99)); // never read by a gate
+    let sum = circuit.add_input(int(7));
+    let expected = circuit.add_input(int(7));
+    circuit.add_gate(Gate::Add(a, b, sum));
+    circuit.add_gate(Gate::AssertEqual(sum, expected));
+    circuit.add_gate(Gate::AssertEqual(expected, sum)); // the same constraint with its operands swapped
+    circuit.add_gate(Gate::AssertEqual(sum, expected));
+    let before = circuit.to_r1cs().constraints.len();
+
+    let report = circuit.optimize();
+    assert_eq!(report, OptimizationReport { constraints_removed: 2, constraints_added: 0, inputs_pruned: 1 });
+    assert!(circuit.to_r1cs().constraints.len() < before);
+    assert_eq!(circuit.gates(), &[Gate::Add(0, 1, 2), Gate::AssertEqual(2, 3)]);
+
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+    assert!(circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn preimage_proof_holds_only_for_the_real_preimage() {
+    let hash = || Box::new(hash_functions::ModSquareHash::default()) as Box<dyn HashFunction>;
+    let output = hash().hash(&int(3), &int(4));
+
+    assert!(Circuit::preimage_proof(hash(), output.clone(), int(3), int(4)).prove().valid);
+    assert!(!Circuit::preimage_proof(hash(), output.clone(), int(5), int(4)).prove().valid);
+    assert!(!Circuit::preimage_proof(hash(), output, int(3), int(5)).prove().valid);
+  }
+
+  #[test]
+  fn explanation_of_an_addition_walks_through_the_satisfied_gate() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+
+    let explanation = circuit.explain_proof();
+    assert!(explanation.contains("input[0] = 10"));
+    assert!(explanation.contains("input[1] = 20"));
+    assert!(explanation.contains("Add gate computes 10 + 20 = 30"));
+    assert!(explanation.contains("constraint satisfied"));
+    assert!(explanation.contains("1 of 1 constraints satisfied"));
+  }
+
+  #[test]
+  fn json_spec_of_a_multiplication_parses_into_inputs_and_one_mul_gate() {
+    let circuit = Circuit::from_json(r#"{ "inputs": [5, 4, "20"], "gates": [{ "Mul": [0, 1, 2] }], "outputs": [20] }"#).unwrap();
+    assert_eq!(circuit.inputs, vec![int(5), int(4), int(20)]);
+    assert_eq!(circuit.gates(), &[Gate::Mul(0, 1, 2)]);
+    assert_eq!(circuit.outputs, vec![int(20)]);
+    assert!(circuit.hash_function.is_none());
+
+    assert!(Circuit::from_json(r#"{ "hash_function": "poseidon", "inputs": [], "gates": [] }"#).unwrap().hash_function.is_some());
+    assert!(matches!(
+      Circuit::from_json(r#"{ "hash_function": "md5", "inputs": [], "gates": [] }"#),
+      Err(ZkMockError::UnknownHashFunction(name)) if name == "md5"
+    ));
+  }
+
+  #[test]
+  fn public_inputs_verify_against_the_committed_root_only_as_members() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_public_input(int(3));
+    let b = circuit.add_public_input(int(4));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    circuit.set_public_input_commitment(true);
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let members = vec![(int(3), circuit.public_input_path(0)), (int(4), circuit.public_input_path(1))];
+    assert!(circuit.verify_proof_with_public_inputs(file.path(), &members).unwrap());
+
+    let mut non_member = members.clone();
+    non_member[1].0 = int(5);
+    assert!(!circuit.verify_proof_with_public_inputs(file.path(), &non_member).unwrap());
+    let swapped = vec![members[1].clone(), members[0].clone()];
+    assert!(!circuit.verify_proof_with_public_inputs(file.path(), &swapped).unwrap());
+    assert!(!circuit.verify_proof_with_public_inputs(file.path(), &members[..1]).unwrap());
+    assert!(!circuit.verify_proof_with_public_inputs(file.path(), &[]).unwrap());
+    assert!(matches!(circuit.verify_proof_with_public_inputs("/nonexistent/zk-mock.bin", &members), Err(ZkMockError::Io(_))));
+  }
+
+  #[test]
+  fn commit_gate_holds_only_for_the_committed_randomness() {
+    let commit = |randomness: i64| {
+      let hash = hash_functions::ModSquareHash::default();
+      let commitment = hash.hash(&int(42), &int(7));
+      let mut circuit = Circuit::new(Some(Box::new(hash)));
+      let value = circuit.add_input(int(42));
+      let randomness = circuit.add_input(int(randomness));
+      let commitment = circuit.add_public_input(commitment);
+      circuit.add_gate(Gate::Commit(value, randomness, commitment));
+      circuit.prove().valid
+    };
+    assert!(commit(7));
+    assert!(!commit(8));
+  }
+
+  #[test]
+  fn outputs_for_evaluates_an_adder_over_several_input_pairs() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(0));
+    let b = circuit.add_input(int(0));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+
+    for (x, y) in [(1, 2), (10, -4), (1000, 2345)] {
+      assert_eq!(circuit.outputs_for(&[int(x), int(y)]), vec![int(x + y)]);
+    }
+    assert_eq!(circuit.inputs, vec![int(0), int(0), int(0)]);
+  }
+
+  #[test]
+  fn find_unsatisfiable_flags_assert_equal_between_distinct_constants() {
+    let mut circuit = Circuit::new(None);
+    let two = circuit.add_gate_with_output(|wire| Gate::Const(wire, int(2)));
+    let three = circuit.add_gate_with_output(|wire| Gate::Const(wire, int(3)));
+    let also_two = circuit.add_gate_with_output(|wire| Gate::Const(wire, int(2)));
+    circuit.add_gate(Gate::AssertEqual(two, also_two));
+    assert!(circuit.find_unsatisfiable().is_empty());
+
+    circuit.add_gate(Gate::AssertEqual(two, three));
+    assert_eq!(circuit.find_unsatisfiable(), vec![4]);
+  }
+
+  #[test]
+  fn named_outputs_are_read_back_by_name_from_verified_proofs() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    let sum = circuit.add_gate_with_output(|out| Gate::Add(a, b, out));
+    let product = circuit.add_gate_with_output(|out| Gate::Mul(a, b, out));
+    circuit.add_named_output("sum", sum);
+    circuit.add_named_output("product", product);
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let outputs = circuit.verify_proof_outputs(file.path()).unwrap().unwrap();
+    assert_eq!(outputs.get("sum"), Some(&int(7)));
+    assert_eq!(outputs.get("product"), Some(&int(12)));
+
+    // against a different circuit the proof does not verify, so its outputs are withheld
+    let mut other = Circuit::new(None);
+    let a = other.add_input(int(3));
+    let b = other.add_input(int(4));
+    other.add_gate_with_output(|out| Gate::Add(a, b, out));
+    other.add_gate_with_output(|out| Gate::Sub(a, b, out));
+    assert_eq!(other.verify_proof_outputs(file.path()).unwrap(), None);
+    assert!(matches!(circuit.verify_proof_outputs("/nonexistent/zk-mock.bin"), Err(ZkMockError::Io(_))));
+  }
+
+  #[test]
+  fn replaying_a_recorded_log_reconstructs_the_circuit() {
+    let mut circuit = Circuit::new(None);
+    circuit.enable_replay_log();
+    let a = circuit.add_named_input("a", int(6));
+    let b = circuit.add_public_input(int(7));
+    let product = circuit.add_gate_with_output(|out| Gate::Mul(a, b, out));
+    circuit.add_named_output("product", product);
+    circuit.add_output(int(42));
+    circuit.set_modulus(Some(int(101)));
+
+    let log = circuit.replay_log().unwrap();
+    let replayed = Circuit::replay(&log).unwrap();
+    assert_eq!(replayed.to_bytes(), circuit.to_bytes());
+    assert_eq!(replayed.replay_log().unwrap(), log);
+    assert!(matches!(Circuit::replay("not json"), Err(ZkMockError::InvalidReplayLog(_))));
+  }
+
+  #[test]
+  fn verification_report_lists_passing_and_failing_constraints() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_public_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|out| Gate::Mul(a, b, out));
+    let sum = circuit.add_input(int(30));
+    circuit.add_gate(Gate::Add(a, b, sum));
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let report = circuit.verify_proof_detailed(file.path()).unwrap();
+    assert!(report.valid);
+    assert_eq!((report.total_constraints(), report.passed_constraints()), (2, 2));
+    assert_eq!(report.public_inputs, vec![(0, int(10))]);
+    assert_eq!(report.circuit_hash, circuit.hash_structure());
+
+    circuit.inputs[sum] = int(31);
+    circuit.generate_proof(file.path()).unwrap();
+    let report = circuit.verify_proof_detailed(file.path()).unwrap();
+    assert!(!report.valid && !report.claimed_valid);
+    assert_eq!(report.failing_constraints(), vec![1]);
+  }
+
+  #[test]
+  fn verification_report_rejects_crafted_public_indices_without_panicking() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_public_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|out| Gate::Add(a, b, out));
+    let file = proof_file();
+
+    for craft in [|r1cs: &mut R1CS| r1cs.public.push(usize::MAX), |r1cs: &mut R1CS| r1cs.variables[0].value = None] {
+      let mut proof = circuit.prove();
+      let mut r1cs = R1CS::from_bytes(&proof.r1cs).unwrap();
+      craft(&mut r1cs);
+      proof.r1cs = r1cs.to_bytes();
+      proof.save(file.path());
+      assert!(!circuit.verify_proof_detailed(file.path()).unwrap().valid);
+    }
+  }
+
+  #[test]
+  fn is_zero_outputs_one_only_for_zero_and_rejects_inconsistent_witnesses() {
+    let is_zero = |x: i64| {
+      let mut circuit = Circuit::new(None);
+      circuit.set_modulus(Some(int(101)));
+      let x = circuit.add_input(int(x));
+      let output = circuit.add_is_zero(x);
+      (circuit, output)
+    };
+    for (x, expected) in [(0, 1), (5, 0), (-3, 0)] {
+      let (circuit, output) = is_zero(x);
+      assert_eq!(circuit.inputs[output], int(expected));
+      assert!(circuit.prove().valid);
+    }
+
+    for (x, lie) in [(5, 1), (0, 0)] {
+      let (mut circuit, output) = is_zero(x);
+      circuit.inputs[output] = int(lie);
+      assert!(!circuit.prove().valid);
+    }
+  }
+
+  #[test]
+  fn set_inputs_from_requires_one_value_per_input() {
+    let mut circuit = Circuit::from_json(r#"{ "inputs": [0, 0, 0], "gates": [{ "Add": [0, 1, 2] }] }"#).unwrap();
+    let values = read_input_values("2\n 3 \n\n5\n".as_bytes()).unwrap();
+    circuit.set_inputs_from(&values).unwrap();
+    assert_eq!(circuit.inputs, vec![int(2), int(3), int(5)]);
+    assert!(circuit.prove().valid);
+
+    assert!(matches!(circuit.set_inputs_from(&[int(1), int(2)]), Err(ZkMockError::InputCountMismatch { expected: 3, found: 2 })));
+    assert_eq!(circuit.inputs, vec![int(2), int(3), int(5)]);
+  }
+
+  #[test]
+  fn empty_circuit_is_refused_in_strict_mode_and_warned_about_otherwise() {
+    let mut circuit = Circuit::new(None);
+    circuit.add_input(int(1));
+    let proof = circuit.try_prove().unwrap();
+    assert!(proof.valid);
+    assert_eq!(proof.constraint_count(), 0);
+    assert_eq!(proof.warnings(), [ZkMockError::EmptyCircuit.to_string()]);
+
+    circuit.set_strict(true);
+    assert!(matches!(circuit.try_prove(), Err(ZkMockError::EmptyCircuit)));
+
+    circuit.set_strict(false);
+    circuit.add_gate(Gate::Bool(0));
+    assert!(circuit.try_prove().unwrap().warnings().is_empty());
+  }
+
+  #[test]
+  fn assert_deterministic_accepts_a_deterministic_circuit() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|out| Gate::Hash(a, b, out));
+    circuit.assert_deterministic();
+  }
+
+  // Mixes a call counter into every hash, so no two proofs come out the same
+  struct CountingHash(AtomicUsize);
+
+  impl HashFunction for CountingHash {
+    fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+      a + b + self.0.fetch_add(1, Ordering::Relaxed)
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "not deterministic")]
+  fn assert_deterministic_catches_a_nondeterministic_hash() {
+    let mut circuit = Circuit::new(Some(Box::new(CountingHash(AtomicUsize::new(0)))));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|out| Gate::Hash(a, b, out));
+    circuit.assert_deterministic();
+  }
+
+  #[test]
+  fn structure_hash_follows_gates_but_not_input_values() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    let hash = circuit.hash_structure();
+    assert_eq!(circuit.hash_structure(), hash);
+
+    circuit.inputs[a] = int(30);
+    assert_eq!(circuit.hash_structure(), hash);
+
+    circuit.add_gate_with_output(|product| Gate::Mul(a, b, product));
+    assert_ne!(circuit.hash_structure(), hash);
+  }
+
+  #[test]
+  fn proof_verifies_against_an_independently_built_identical_circuit() {
+    let mut prover = Circuit::new(None);
+    let a = prover.add_input(int(3));
+    let b = prover.add_input(int(4));
+    let c = prover.add_input(int(5));
+    let product = prover.add_gate_with_output(|product| Gate::Mul(a, b, product));
+    prover.add_gate_with_output(|sum| Gate::Add(product, c, sum));
+    let file = proof_file();
+    prover.generate_proof(file.path()).unwrap();
+
+    // the verifier parses the same structure from a spec and knows none of the private values
+    let verifier = Circuit::from_json(r#"{ "inputs": [0, 0, 0], "gates": [{ "Mul": [0, 1, 3] }, { "Add": [3, 2, 4] }] }"#).unwrap();
+    assert_eq!(verifier.hash_structure(), prover.hash_structure());
+    assert!(verifier.verify_proof(file.path()).unwrap());
+
+    let different = Circuit::from_json(r#"{ "inputs": [0, 0, 0], "gates": [{ "Add": [0, 1, 3] }, { "Add": [2, 3, 4] }] }"#).unwrap();
+    assert!(!different.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn context_bound_proof_only_verifies_under_its_context() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    let file = proof_file();
+    assert!(circuit.generate_proof_with_context(file.path(), "deposit_1").unwrap().valid);
+
+    assert!(circuit.verify_proof_with_context(file.path(), "deposit_1").unwrap());
+    assert!(!circuit.verify_proof_with_context(file.path(), "deposit_2").unwrap());
+    assert!(!circuit.verify_proof(file.path()).unwrap());
+
+    std::fs::write(file.path(), b"not a proof").unwrap();
+    assert!(circuit.verify_proof_with_context(file.path(), "deposit_1").is_err());
+  }
+
+  #[test]
+  fn verify_circuit_proof_checks_the_spec_against_the_proof() {
+    let spec = r#"{ "inputs": [5, 4, 20], "gates": [{ "Mul": [0, 1, 2] }] }"#;
+    let file = proof_file();
+    Circuit::from_json(spec).unwrap().generate_proof(file.path()).unwrap();
+    assert!(verify_circuit_proof(spec, file.path()).unwrap());
+
+    let other = r#"{ "inputs": [5, 4, 9], "gates": [{ "Add": [0, 1, 2] }] }"#;
+    assert!(!verify_circuit_proof(other, file.path()).unwrap());
+    assert!(matches!(verify_circuit_proof("{", file.path()), Err(ZkMockError::InvalidSpec(_))));
+  }
+
+  #[test]
+  fn mux_selects_the_indexed_option_and_rejects_out_of_range_indices() {
+    let mux = |index: i64| {
+      let mut circuit = Circuit::new(None);
+      let options = vec![circuit.add_input(int(10)), circuit.add_input(int(20)), circuit.add_input(int(30))];
+      let index = circuit.add_input(int(index));
+      let output = circuit.add_gate_with_output(|output| Gate::Mux(index, options, output));
+      (circuit, output)
+    };
+
+    let (circuit, output) = mux(2);
+    assert_eq!(circuit.inputs[output], int(30));
+    assert!(circuit.prove().valid);
+
+    let (mut circuit, output) = mux(1);
+    circuit.inputs[output] = int(30); // claims the third option for index 1
+    assert!(!circuit.prove().valid);
+
+    let (circuit, _) = mux(3);
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn hash_gate_without_hash_function_is_reported_with_its_index() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    circuit.add_input(int(0));
+    circuit.gates.push(Gate::Hash(a, b, 3));
+
+    let file = proof_file();
+    let error = circuit.generate_proof(file.path()).unwrap_err();
+    assert!(matches!(error, ZkMockError::MissingHashFunction { gate: 1 }));
+    assert!(error.to_string().contains("Gate 1"));
+    assert!(!std::path::Path::new(file.path()).exists());
+  }
+
+  #[test]
+  fn incremental_check_rejects_a_gate_with_a_wrong_output() {
+    let mut circuit = Circuit::new(None);
+    circuit.set_incremental_check(true);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    let wrong = circuit.add_input(int(8));
+    let right = circuit.add_input(int(7));
+
+    let result = circuit.try_add_gate(Gate::Add(a, b, wrong));
+    assert!(matches!(result, Err(ZkMockError::UnsatisfiedGate { gate: 0, ref expected, ref found }) if *expected == int(7) && *found == int(8)));
+    assert!(circuit.gates.is_empty());
+    circuit.try_add_gate(Gate::Add(a, b, right)).unwrap();
+
+    // without the check the mistake only shows when proving
+    circuit.set_incremental_check(false);
+    circuit.try_add_gate(Gate::Add(a, b, wrong)).unwrap();
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn compact_bytes_round_trip_every_gate_and_beat_bincode() {
+    let mut circuit = Circuit::new(None);
+    for value in [3, 4, 7, 12, 1, 0, 1, -5] {
+      circuit.add_input(int(value));
+    }
+    circuit.public_inputs.push(2);
+    circuit.gates = vec![
+      Gate::Add(0, 1, 2), Gate::Mul(0, 1, 3), Gate::Hash(0, 1, 8), Gate::Commit(0, 1, 9), Gate::Const(4, int(1)),
+      Gate::IsZero(5, 6, 10), Gate::Mux(4, vec![0, 1], 11), Gate::Poly(vec![4, 0], vec![int(2), int(-3)], 7),
+      Gate::Sub(2, 1, 12), Gate::LessThanOrEqual(0, 1), Gate::Assert(4), Gate::Linear(vec![(0, int(4)), (1, int(-1))], 13),
+      Gate::AssertEqual(4, 6), Gate::LessThan(0, 1), Gate::Bool(6)
+    ];
+    circuit.add_named_output("sum", 2);
+
+    let bytes = circuit.to_bytes();
+    let decoded = Circuit::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.gates, circuit.gates);
+    assert_eq!(decoded.inputs, circuit.inputs);
+    assert_eq!(decoded.public_inputs, circuit.public_inputs);
+    assert_eq!(decoded.to_bytes(), bytes);
+
+    let bincode = bincode::serialize(&(&circuit.inputs, &circuit.public_inputs, &circuit.gates, &circuit.named_outputs)).unwrap();
+    assert!(bytes.len() < bincode.len(), "{} >= {}", bytes.len(), bincode.len());
+  }
+
+  #[test]
+  fn dry_run_flags_a_declared_output_the_gates_do_not_compute() {
+    let spec = |output: i64| format!(r#"{{ "inputs": [3, 4, 5], "gates": [{{ "Mul": [0, 1, 3] }}, {{ "Add": [3, 2, 4] }}], "outputs": [{}] }}"#, output);
+    assert!(Circuit::from_json(&spec(17)).unwrap().dry_run().is_consistent());
+
+    let report = Circuit::from_json(&spec(18)).unwrap().dry_run();
+    assert!(!report.is_consistent());
+    let mismatches = report.mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!((mismatches[0].wire, &mismatches[0].computed, &mismatches[0].expected), (4, &int(17), &Some(int(18))));
+  }
+
+  #[test]
+  fn poly_gate_evaluates_three_x_squared_plus_two_x_plus_one() {
+    let mut circuit = Circuit::new(None);
+    let x = circuit.add_input(int(2));
+    // input i is raised to the power i, so passing x three times gives 1 + 2x + 3x^2
+    let output = circuit.add_gate_with_output(|out| Gate::Poly(vec![x, x, x], vec![int(1), int(2), int(3)], out));
+    assert_eq!(circuit.inputs[output], int(17));
+    assert!(circuit.prove().valid);
+
+    circuit.inputs[output] = int(18);
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn poly_gate_chains_each_power_of_a_wire_once() {
+    let mut circuit = Circuit::new(None);
+    let x = circuit.add_input(int(3));
+    let output = circuit.add_gate_with_output(|out| Gate::Poly(vec![x; 6], vec![int(1); 6], out));
+    assert_eq!(circuit.inputs[output], int(1 + 3 + 9 + 27 + 81 + 243));
+    assert!(circuit.prove().valid);
+
+    // x^2 .. x^5 take one Mul constraint each, not x^i's i - 1 per term
+    let r1cs = circuit.to_r1cs();
+    assert_eq!(r1cs.constraints.iter().filter(|constraint| matches!(constraint.operation, Operation::Mul)).count(), 4);
+  }
+
+  #[test]
+  fn verification_cache_hits_an_unchanged_proof_and_misses_a_modified_one() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("zk-mock-cache-test-{}", std::process::id()));
+    let cache = VerificationCache::open(&dir).unwrap();
+    assert!(circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    assert!(circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+    let mut proof = Proof::load(file.path());
+    proof.valid = false;
+    proof.save(file.path());
+    assert!(!circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn sub_gate_proves_only_the_right_difference() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(20));
+    let b = circuit.add_input(int(8));
+    let difference = circuit.add_gate_with_output(|out| Gate::Sub(a, b, out));
+    assert_eq!(circuit.inputs[difference], int(12));
+    assert!(circuit.prove().valid);
+
+    circuit.inputs[difference] = int(13);
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn budget_limits_inputs_and_gates() {
+    let mut circuit = Circuit::new(None);
+    circuit.set_budget(Some(CircuitBudget { max_inputs: 3, max_gates: 1 }));
+    let a = circuit.try_add_input(int(1)).unwrap();
+    let b = circuit.try_add_input(int(2)).unwrap();
+    let sum = circuit.try_add_input(int(3)).unwrap();
+    assert!(matches!(circuit.try_add_input(int(4)), Err(ZkMockError::BudgetExceeded { resource: "inputs", limit: 3 })));
+
+    circuit.try_add_gate(Gate::Add(a, b, sum)).unwrap();
+    assert!(matches!(circuit.try_add_gate(Gate::Add(b, a, sum)), Err(ZkMockError::BudgetExceeded { resource: "gates", limit: 1 })));
+    assert_eq!((circuit.inputs.len(), circuit.gates.len()), (3, 1));
+  }
+
+  #[test]
+  fn sorted_check_fails_exactly_at_the_out_of_order_pair() {
+    let sorted = |values: [i64; 4]| {
+      let mut circuit = Circuit::new(None);
+      let wires: Vec<usize> = values.iter().map(|&value| circuit.add_input(int(value))).collect();
+      circuit.add_sorted_check(&wires);
+      circuit
+    };
+
+    let circuit = sorted([1, 3, 3, 7]);
+    assert_eq!(circuit.gate_count(), 3);
+    assert!(circuit.prove().valid);
+
+    let circuit = sorted([1, 5, 3, 7]);
+    assert_eq!(circuit.outputs_for(&circuit.inputs), vec![int(1), int(0), int(1)]);
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn assert_gate_requires_its_wire_to_hold_one() {
+    let assert_on = |value: i64| {
+      let mut circuit = Circuit::new(None);
+      let wire = circuit.add_input(int(value));
+      circuit.set_incremental_check(true);
+      let result = circuit.try_add_gate(Gate::Assert(wire));
+      (circuit, result)
+    };
+
+    let (circuit, result) = assert_on(1);
+    result.unwrap();
+    assert!(circuit.prove().valid);
+
+    let (mut circuit, result) = assert_on(0);
+    let error = result.unwrap_err();
+    assert!(matches!(error, ZkMockError::AssertionFailed { gate: 0, .. }));
+    assert_eq!(error.to_string(), "Gate 0 asserts input[0] == 1 (input[0] = 0), which does not hold");
+
+    circuit.set_incremental_check(false);
+    circuit.add_gate(Gate::Assert(0));
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn addition_output_is_derived_from_its_two_inputs() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    let sum = circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    assert_eq!(circuit.inputs[sum], int(30));
+    assert!(circuit.prove().valid);
+
+    // compute_witness re-derives it after the inputs change
+    circuit.inputs[a] = int(15);
+    circuit.compute_witness();
+    assert_eq!(circuit.inputs[sum], int(35));
+    assert!(circuit.prove().valid);
+  }
+
+  #[test]
+  fn verifying_an_empty_file_returns_an_error() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+
+    let file = proof_file();
+    std::fs::write(file.path(), b"").unwrap();
+    assert!(matches!(circuit.verify_proof(file.path()), Err(ZkMockError::MalformedProof(_))));
+    std::fs::remove_file(file.path()).unwrap();
+    assert!(matches!(circuit.verify_proof(file.path()), Err(ZkMockError::Io(_))));
+  }
+
+  #[test]
+  fn linear_gate_proves_two_a_plus_three_b() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(4));
+    let b = circuit.add_input(int(5));
+    let c = circuit.add_gate_with_output(|c| Gate::Linear(vec![(a, int(2)), (b, int(3))], c));
+    assert_eq!(circuit.inputs[c], int(23));
+    assert!(circuit.prove().valid);
+
+    circuit.inputs[c] = int(22);
+    assert!(!circuit.prove().valid);
+  }
+
+  // Records what a backend would be asked to build
+  #[derive(Default)]
+  struct RecordingSynthesizer {
+    variables: Vec<(usize, bool)>,
+    constraints: Vec<(usize, Vec<u8>)>
+  }
+
+  impl ConstraintSynthesizer for RecordingSynthesizer {
+    fn alloc(&mut self, variable: &Variable, public: bool) {
+      self.variables.push((variable.index, public));
+    }
+
+    fn enforce(&mut self, gate: usize, constraint: &r1cs::Constraint) {
+      self.constraints.push((gate, bincode::serialize(constraint).unwrap()));
+    }
+  }
+
+  #[test]
+  fn synthesize_emits_the_constraints_of_every_gate_in_order() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_public_input(int(4));
+    let sum = circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    circuit.add_gate_with_output(|product| Gate::Mul(sum, b, product));
+
+    let mut synthesizer = RecordingSynthesizer::default();
+    circuit.synthesize(&mut synthesizer);
+    assert_eq!(synthesizer.variables, vec![(0, false), (1, true), (2, false), (3, false)]);
+
+    let r1cs = circuit.to_r1cs();
+    let expected: Vec<(usize, Vec<u8>)> = r1cs.constraints.iter().enumerate()
+      .map(|(gate, constraint)| (gate, bincode::serialize(constraint).unwrap()))
+      .collect();
+    assert_eq!(synthesizer.constraints, expected);
+  }
+
+  #[test]
+  fn gates_wired_past_the_allocated_wires_are_rejected() {
+    let mut circuit = Circuit::new(None);
+    for value in [1, 2, 3] {
+      circuit.add_input(int(value));
+    }
+    assert!(matches!(circuit.try_add_gate(Gate::Add(0, 1, 99)), Err(ZkMockError::UnallocatedWire { gate: 0, wire: 99, next: 3 })));
+    assert!(matches!(circuit.try_add_gate(Gate::Add(0, 99, 2)), Err(ZkMockError::UndefinedWire { gate: 0, wire: 99 })));
+    assert!(matches!(circuit.try_add_gate(Gate::Mul(0, 1, usize::MAX)), Err(ZkMockError::UnallocatedWire { wire: usize::MAX, .. })));
+    assert!(circuit.gates.is_empty());
+    circuit.try_add_gate(Gate::Add(0, 1, 3)).unwrap();
+
+    // gates that bypass try_add_gate are caught before proving
+    circuit.gates.push(Gate::Add(0, 3, 99));
+    let error = circuit.try_prove().unwrap_err();
+    assert_eq!(error.to_string(), "Gate 1 writes wire 99, but the next unallocated wire is 4");
+
+    let spec = r#"{ "inputs": [1, 2, 3], "gates": [{ "Add": [0, 1, 99] }] }"#;
+    assert!(matches!(Circuit::from_json(spec), Err(ZkMockError::UnallocatedWire { gate: 0, wire: 99, next: 3 })));
+  }
+
+  #[test]
+  fn shared_circuit_verifies_proofs_from_several_threads() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|out| Gate::Hash(a, b, out));
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let circuit = std::sync::Arc::new(circuit);
+    let threads: Vec<_> = (0..4).map(|_| {
+      let (circuit, path) = (circuit.clone(), file.path().to_string());
+      std::thread::spawn(move || circuit.verify_proof(&path).unwrap())
+    }).collect();
+    for thread in threads {
+      assert!(thread.join().unwrap());
+    }
+  }
+
+  #[test]
+  fn assert_equal_passes_for_equal_inputs_only() {
+    let circuit = |a: i64, b: i64| {
+      let mut circuit = Circuit::new(None);
+      let a = circuit.add_input(int(a));
+      let b = circuit.add_input(int(b));
+      circuit.add_gate(Gate::AssertEqual(a, b));
+      circuit
+    };
+    assert!(circuit(7, 7).prove().valid);
+    assert!(!circuit(7, 8).prove().valid);
+  }
+
+  #[test]
+  fn witness_commitment_covers_every_wire() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    let sum = circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    let commitment = circuit.witness_commitment();
+    for wire in [a, b, sum] {
+      let mut changed = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+      changed.inputs = circuit.inputs.clone();
+      changed.gates = circuit.gates.clone();
+      changed.inputs[wire] += 1;
+      assert_ne!(changed.witness_commitment(), commitment, "wire {}", wire);
+    }
+
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+    let opening = |wire: usize| (wire, circuit.inputs[wire].clone(), circuit.witness_path(wire));
+    assert!(circuit.verify_proof_with_witness(file.path(), &[opening(a), opening(b), opening(sum)]).unwrap());
+    assert!(circuit.verify_proof_with_witness(file.path(), &[opening(sum)]).unwrap());
+
+    let (_, value, path) = opening(a);
+    assert!(!circuit.verify_proof_with_witness(file.path(), &[(b, value.clone(), path.clone())]).unwrap());
+    assert!(!circuit.verify_proof_with_witness(file.path(), &[(a, value + 1, path)]).unwrap());
+    assert!(!circuit.verify_proof_with_witness(file.path(), &[]).unwrap());
+  }
+
+  #[test]
+  fn prove_with_witness_checks_the_supplied_witness() {
+    let mut circuit = Circuit::new(None);
+    let x = circuit.add_public_input(int(12));
+    let a = circuit.add_private_input(int(0));
+    let b = circuit.add_private_input(int(0));
+    circuit.add_gate(Gate::Mul(a, b, x));
+
+    let file = proof_file();
+    circuit.prove_with_witness(&[int(3), int(4)]).unwrap().save(file.path());
+    assert!(circuit.verify_proof(file.path()).unwrap());
+    circuit.prove_with_witness(&[int(3), int(5)]).unwrap().save(file.path());
+    assert!(!circuit.verify_proof(file.path()).unwrap());
+    assert!(matches!(circuit.prove_with_witness(&[int(3)]), Err(ZkMockError::InputCountMismatch { expected: 2, found: 1 })));
+  }
+
+  #[test]
+  fn from_json_rejects_duplicate_public_inputs() {
+    let spec = r#"{"inputs": [1, 2], "public_inputs": [0, 0], "gates": []}"#;
+    assert!(matches!(Circuit::from_json(spec), Err(ZkMockError::InvalidSpec(reason)) if reason.contains("listed twice")));
+  }
+
+  #[test]
+  fn json_round_trip_of_the_addition_circuit() {
+    let mut circuit = Circuit::new(None);
+    let input1 = circuit.add_input(int(10));
+    let input2 = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|output_index| Gate::Add(input1, input2, output_index));
+    circuit.add_output(int(30));
+
+    let json = circuit.to_json();
+    let loaded = Circuit::from_json(&json).unwrap();
+    assert_eq!(loaded.gates(), circuit.gates());
+    assert_eq!(loaded.inputs, circuit.inputs);
+    assert_eq!(loaded.outputs, circuit.outputs);
+    assert_eq!(loaded.to_json(), json);
+
+    let file = proof_file();
+    loaded.generate_proof(file.path()).unwrap();
+    assert!(circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn strict_mode_writes_no_proof_of_a_false_statement() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(2));
+    let b = circuit.add_input(int(3));
+    let wrong = circuit.add_input(int(7));
+    circuit.add_gate(Gate::Add(a, b, wrong));
+    circuit.set_strict(true);
+
+    let file = proof_file();
+    assert!(matches!(circuit.generate_proof(file.path()), Err(ZkMockError::UnsatisfiedCircuit { .. })));
+    assert!(!std::path::Path::new(file.path()).exists());
+  }
+
+  #[test]
+  fn proof_verifies_against_the_circuit_built_in_another_gate_order() {
+    let build = |swapped: bool| {
+      let mut circuit = Circuit::new(None);
+      let wires: Vec<usize> = [2, 3, 5, 7, 5, 35].into_iter().map(|value| circuit.add_input(int(value))).collect();
+      if swapped {
+        circuit.add_gate(Gate::Mul(wires[3], wires[2], wires[5]));
+        circuit.add_gate(Gate::Add(wires[1], wires[0], wires[4]));
+      } else {
+        circuit.add_gate(Gate::Add(wires[0], wires[1], wires[4]));
+        circuit.add_gate(Gate::Mul(wires[2], wires[3], wires[5]));
+      }
+      circuit
+    };
+    let file = proof_file();
+    build(false).generate_proof(file.path()).unwrap();
+    assert!(build(true).verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn corrupting_a_value_byte_fails_verification() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(0xDEADBEEF));
+    let b = circuit.add_input(int(1));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+    assert!(circuit.verify_proof(file.path()).unwrap());
+
+    let mut bytes = std::fs::read(file.path()).unwrap();
+    let value = bytes.windows(4).position(|window| window == [0xEF, 0xBE, 0xAD, 0xDE]).expect("the value is in the proof");
+    bytes[value] ^= 1;
+    std::fs::write(file.path(), &bytes).unwrap();
+    assert!(!circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn less_than_at_eight_bits() {
+    let compare = |a: i64, b: i64| {
+      let mut circuit = Circuit::new(None);
+      circuit.set_comparison_bits(8);
+      let a = circuit.add_input(int(a));
+      let b = circuit.add_input(int(b));
+      circuit.add_gate(Gate::LessThan(a, b));
+      let file = proof_file();
+      let valid = circuit.generate_proof(file.path()).unwrap().valid;
+      assert_eq!(circuit.verify_proof(file.path()).unwrap(), valid);
+      valid
+    };
+    assert!(compare(3, 5));
+    assert!(!compare(5, 3));
+    assert!(!compare(3, 3));
+    // 300 does not fit in 8 bits
+    assert!(!compare(3, 300));
+  }
+
+  #[test]
+  fn bool_gate_accepts_only_zero_and_one() {
+    let holds = |value: i64| {
+      let mut circuit = Circuit::new(None);
+      let wire = circuit.add_input(int(value));
+      circuit.add_gate(Gate::Bool(wire));
+      let r1cs = circuit.to_r1cs();
+      assert!(matches!(r1cs.constraints[0].operation, r1cs::Operation::Mul));
+      circuit.prove().valid
+    };
+    assert!(holds(0));
+    assert!(holds(1));
+    assert!(!holds(2));
+  }
+
+  #[test]
+  fn addition_circuit_built_by_name() {
+    let mut circuit = Circuit::new(None);
+    circuit.add_named_input("a", int(10));
+    circuit.add_named_input("b", int(20));
+    circuit.add_named_input("sum", int(30));
+    circuit.add_gate_by_names(&["a", "b", "sum"], |wires| Gate::Add(wires[0], wires[1], wires[2]));
+    circuit.add_output(int(30));
+
+    assert_eq!(circuit.get_input_by_name("b"), Some(&int(20)));
+    assert_eq!(circuit.input_index("sum"), Some(2));
+    let file = proof_file();
+    assert!(circuit.generate_proof(file.path()).unwrap().valid);
+    assert!(circuit.verify_proof(file.path()).unwrap());
+
+    assert!(matches!(circuit.try_add_named_input("a", int(1)), Err(ZkMockError::DuplicateInputName(_))));
+    assert!(matches!(circuit.try_add_gate_by_names(&["a", "c"], |wires| Gate::AssertEqual(wires[0], wires[1])), Err(ZkMockError::UnknownInputName(name)) if name == "c"));
+  }
+
+  #[test]
+  fn stats_report_a_three_gate_chain_as_depth_three() {
+    let mut circuit = Circuit::new(None);
+    let x = circuit.add_public_input(int(2));
+    let y = circuit.add_input(int(3));
+    let sum = circuit.add_gate_with_output(|out| Gate::Add(x, y, out));
+    let product = circuit.add_gate_with_output(|out| Gate::Mul(sum, y, out));
+    let difference = circuit.add_gate_with_output(|out| Gate::Sub(product, x, out));
+    // independent of the chain, so it does not deepen it
+    circuit.add_gate_with_output(|out| Gate::Add(x, y, out));
+    circuit.add_output(int(13));
+    assert_eq!(circuit.get_input(difference), Some(&int(13)));
+
+    let stats = circuit.stats();
+    assert_eq!(stats.depth, 3);
+    assert_eq!(stats.gate_counts.get("Add"), Some(&2));
+    assert_eq!(stats.gate_counts.get("Mul"), Some(&1));
+    assert_eq!(stats.gate_counts.get("Sub"), Some(&1));
+    assert_eq!((stats.public_inputs, stats.outputs), (1, 1));
+  }
+
+  #[test]
+  fn keccak_circuits_reject_wires_they_cannot_hash() {
+    let spec = r#"{"hash_function": "keccak256", "inputs": [-1, 2, 0], "gates": [{"Hash": [0, 1, 2]}]}"#;
+    let circuit = Circuit::from_json(spec).unwrap();
+    assert!(matches!(circuit.validate(), Err(ZkMockError::FieldEncoding(_))));
+    assert!(matches!(circuit.try_prove(), Err(ZkMockError::FieldEncoding(_))));
+    assert!(matches!(circuit.evaluate(), Err(ZkMockError::FieldEncoding(_))));
+
+    // 2 - 5 is negative, and hashing it would panic just the same
+    let spec = r#"{"hash_function": "keccak256", "inputs": [2, 5], "gates": [{"Sub": [0, 1, 2]}, {"Hash": [2, 0, 3]}]}"#;
+    assert!(matches!(Circuit::from_json(spec).unwrap().try_prove(), Err(ZkMockError::FieldEncoding(_))));
+
+    let spec = r#"{"hash_function": "keccak256", "inputs": [1, 2], "gates": [{"Hash": [0, 1, 2]}]}"#;
+    assert!(Circuit::from_json(spec).unwrap().try_prove().unwrap().valid);
+  }
+
+  #[test]
+  fn multiplication_chain_allocates_its_intermediate_wire() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(2));
+    let b = circuit.add_input(int(3));
+    let c = circuit.add_input(int(4));
+    let result = circuit.add_input(int(24));
+    // wire 4 holds a * b without being supplied as an input
+    circuit.add_gate(Gate::Mul(a, b, 4));
+    circuit.add_gate(Gate::Mul(4, c, result));
+
+    let r1cs = circuit.to_r1cs();
+    assert_eq!(r1cs.variables.len(), 5);
+    assert_eq!(r1cs.variables[4].value, Some(int(6)));
+    let file = proof_file();
+    assert!(circuit.generate_proof(file.path()).unwrap().valid);
+    assert!(circuit.verify_proof(file.path()).unwrap());
+
+    circuit.set_inputs_from(&[int(2), int(3), int(4), int(25)]).unwrap();
+    assert!(!circuit.prove().valid);
+  }
+
+  #[test]
+  fn budget_counts_intermediate_wires_as_inputs() {
+    let spec = r#"{"inputs": [2, 3], "gates": [{"Mul": [0, 1, 2]}, {"Mul": [2, 2, 3]}, {"Mul": [3, 3, 4]}]}"#;
+    let budget = CircuitBudget { max_inputs: 4, max_gates: 10 };
+    assert!(matches!(Circuit::from_json_with_budget(spec, budget), Err(ZkMockError::BudgetExceeded { resource: "inputs", limit: 4 })));
+    assert!(Circuit::from_json_with_budget(spec, CircuitBudget { max_inputs: 5, ..budget }).is_ok());
+
+    let mut circuit = Circuit::new(None);
+    circuit.set_budget(Some(CircuitBudget { max_inputs: 3, max_gates: 10 }));
+    let a = circuit.add_input(int(2));
+    let b = circuit.add_input(int(3));
+    circuit.try_add_gate(Gate::Mul(a, b, 2)).unwrap();
+    assert!(matches!(circuit.try_add_gate(Gate::Mul(2, 2, 3)), Err(ZkMockError::BudgetExceeded { resource: "inputs", limit: 3 })));
+    assert!(matches!(circuit.try_add_input(int(1)), Err(ZkMockError::InputAfterIntermediate { wire: 2 })));
+  }
+
+  #[test]
+  fn multiplication_example_evaluates_to_20() {
+    let mut circuit = Circuit::new(None);
+    let input1 = circuit.add_input(int(5));
+    let input2 = circuit.add_input(int(4));
+    let output_index = circuit.add_input(int(20));
+    circuit.add_gate(Gate::Mul(input1, input2, output_index));
+    circuit.add_output(int(20));
+    assert_eq!(circuit.evaluate().unwrap(), vec![int(20)]);
+
+    circuit.gates.push(Gate::Add(input1, 9, 3));
+    assert!(matches!(circuit.evaluate(), Err(ZkMockError::UndefinedWire { gate: 1, wire: 9 })));
+    circuit.gates.pop();
+    circuit.gates.push(Gate::Hash(input1, input2, 3));
+    assert!(matches!(circuit.evaluate(), Err(ZkMockError::MissingHashFunction { gate: 1 })));
+  }
+
+  #[test]
+  fn verifier_with_different_public_inputs_rejects_the_proof() {
+    let circuit = |public: i64, a: i64, b: i64| {
+      let mut circuit = Circuit::new(None);
+      let x = circuit.add_public_input(int(public));
+      let a = circuit.add_private_input(int(a));
+      let b = circuit.add_private_input(int(b));
+      circuit.add_gate(Gate::Mul(a, b, x));
+      circuit
+    };
+    let prover = circuit(12, 3, 4);
+    let file = proof_file();
+    let proof = prover.generate_proof(file.path()).unwrap();
+    assert_eq!(proof.public_inputs, vec![int(12)]);
+
+    // the verifier only knows the public input, its private values are placeholders
+    assert!(circuit(12, 0, 0).verify_proof(file.path()).unwrap());
+    let report = circuit(13, 0, 0).verify_proof_detailed(file.path()).unwrap();
+    assert!(!report.valid && !report.public_inputs_match);
+  }
+
+  #[test]
+  fn forged_named_outputs_are_rejected() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    let sum = circuit.add_gate_with_output(|out| Gate::Add(a, b, out));
+    circuit.add_named_output("sum", sum);
+    let file = proof_file();
+    let proof = circuit.generate_proof(file.path()).unwrap();
+    assert!(circuit.verify_proof_detailed(file.path()).unwrap().outputs_match);
+
+    let mut forged = proof.clone();
+    forged.outputs.insert("sum".to_string(), int(8));
+    forged.save(file.path());
+    let report = circuit.verify_proof_detailed(file.path()).unwrap();
+    assert!(!report.valid && !report.outputs_match);
+    assert_eq!(circuit.verify_proof_outputs(file.path()).unwrap(), None);
+
+    let mut extra = proof;
+    extra.outputs.insert("bonus".to_string(), int(1));
+    extra.save(file.path());
+    assert!(!circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn witness_openings_against_a_forged_root_are_rejected() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    let product = circuit.add_gate_with_output(|out| Gate::Mul(a, b, out));
+    let file = proof_file();
+    let mut proof = circuit.generate_proof(file.path()).unwrap();
+
+    // commit to a fake witness claiming 3 * 4 = 13 and open the product against it
+    let fake = vec![int(3), int(4), int(13)];
+    let fake_tree = MerkleTree::new(fake.clone(), hash_functions::ModSquareHash::default());
+    proof.witness_root = Some(fake_tree.root.clone());
+    proof.challenge = proof.fiat_shamir_transcript().1;
+    proof.save(file.path());
+    assert!(circuit.verify_proof(file.path()).unwrap());
+    let opening = (product, fake[product].clone(), fake_tree.merkle_path(product));
+    assert!(!circuit.verify_proof_with_witness(file.path(), &[opening]).unwrap());
+  }
+
+  #[test]
+  fn committed_public_inputs_are_bound_to_the_statement() {
+    let build = |a: i64, b: i64| {
+      let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+      let a = circuit.add_public_input(int(a));
+      let b = circuit.add_public_input(int(b));
+      circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+      circuit.set_public_input_commitment(true);
+      circuit
+    };
+    let circuit = build(3, 4);
+    let file = proof_file();
+    let mut proof = circuit.generate_proof(file.path()).unwrap();
+    assert!(proof.public_inputs.is_empty());
+    assert_eq!(proof.public_inputs_root, circuit.public_inputs_root());
+    assert!(circuit.verify_proof(file.path()).unwrap());
+    assert!(!build(3, 5).verify_proof(file.path()).unwrap());
+
+    // a root over other values, opened with matching paths, is not the statement's root
+    let forged = build(5, 6);
+    proof.public_inputs_root = forged.public_inputs_root();
+    proof.save(file.path());
+    assert!(!circuit.verify_proof(file.path()).unwrap());
+    let openings = vec![(int(5), forged.public_input_path(0)), (int(6), forged.public_input_path(1))];
+    assert!(!circuit.verify_proof_with_public_inputs(file.path(), &openings).unwrap());
+  }
+
+
+  #[test]
+  fn gate_outputs_are_allocated_past_intermediate_wires() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate(Gate::Mul(a, b, 2)); // intermediate wire 2
+    let sum = circuit.add_gate_with_output(|output| Gate::Add(a, b, output));
+    assert_eq!(sum, 3);
+    assert_eq!(circuit.inputs.len(), 2);
+    assert!(matches!(circuit.try_add_input(int(1)), Err(ZkMockError::InputAfterIntermediate { wire: 2 })));
+
+    circuit.add_output(int(7));
+    let file = proof_file();
+    assert!(circuit.generate_proof(file.path()).unwrap().valid);
+    assert!(circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn a_wire_written_twice_is_rejected() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate(Gate::Mul(a, b, 2));
+    assert!(matches!(circuit.try_add_gate(Gate::Add(a, b, 2)), Err(ZkMockError::WireWrittenTwice { gate: 1, wire: 2, earlier: 0 })));
+    assert!(matches!(circuit.try_add_gate(Gate::IsZero(a, 3, 3)), Err(ZkMockError::WireWrittenTwice { gate: 1, wire: 3, earlier: 1 })));
+    assert_eq!(circuit.gates().len(), 1);
+
+    let spec = r#"{"inputs": [3, 4, 7], "gates": [{"Add": [0, 1, 2]}, {"Add": [1, 0, 2]}]}"#;
+    assert!(matches!(Circuit::from_json(spec), Err(ZkMockError::WireWrittenTwice { gate: 1, wire: 2, earlier: 0 })));
+  }
+
+
+  #[test]
+  fn truncated_or_forged_encodings_are_rejected() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_public_input(int(3));
+    let b = circuit.add_named_input("b", int(4));
+    let sum = circuit.add_gate_with_output(|output| Gate::Add(a, b, output));
+    circuit.add_named_output("sum", sum);
+    let bytes = circuit.to_bytes();
+    assert!(Circuit::from_bytes(&bytes).is_ok());
+    for len in 0..bytes.len() {
+      assert!(matches!(Circuit::from_bytes(&bytes[..len]), Err(ZkMockError::MalformedCircuit(_))), "prefix of {} bytes", len);
+    }
+
+    let forged = |forge: fn(&mut Circuit)| {
+      let mut forged = Circuit::from_bytes(&bytes).unwrap();
+      forge(&mut forged);
+      Circuit::from_bytes(&forged.to_bytes())
+    };
+    let malformed = |result: Result<Circuit, ZkMockError>, expected: &str| {
+      matches!(result, Err(ZkMockError::MalformedCircuit(reason)) if reason.contains(expected))
+    };
+    assert!(malformed(forged(|circuit| circuit.public_inputs.push(7)), "public input"));
+    assert!(malformed(forged(|circuit| circuit.input_names.push(("c".to_string(), 3))), "input name 'c'"));
+    assert!(malformed(forged(|circuit| circuit.named_outputs[0].1 = 9), "named output 'sum'"));
+    assert!(malformed(forged(|circuit| circuit.comparison_bits = 1 << 40), "comparison width"));
+  }
+
+
+  #[test]
+  fn optimize_folds_constants_and_expands_high_degree_gates() {
+    let mut circuit = Circuit::new(None);
+    let x = circuit.add_input(int(2));
+    circuit.add_input(int(99)); // never read by a gate
+    let two = circuit.add_gate_with_output(|output| Gate::Const(output, int(2)));
+    let four = circuit.add_gate_with_output(|output| Gate::Mul(two, two, output));
+    circuit.add_gate(Gate::LessThan(two, four)); // holds on constants
+    let poly = circuit.add_gate_with_output(|output| Gate::Poly(vec![x, x, x, x], vec![int(1), int(2), int(3), int(4)], output));
+    circuit.add_named_output("poly", poly);
+    let before = circuit.constraint_count();
+
+    let report = circuit.optimize();
+    assert!(report.constraints_removed > 0, "{:?}", report);
+    assert_eq!(report.inputs_pruned, 1);
+    assert_eq!(circuit.constraint_count(), before - report.constraints_removed + report.constraints_added);
+    assert!(circuit.constraint_count() < before);
+    assert!(circuit.gates().contains(&Gate::Const(2, int(4))));
+    assert!(!circuit.gates().iter().any(|gate| matches!(gate, Gate::Poly(..) | Gate::LessThan(..))));
+    assert_eq!(circuit.gates().iter().filter(|gate| matches!(gate, Gate::Mul(..))).count(), 2); // x^2 and x^3, chained once
+
+    let file = proof_file();
+    let proof = circuit.generate_proof(file.path()).unwrap();
+    assert!(proof.valid);
+    assert_eq!(proof.outputs.get("poly"), Some(&int(1 + 2 * 2 + 3 * 4 + 4 * 8)));
+    assert!(circuit.verify_proof(file.path()).unwrap());
+  }
+
+  #[test]
+  fn the_challenge_is_carried_in_the_proof_and_re_derived_when_verifying() {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_public_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|output| Gate::Add(a, b, output));
+    let file = proof_file();
+    let mut proof = circuit.generate_proof(file.path()).unwrap();
+    assert_eq!(proof.transcript().entries().last(), Some(&TranscriptEntry::Challenge { label: "challenge".to_string(), value: proof.challenge.clone() }));
+    assert!(circuit.verify_proof_detailed(file.path()).unwrap().challenge_matches);
+
+    proof.challenge += 1;
+    proof.save(file.path());
+    let report = circuit.verify_proof_detailed(file.path()).unwrap();
+    assert!(!report.challenge_matches);
+    assert!(!report.valid);
+  }
+
+  #[test]
+  fn verification_cache_keys_on_the_hash_function() {
+    let mut circuit = Circuit::new(Some(Box::new(hash_functions::ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|out| Gate::Hash(a, b, out));
+    let file = proof_file();
+    circuit.generate_proof(file.path()).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("zk-mock-cache-hash-test-{}", std::process::id()));
+    let cache = VerificationCache::open(&dir).unwrap();
+    assert!(circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    // same proof and circuit bytes, but another hash function type or another modulus: a miss, and rejected
+    circuit.set_hash_function(Some(Box::new(hash_functions::SimpleAddHash { modulus: None })));
+    assert!(!circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    circuit.set_hash_function(Some(Box::new(hash_functions::ModSquareHash::new(int(7)))));
+    assert!(!circuit.verify_proof_cached(file.path(), &cache).unwrap());
+    assert_eq!((cache.hits(), cache.misses()), (0, 3));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,168 @@
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use sha2::{Digest, Sha256};
+use crate::field;
+
+/// A point on a short Weierstrass curve in affine coordinates, or the point at infinity (the group identity).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Point {
+  Infinity,
+  Affine(BigInt, BigInt) // (x, y), both reduced into [0, p)
+}
+
+impl Point {
+  /// Returns the x-coordinate, or None for the point at infinity.
+  pub fn x(&self) -> Option<&BigInt> {
+    match self {
+      Point::Infinity => None,
+      Point::Affine(x, _) => Some(x)
+    }
+  }
+}
+
+/**
+ * A short Weierstrass curve y^2 = x^3 + a*x + b over the prime field of `p`, with the minimal affine arithmetic
+ * (add, double, scalar multiplication) the Pedersen hash needs.
+ *
+ * Every addition inverts a field element, so scalar multiplication costs a few hundred inversions: fine for a
+ * mock, far slower than the projective formulas real libraries use. Nothing here is constant-time.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Curve {
+  pub a: BigInt,
+  pub b: BigInt,
+  pub p: BigInt, // prime of the base field
+  pub order: BigInt // number of points in the group scalars act on, scalars are reduced modulo it
+}
+
+impl Curve {
+  /// The BN254 (alt_bn128) G1 curve y^2 = x^3 + 3, whose group order is the BN254 scalar field modulus.
+  pub fn bn254() -> Self {
+    Curve {
+      a: BigInt::from(0),
+      b: BigInt::from(3),
+      p: "21888242871839275222246405745257275088696311157297823662689037894645226208583".parse().expect("valid BN254 base field modulus"),
+      order: field::bn254_scalar_modulus()
+    }
+  }
+
+  /// The BN254 G1 generator (1, 2).
+  pub fn bn254_generator() -> Point {
+    Point::Affine(BigInt::from(1), BigInt::from(2))
+  }
+
+  /// Returns true if `point` satisfies the curve equation (the point at infinity always does).
+  pub fn contains(&self, point: &Point) -> bool {
+    match point {
+      Point::Infinity => true,
+      Point::Affine(x, y) => (y * y).mod_floor(&self.p) == self.rhs(x)
+    }
+  }
+
+  /// Returns -point, its reflection over the x-axis.
+  pub fn negate(&self, point: &Point) -> Point {
+    match point {
+      Point::Infinity => Point::Infinity,
+      Point::Affine(x, y) => Point::Affine(x.clone(), (-y).mod_floor(&self.p))
+    }
+  }
+
+  /// Adds two points with the chord rule, falling back to `double` when they are equal.
+  pub fn add(&self, left: &Point, right: &Point) -> Point {
+    match (left, right) {
+      (Point::Infinity, point) | (point, Point::Infinity) => point.clone(),
+      (Point::Affine(x1, y1), Point::Affine(x2, y2)) => {
+        if x1 == x2 {
+          return if y1 == y2 { self.double(left) } else { Point::Infinity };
+        }
+        let slope = (y2 - y1) * self.inverse(&(x2 - x1));
+        self.complete_line(&slope, x1, y1, x2)
+      }
+    }
+  }
+
+  /// Doubles a point with the tangent rule.
+  pub fn double(&self, point: &Point) -> Point {
+    match point {
+      Point::Affine(x, y) if *y != BigInt::from(0) => {
+        let slope = (BigInt::from(3) * x * x + &self.a) * self.inverse(&(BigInt::from(2) * y));
+        self.complete_line(&slope, x, y, x)
+      }
+      // The point at infinity, and points of order two whose tangent is vertical
+      _ => Point::Infinity
+    }
+  }
+
+  /// Computes scalar * point by double-and-add over the bits of the scalar reduced modulo the group order.
+  pub fn mul(&self, point: &Point, scalar: &BigInt) -> Point {
+    let scalar = scalar.mod_floor(&self.order);
+    (0..scalar.bits()).rev().fold(Point::Infinity, |acc, bit| {
+      let doubled = self.double(&acc);
+      if scalar.bit(bit) { self.add(&doubled, point) } else { doubled }
+    })
+  }
+
+  /**
+   * Derives a point with no known discrete logarithm relative to any other from `seed`, by try-and-increment:
+   * x = SHA-256(seed || counter) mod p for counter = 0, 1, ... until x^3 + a*x + b is a square, then the
+   * smaller of the two square roots is taken as y.
+   *
+   * Panics unless p ≡ 3 (mod 4), the case where a square root is a single exponentiation. On curves with a
+   * cofactor the point may lie outside the prime-order subgroup; BN254 G1 has none.
+   */
+  pub fn hash_to_point(&self, seed: &str) -> Point {
+    assert!(self.p.mod_floor(&BigInt::from(4)) == BigInt::from(3), "hash_to_point needs p ≡ 3 (mod 4)");
+    let root_exponent = (&self.p + 1) / 4;
+    (0u32..)
+      .find_map(|counter| {
+        let x = BigInt::from_bytes_be(Sign::Plus, &Sha256::digest(format!("{}-{}", seed, counter))).mod_floor(&self.p);
+        let rhs = self.rhs(&x);
+        let y = rhs.modpow(&root_exponent, &self.p);
+        if (&y * &y).mod_floor(&self.p) != rhs {
+          return None;
+        }
+        let other = (-&y).mod_floor(&self.p);
+        Some(Point::Affine(x, y.min(other)))
+      })
+      .expect("about half of all x-coordinates are on the curve")
+  }
+
+  /// Evaluates x^3 + a*x + b in the base field.
+  fn rhs(&self, x: &BigInt) -> BigInt {
+    (x * x * x + &self.a * x + &self.b).mod_floor(&self.p)
+  }
+
+  /// Inverts a nonzero base field element.
+  fn inverse(&self, value: &BigInt) -> BigInt {
+    value.mod_floor(&self.p).modinv(&self.p).expect("only nonzero elements are inverted")
+  }
+
+  /// Completes an addition or doubling of (x1, y1) and a point with x-coordinate x2, given the line's slope.
+  fn complete_line(&self, slope: &BigInt, x1: &BigInt, y1: &BigInt, x2: &BigInt) -> Point {
+    let slope = slope.mod_floor(&self.p);
+    let x3 = (&slope * &slope - x1 - x2).mod_floor(&self.p);
+    let y3 = (&slope * (x1 - &x3) - y1).mod_floor(&self.p);
+    Point::Affine(x3, y3)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bn254_group_law_holds_on_the_generator() {
+    let curve = Curve::bn254();
+    let generator = Curve::bn254_generator();
+    let doubled = curve.double(&generator);
+    assert_eq!(doubled, Point::Affine(
+      "1368015179489954701390400359078579693043519447331113978918064868415326638035".parse().unwrap(),
+      "9918110051302171585080402603319702774565515993150576347155970296011118125764".parse().unwrap()
+    ));
+    assert_eq!(curve.add(&generator, &generator), doubled);
+    assert_eq!(curve.mul(&generator, &BigInt::from(3)), curve.add(&doubled, &generator));
+    assert!(curve.contains(&curve.mul(&generator, &BigInt::from(12345))));
+    assert_eq!(curve.add(&generator, &curve.negate(&generator)), Point::Infinity);
+    assert_eq!(curve.mul(&generator, &curve.order), Point::Infinity);
+  }
+}
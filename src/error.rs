@@ -0,0 +1,112 @@
+use std::fmt;
+use num_bigint::BigInt;
+
+/// Errors returned by the fallible parts of the library.
+#[derive(Debug)]
+pub enum ZkMockError {
+  /// A Merkle leaf was negative while building a tree in strict mode.
+  NegativeLeaf { index: usize, value: BigInt },
+  /// A circuit spec could not be parsed or is inconsistent.
+  InvalidSpec(String),
+  /// A spec named a hash function the library does not provide.
+  UnknownHashFunction(String),
+  /// A circuit replay log could not be parsed.
+  InvalidReplayLog(String),
+  /// Reading or writing a file failed.
+  Io(std::io::Error),
+  /// A proof file could not be decoded.
+  MalformedProof(String),
+  /// A bulk input assignment had a different number of values than the circuit has inputs.
+  InputCountMismatch { expected: usize, found: usize },
+  /// A proof was requested for a circuit without gates, which proves nothing.
+  EmptyCircuit,
+  /// A value does not fit the fixed 32-byte field encoding.
+  FieldEncoding(String),
+  /// A Merkle authentication path does not have the shape of a path to the claimed leaf index.
+  InvalidMerklePath(String),
+  /// A Hash or Commit gate was found in a circuit that has no hash function set.
+  MissingHashFunction { gate: usize },
+  /// A gate checked on addition computes a different value than its output wire holds.
+  UnsatisfiedGate { gate: usize, expected: BigInt, found: BigInt },
+  /// A compactly encoded circuit could not be decoded.
+  MalformedCircuit(String),
+  /// A saved R1CS file could not be decoded.
+  MalformedR1cs(String),
+  /// Adding an input or gate would take the circuit past its `CircuitBudget`.
+  BudgetExceeded { resource: &'static str, limit: usize },
+  /// An assertion gate checked on addition does not hold; `assertion` describes it with the wire values.
+  AssertionFailed { gate: usize, assertion: String },
+  /// A gate reads a wire past the inputs that no earlier gate writes.
+  UndefinedWire { gate: usize, wire: usize },
+  /// A Merkle leaf index is not below the number of leaves in the tree.
+  IndexOutOfBounds { index: usize, leaf_count: usize },
+  /// Strict mode refused to prove a circuit whose witness violates the listed constraints.
+  UnsatisfiedCircuit { constraints: Vec<usize> },
+  /// An input was added under a name another input already has.
+  DuplicateInputName(String),
+  /// A gate was wired by a name no input was added under.
+  UnknownInputName(String),
+  /// A proof file was written in a format version this library cannot read.
+  UnsupportedProofVersion { found: u16, supported: u16 },
+  /// A gate writes a wire past the next unallocated one, leaving a gap in the wire numbering.
+  UnallocatedWire { gate: usize, wire: usize, next: usize },
+  /// A proof holds more of something (e.g. constraints) than the u32 count its file format stores.
+  ProofTooLarge { what: &'static str, count: usize },
+  /// A new input would take a wire that a gate already writes as an intermediate wire.
+  InputAfterIntermediate { wire: usize },
+  /// A gate writes a wire an earlier gate (or the same gate, twice) already writes.
+  WireWrittenTwice { gate: usize, wire: usize, earlier: usize }
+}
+
+impl fmt::Display for ZkMockError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ZkMockError::NegativeLeaf { index, value } => write!(f, "Merkle leaf {} is negative ({}), canonicalize it first", index, value),
+      ZkMockError::InvalidSpec(reason) => write!(f, "Invalid circuit spec: {}", reason),
+      ZkMockError::UnknownHashFunction(name) => write!(f, "Unknown hash function '{}'", name),
+      ZkMockError::InvalidReplayLog(reason) => write!(f, "Invalid replay log: {}", reason),
+      ZkMockError::Io(error) => write!(f, "I/O error: {}", error),
+      ZkMockError::MalformedProof(reason) => write!(f, "Invalid-proof data format: {}", reason),
+      ZkMockError::InputCountMismatch { expected, found } => write!(f, "Expected {} input values, got {}", expected, found),
+      ZkMockError::EmptyCircuit => write!(f, "Circuit has no gates, a proof for it would prove nothing"),
+      ZkMockError::FieldEncoding(reason) => write!(f, "Field encoding failed: {}", reason),
+      ZkMockError::InvalidMerklePath(reason) => write!(f, "Invalid Merkle path: {}", reason),
+      ZkMockError::MissingHashFunction { gate } => write!(f, "Gate {} needs a hash function but the circuit has none, call set_hash_function first", gate),
+      ZkMockError::UnsatisfiedGate { gate, expected, found } => write!(f, "Gate {} computes {}, but its output wire holds {}", gate, expected, found),
+      ZkMockError::MalformedCircuit(reason) => write!(f, "Invalid encoded circuit: {}", reason),
+      ZkMockError::MalformedR1cs(reason) => write!(f, "Invalid R1CS file: {}", reason),
+      ZkMockError::BudgetExceeded { resource, limit } => write!(f, "Circuit budget exceeded: at most {} {} allowed", limit, resource),
+      ZkMockError::AssertionFailed { gate, assertion } => write!(f, "Gate {} asserts {}, which does not hold", gate, assertion),
+      ZkMockError::UndefinedWire { gate, wire } => write!(f, "Gate {} reads wire {}, which is neither an input nor written by an earlier gate", gate, wire),
+      ZkMockError::IndexOutOfBounds { index, leaf_count } => write!(f, "Leaf index {} is out of bounds for a tree with {} leaves", index, leaf_count),
+      ZkMockError::UnsatisfiedCircuit { constraints } => write!(f, "Witness violates constraints {:?}, refusing to prove a false statement", constraints),
+      ZkMockError::DuplicateInputName(name) => write!(f, "An input named '{}' already exists", name),
+      ZkMockError::UnknownInputName(name) => write!(f, "No input is named '{}'", name),
+      ZkMockError::UnsupportedProofVersion { found, supported } => write!(f, "Proof file format version {} is not supported, expected version {}", found, supported),
+      ZkMockError::UnallocatedWire { gate, wire, next } => write!(f, "Gate {} writes wire {}, but the next unallocated wire is {}", gate, wire, next),
+      ZkMockError::ProofTooLarge { what, count } => write!(f, "Proof has {} {}, more than the proof format can count", count, what),
+      ZkMockError::InputAfterIntermediate { wire } => write!(f, "A new input would take wire {}, which a gate already writes as an intermediate wire; add inputs before such gates", wire),
+      ZkMockError::WireWrittenTwice { gate, wire, earlier } => write!(f, "Gate {} writes wire {}, which gate {} already writes", gate, wire, earlier)
+    }
+  }
+}
+
+impl std::error::Error for ZkMockError {}
+
+impl From<std::io::Error> for ZkMockError {
+  fn from(error: std::io::Error) -> Self {
+    ZkMockError::Io(error)
+  }
+}
+
+/// Describes why a single R1CS constraint does not hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintError(pub String);
+
+impl fmt::Display for ConstraintError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl std::error::Error for ConstraintError {}
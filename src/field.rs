@@ -0,0 +1,188 @@
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use serde::{Deserialize, Serialize};
+
+/// Order of the BN254 (alt_bn128) scalar field, the field most circom/snarkjs circuits are written over.
+pub fn bn254_scalar_modulus() -> BigInt {
+  "21888242871839275222246405745257275088548364400416034343698204186575808495617".parse().expect("valid BN254 modulus")
+}
+
+/**
+ * An element of the prime field of integers modulo `modulus`, always kept reduced into `[0, modulus)`.
+ *
+ * Supports `+`, `-`, `*` and unary `-` (also on references) plus `inv`, every result reduced modulo the prime,
+ * so p - 1 + 1 wraps to 0 as it does in a real proof system. Combining elements of different fields panics.
+ *
+ * The constraint system itself stays on plain `BigInt`s: setting `R1CS::modulus` (or `Circuit::set_modulus`)
+ * is the opt-in field mode, in which every constraint comparison happens modulo the prime.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FieldElement {
+  value: BigInt, // canonical representative in [0, modulus)
+  modulus: BigInt // the prime the element lives modulo
+}
+
+impl FieldElement {
+  /// Creates the element `value mod modulus`; negative values wrap around.
+  pub fn new(value: BigInt, modulus: &BigInt) -> Self {
+    FieldElement { value: value.mod_floor(modulus), modulus: modulus.clone() }
+  }
+
+  /// Creates the zero element of the field.
+  pub fn zero(modulus: &BigInt) -> Self {
+    FieldElement::new(BigInt::from(0), modulus)
+  }
+
+  /// Creates the one element of the field.
+  pub fn one(modulus: &BigInt) -> Self {
+    FieldElement::new(BigInt::from(1), modulus)
+  }
+
+  /// Returns the element's canonical value, in `[0, modulus)`.
+  pub fn value(&self) -> &BigInt {
+    &self.value
+  }
+
+  /// Returns the prime of the element's field.
+  pub fn modulus(&self) -> &BigInt {
+    &self.modulus
+  }
+
+  /// Returns true for the zero element.
+  pub fn is_zero(&self) -> bool {
+    self.value == BigInt::from(0)
+  }
+
+  /// Returns the multiplicative inverse, or None for zero (which has none).
+  pub fn inv(&self) -> Option<FieldElement> {
+    let inverse = self.value.modinv(&self.modulus)?;
+    Some(FieldElement { value: inverse, modulus: self.modulus.clone() })
+  }
+
+  /// Raises the element to a non-negative power.
+  pub fn pow(&self, exponent: &BigInt) -> FieldElement {
+    FieldElement { value: self.value.modpow(exponent, &self.modulus), modulus: self.modulus.clone() }
+  }
+
+  /// Builds an element with `value` in this element's field, panicking if `other` lives in another field.
+  fn combine(&self, other: &FieldElement, value: BigInt) -> FieldElement {
+    assert!(self.modulus == other.modulus, "Cannot combine elements of different fields ({} and {})", self.modulus, other.modulus);
+    FieldElement::new(value, &self.modulus)
+  }
+}
+
+impl Add for &FieldElement {
+  type Output = FieldElement;
+
+  fn add(self, other: &FieldElement) -> FieldElement {
+    self.combine(other, &self.value + &other.value)
+  }
+}
+
+impl Sub for &FieldElement {
+  type Output = FieldElement;
+
+  fn sub(self, other: &FieldElement) -> FieldElement {
+    self.combine(other, &self.value - &other.value)
+  }
+}
+
+impl Mul for &FieldElement {
+  type Output = FieldElement;
+
+  fn mul(self, other: &FieldElement) -> FieldElement {
+    self.combine(other, &self.value * &other.value)
+  }
+}
+
+impl Neg for &FieldElement {
+  type Output = FieldElement;
+
+  fn neg(self) -> FieldElement {
+    FieldElement::new(-&self.value, &self.modulus)
+  }
+}
+
+impl Add for FieldElement {
+  type Output = FieldElement;
+
+  fn add(self, other: FieldElement) -> FieldElement {
+    &self + &other
+  }
+}
+
+impl Sub for FieldElement {
+  type Output = FieldElement;
+
+  fn sub(self, other: FieldElement) -> FieldElement {
+    &self - &other
+  }
+}
+
+impl Mul for FieldElement {
+  type Output = FieldElement;
+
+  fn mul(self, other: FieldElement) -> FieldElement {
+    &self * &other
+  }
+}
+
+impl Neg for FieldElement {
+  type Output = FieldElement;
+
+  fn neg(self) -> FieldElement {
+    -&self
+  }
+}
+
+impl fmt::Display for FieldElement {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::r1cs::{Operation, R1CS};
+
+  fn element(value: i64) -> FieldElement {
+    FieldElement::new(BigInt::from(value), &bn254_scalar_modulus())
+  }
+
+  #[test]
+  fn arithmetic_wraps_around_the_prime() {
+    let p = bn254_scalar_modulus();
+    let last = FieldElement::new(&p - 1, &p);
+    assert!((&last + &element(1)).is_zero());
+    assert_eq!(element(0) - element(1), last);
+    assert_eq!(-element(1), last);
+    assert_eq!(last.clone() * last, element(1));
+
+    let inverse = element(2).inv().unwrap();
+    assert_eq!(inverse * element(2), element(1));
+    assert!(element(0).inv().is_none());
+  }
+
+  #[test]
+  fn constraint_checks_wrap_in_field_mode() {
+    let p = bn254_scalar_modulus();
+    let system = |r1cs: &mut R1CS| {
+      let a = r1cs.append_variable(Some(&p - 1));
+      let b = r1cs.append_variable(Some(BigInt::from(1)));
+      let sum = r1cs.append_variable(Some(BigInt::from(0)));
+      r1cs.add_constraints(vec![(a, BigInt::from(1))], vec![(b, BigInt::from(1))], vec![(sum, BigInt::from(1))], Operation::Add);
+    };
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+
+    let mut in_field = R1CS::new_in_field(p.clone());
+    system(&mut in_field);
+    assert!(in_field.is_satisfied(no_hash));
+
+    let mut over_integers = R1CS::new();
+    system(&mut over_integers);
+    assert!(!over_integers.is_satisfied(no_hash));
+  }
+}
@@ -1,22 +1,767 @@
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use sha2::{Digest, Sha256};
+use crate::ec::{Curve, Point};
+use crate::error::ZkMockError;
+use crate::field;
+use crate::proof;
+use crate::r1cs;
 
-pub trait HashFunction {
-  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt;
+/// Hashes two nodes into one. Generic over the node type `T`, `BigInt` unless stated otherwise,
+/// so byte-oriented hashes can work on fixed-size arrays such as `[u8; 32]` directly.
+/// Hash functions are `Send + Sync`, so a circuit holding one can be shared across threads (e.g. in an `Arc`).
+pub trait HashFunction<T = BigInt>: Send + Sync {
+  fn hash(&self, a: &T, b: &T) -> T;
+
+  /// Hashes three nodes, e.g. two children and a domain tag. Defaults to `hash(hash(a, b), c)`.
+  fn hash3(&self, a: &T, b: &T, c: &T) -> T {
+    self.hash(&self.hash(a, b), c)
+  }
+
+  /// Hashes any number of nodes, e.g. the children of an N-ary Merkle node. Defaults to folding pairwise from
+  /// the left, `hash(hash(inputs[0], inputs[1]), inputs[2])...`, so two inputs hash like `hash`.
+  /// Panics on an empty slice.
+  fn hash_many(&self, inputs: &[T]) -> T
+  where T: Clone
+  {
+    let (first, rest) = inputs.split_first().expect("hash_many needs at least one input");
+    rest.iter().fold(first.clone(), |acc, input| self.hash(&acc, input))
+  }
+
+  /// Returns true if `out` is a reduced field element, i.e. lies in `[0, modulus)`.
+  /// Field hashes call this (in a debug assertion) on their result to catch a round that forgot to reduce.
+  fn validate_output(&self, out: &BigInt, modulus: &BigInt) -> bool {
+    out.sign() != Sign::Minus && out < modulus
+  }
+
+  /// Returns an error if `hash` cannot take `value` as an input, so callers such as `Circuit::validate` can reject
+  /// it up front rather than panic mid-proof. Every value is accepted by default.
+  fn check_input(&self, _value: &T) -> Result<(), ZkMockError> {
+    Ok(())
+  }
+
+  /// Names the hash function, for keys such as `Circuit::verify_proof_cached`'s that must tell hash functions apart.
+  /// Defaults to the implementing type's name; hashes with parameters (a modulus, a salt, ...) include them as well.
+  fn identity(&self) -> String {
+    std::any::type_name::<Self>().to_string()
+  }
+}
+
+// Lets borrowed and boxed (including `dyn`) hash functions be used wherever a generic `H: HashFunction` is expected
+impl<T, H: HashFunction<T> + ?Sized> HashFunction<T> for &H {
+  fn hash(&self, a: &T, b: &T) -> T {
+    (**self).hash(a, b)
+  }
+
+  fn hash3(&self, a: &T, b: &T, c: &T) -> T {
+    (**self).hash3(a, b, c)
+  }
+
+  fn hash_many(&self, inputs: &[T]) -> T
+  where T: Clone
+  {
+    (**self).hash_many(inputs)
+  }
+
+  fn check_input(&self, value: &T) -> Result<(), ZkMockError> {
+    (**self).check_input(value)
+  }
+
+  fn identity(&self) -> String {
+    (**self).identity()
+  }
+}
+
+impl<T, H: HashFunction<T> + ?Sized> HashFunction<T> for Box<H> {
+  fn hash(&self, a: &T, b: &T) -> T {
+    (**self).hash(a, b)
+  }
+
+  fn hash3(&self, a: &T, b: &T, c: &T) -> T {
+    (**self).hash3(a, b, c)
+  }
+
+  fn hash_many(&self, inputs: &[T]) -> T
+  where T: Clone
+  {
+    (**self).hash_many(inputs)
+  }
+
+  fn check_input(&self, value: &T) -> Result<(), ZkMockError> {
+    (**self).check_input(value)
+  }
+
+  fn identity(&self) -> String {
+    (**self).identity()
+  }
+}
+
+/// `hash(a, b) = a + b`, reduced modulo `modulus` when one is set, so Merkle nodes built with it stay in the field.
+/// The default has no modulus.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimpleAddHash {
+  pub modulus: Option<BigInt>
 }
 
-pub struct SimpleAddHash;
+impl SimpleAddHash {
+  pub fn new(modulus: Option<BigInt>) -> Self {
+    SimpleAddHash { modulus }
+  }
+}
 
 impl HashFunction for SimpleAddHash {
   fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
-    a + b
+    let out = r1cs::reduce(&(a + b), self.modulus.as_ref());
+    debug_assert!(self.modulus.as_ref().is_none_or(|modulus| self.validate_output(&out, modulus)));
+    out
+  }
+
+  fn identity(&self) -> String {
+    format!("SimpleAddHash({:?})", self.modulus)
   }
 }
 
-pub struct CustomHash;
+/// A tunable demonstration hash (not cryptographically secure): `hash(a, b) = (a * multiplier + b + offset) mod modulus`,
+/// without the reduction when no modulus is set. The default (multiplier 1, offset 0, no modulus) is plain `a + b`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomHash {
+  pub multiplier: BigInt,
+  pub offset: BigInt,
+  pub modulus: Option<BigInt>
+}
+
+impl CustomHash {
+  pub fn new(multiplier: BigInt, offset: BigInt, modulus: Option<BigInt>) -> Self {
+    CustomHash { multiplier, offset, modulus }
+  }
+}
+
+impl Default for CustomHash {
+  fn default() -> Self {
+    CustomHash::new(BigInt::from(1), BigInt::from(0), None)
+  }
+}
 
 impl HashFunction for CustomHash {
   fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
-    // A simple custom hash function for demonstration (not cryptographically secure)
-    a + b
+    r1cs::reduce(&(a * &self.multiplier + b + &self.offset), self.modulus.as_ref())
+  }
+
+  fn identity(&self) -> String {
+    format!("CustomHash({}, {}, {:?})", self.multiplier, self.offset, self.modulus)
+  }
+}
+
+/// SHA-256 over the concatenated 32-byte nodes, for trees with `[u8; 32]` nodes instead of `BigInt` ones.
+/// Over `BigInt`s it hashes each value's length-prefixed big-endian two's-complement bytes, so distinct pairs never
+/// collide before hashing, and reads the digest back as a big-endian unsigned integer; it is `Transcript`'s default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sha256Hash;
+
+impl HashFunction for Sha256Hash {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let mut hasher = Sha256::new();
+    for value in [a, b] {
+      let bytes = value.to_signed_bytes_be();
+      hasher.update((bytes.len() as u64).to_be_bytes());
+      hasher.update(&bytes);
+    }
+    BigInt::from_bytes_be(Sign::Plus, &hasher.finalize())
+  }
+}
+
+impl HashFunction<[u8; 32]> for Sha256Hash {
+  fn hash(&self, a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(a);
+    hasher.update(b);
+    hasher.finalize().into()
+  }
+}
+
+// Keccak-f[1600] round constants, applied to lane (0, 0) at the end of each of the 24 rounds
+const KECCAK_ROUND_CONSTANTS: [u64; 24] = [
+  0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+  0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+  0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+  0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+  0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+  0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008
+];
+// Rotation of each lane visited by the combined rho and pi steps, in visiting order
+const KECCAK_ROTATIONS: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+// Lane (index x + 5y) each step of the rho and pi walk moves the previous lane to, starting from lane 1
+const KECCAK_PI_LANES: [usize; 24] = [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+const KECCAK256_RATE: usize = 136; // bytes absorbed per permutation: 1600 bits minus 2 * 256 bits of capacity
+
+/// Runs the 24 rounds of the Keccak-f[1600] permutation over the 5x5 lanes of `state`, lane (x, y) at index x + 5y.
+fn keccak_f1600(state: &mut [u64; 25]) {
+  for round_constant in KECCAK_ROUND_CONSTANTS {
+    // theta: xor every lane with the parities of the two neighbouring columns
+    let parities: [u64; 5] = std::array::from_fn(|x| (0..25).step_by(5).fold(0, |parity, y| parity ^ state[x + y]));
+    for (position, lane) in state.iter_mut().enumerate() {
+      let x = position % 5;
+      *lane ^= parities[(x + 4) % 5] ^ parities[(x + 1) % 5].rotate_left(1);
+    }
+
+    // rho and pi: rotate every lane and move it to its new position
+    let mut carried = state[1];
+    for (&rotation, &target) in KECCAK_ROTATIONS.iter().zip(&KECCAK_PI_LANES) {
+      let displaced = state[target];
+      state[target] = carried.rotate_left(rotation);
+      carried = displaced;
+    }
+
+    // chi: the only non-linear step, mixing each row
+    for row in state.chunks_exact_mut(5) {
+      let original: [u64; 5] = row.try_into().expect("rows have 5 lanes");
+      for (x, lane) in row.iter_mut().enumerate() {
+        *lane = original[x] ^ (!original[(x + 1) % 5] & original[(x + 2) % 5]);
+      }
+    }
+
+    // iota
+    state[0] ^= round_constant;
+  }
+}
+
+/// Computes the Keccak-256 digest of `data`, as Ethereum's `keccak256` does: the original Keccak padding (0x01 ... 0x80),
+/// not the 0x06 domain byte that standardised SHA3-256 pads with, so the two give different digests.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+  let mut padded = data.to_vec();
+  padded.push(0x01);
+  padded.resize(padded.len().div_ceil(KECCAK256_RATE) * KECCAK256_RATE, 0);
+  *padded.last_mut().expect("padding added a byte") |= 0x80;
+
+  let mut state = [0u64; 25];
+  for block in padded.chunks(KECCAK256_RATE) {
+    for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+      *lane ^= u64::from_le_bytes(bytes.try_into().expect("the rate is a whole number of lanes"));
+    }
+    keccak_f1600(&mut state);
+  }
+
+  let mut digest = [0u8; 32];
+  for (bytes, lane) in digest.chunks_mut(8).zip(&state) {
+    bytes.copy_from_slice(&lane.to_le_bytes());
+  }
+  digest
+}
+
+/**
+ * Keccak-256 as Ethereum uses it, for mocks mirroring on-chain Merkle structures.
+ *
+ * `hash(a, b)` is keccak256 over the two inputs each encoded as 32 big-endian bytes (see `proof::to_field_bytes`),
+ * i.e. Solidity's `keccak256(abi.encodePacked(uint256(a), uint256(b)))`, with the digest read back as a big-endian
+ * unsigned integer. Negative inputs and inputs of 2^256 or more have no such encoding: `try_hash` rejects them with
+ * `ZkMockError::FieldEncoding` and `hash` panics, while `check_input` reports them so circuits refuse them up front.
+ * Trees over raw 32-byte nodes can use the `[u8; 32]` implementation.
+ */
+pub struct Keccak256;
+
+impl Keccak256 {
+  /// Fallible counterpart of `hash`.
+  pub fn try_hash(&self, a: &BigInt, b: &BigInt) -> Result<BigInt, ZkMockError> {
+    let digest = <Self as HashFunction<[u8; 32]>>::hash(self, &proof::to_field_bytes(a)?, &proof::to_field_bytes(b)?);
+    Ok(BigInt::from_bytes_be(Sign::Plus, &digest))
+  }
+}
+
+impl HashFunction for Keccak256 {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    self.try_hash(a, b).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  fn check_input(&self, value: &BigInt) -> Result<(), ZkMockError> {
+    proof::to_field_bytes(value).map(|_| ())
+  }
+}
+
+impl HashFunction<[u8; 32]> for Keccak256 {
+  fn hash(&self, a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    keccak256(&[a.as_slice(), b.as_slice()].concat())
+  }
+}
+
+/// Rabin-style mock: `hash(a, b) = (a^2 + b) mod n` for a fixed composite `n`.
+/// Non-linear and order-sensitive, so it catches Merkle mistakes that pure addition hides, but still not cryptographically secure.
+pub struct ModSquareHash {
+  pub modulus: BigInt // composite n, its factorization is what makes square roots hard
+}
+
+impl ModSquareHash {
+  pub fn new(modulus: BigInt) -> Self {
+    ModSquareHash { modulus }
+  }
+}
+
+impl Default for ModSquareHash {
+  /// Uses n = (2^31 - 1) * (2^61 - 1), a product of two Mersenne primes.
+  fn default() -> Self {
+    let p = (BigInt::from(1) << 31) - 1;
+    let q = (BigInt::from(1) << 61) - 1;
+    ModSquareHash::new(p * q)
+  }
+}
+
+impl HashFunction for ModSquareHash {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let out = (a * a + b).mod_floor(&self.modulus);
+    debug_assert!(self.validate_output(&out, &self.modulus));
+    out
+  }
+
+  fn identity(&self) -> String {
+    format!("ModSquareHash({})", self.modulus)
+  }
+}
+
+/// Domain-separated wrapper: mixes a fixed salt into every hash of the inner function,
+/// `hash(a, b) = inner.hash(inner.hash(salt, a), b)`, so different salts (e.g. protocol versions) never share outputs.
+pub struct Salted<H: HashFunction> {
+  pub salt: BigInt,
+  pub inner: H
+}
+
+impl<H: HashFunction> Salted<H> {
+  pub fn new(salt: BigInt, inner: H) -> Self {
+    Salted { salt, inner }
+  }
+}
+
+impl<H: HashFunction> HashFunction for Salted<H> {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    self.inner.hash(&self.inner.hash(&self.salt, a), b)
+  }
+
+  fn check_input(&self, value: &BigInt) -> Result<(), ZkMockError> {
+    self.inner.check_input(value)
+  }
+
+  fn identity(&self) -> String {
+    format!("Salted({}, {})", self.salt, self.inner.identity())
   }
-}
\ No newline at end of file
+}
+
+/// Composes several hash functions into one: the first stage hashes `(a, b)`, every later stage hashes the
+/// previous output with a fixed padding element, e.g. `second.hash(first.hash(a, b), padding)` for two stages.
+/// Built with `HashChain::new(first).then(second)`; the padding defaults to 0 and can be changed with `padding`.
+pub struct HashChain {
+  stages: Vec<Box<dyn HashFunction>>,
+  padding: BigInt // second input of every stage after the first
+}
+
+impl HashChain {
+  /// Starts a chain whose first stage is `first`.
+  pub fn new(first: Box<dyn HashFunction>) -> Self {
+    HashChain { stages: vec![first], padding: BigInt::from(0) }
+  }
+
+  /// Appends a stage that hashes the previous stage's output.
+  pub fn then(mut self, next: Box<dyn HashFunction>) -> Self {
+    self.stages.push(next);
+    self
+  }
+
+  /// Sets the padding element later stages hash the previous output with.
+  pub fn padding(mut self, padding: BigInt) -> Self {
+    self.padding = padding;
+    self
+  }
+}
+
+impl HashFunction for HashChain {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let (first, rest) = self.stages.split_first().expect("a hash chain has at least one stage");
+    rest.iter().fold(first.hash(a, b), |out, stage| stage.hash(&out, &self.padding))
+  }
+
+  /// Only the first stage sees the inputs, later ones hash its output.
+  fn check_input(&self, value: &BigInt) -> Result<(), ZkMockError> {
+    self.stages[0].check_input(value)
+  }
+
+  fn identity(&self) -> String {
+    let stages: Vec<String> = self.stages.iter().map(|stage| stage.identity()).collect();
+    format!("HashChain([{}], {})", stages.join(", "), self.padding)
+  }
+}
+
+const POSEIDON_WIDTH: usize = 3; // state elements: one capacity element plus the two inputs
+const POSEIDON_FULL_ROUNDS: usize = 8; // half before and half after the partial rounds
+const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+
+/**
+ * Poseidon over the BN254 scalar field for two inputs, the hash real circuits use because it costs few constraints.
+ *
+ * Configuration (the width-3 parameters of the Poseidon paper for a 254-bit field):
+ * - state `[0, a, b]` with a and b reduced into the field, the first element being the capacity;
+ * - S-box x^5, 8 full rounds (4 before and 4 after) and 57 partial rounds that only apply it to the first element;
+ * - round constant i is SHA-256("poseidon-bn254-t3-" || i) reduced into the field, 3 per round;
+ * - MDS matrix is the Cauchy matrix M[i][j] = 1 / (i + (3 + j)), invertible since all i + 3 + j differ from 0 and each other.
+ *
+ * The output is the first state element after the permutation. The constants are generated here rather than taken
+ * from circomlib's Grain LFSR, so hashes do not match circomlib's Poseidon: the structure, not the exact values, is the point.
+ */
+pub struct Poseidon {
+  modulus: BigInt,
+  round_constants: Vec<BigInt>, // POSEIDON_WIDTH per round, in round order
+  mds: Vec<Vec<BigInt>>
+}
+
+impl Poseidon {
+  pub fn new() -> Self {
+    let modulus = field::bn254_scalar_modulus();
+    let rounds = POSEIDON_FULL_ROUNDS + POSEIDON_PARTIAL_ROUNDS;
+    let round_constants = (0..rounds * POSEIDON_WIDTH)
+      .map(|i| BigInt::from_bytes_be(Sign::Plus, &Sha256::digest(format!("poseidon-bn254-t3-{}", i))).mod_floor(&modulus))
+      .collect();
+    let mds = (0..POSEIDON_WIDTH)
+      .map(|row| (0..POSEIDON_WIDTH)
+        .map(|column| BigInt::from(row + POSEIDON_WIDTH + column).modinv(&modulus).expect("Cauchy entries are invertible"))
+        .collect())
+      .collect();
+    Poseidon { modulus, round_constants, mds }
+  }
+
+  /// Runs the Poseidon permutation over `state` in place.
+  fn permute(&self, state: &mut [BigInt]) {
+    let half_full = POSEIDON_FULL_ROUNDS / 2;
+    for (round, constants) in self.round_constants.chunks(POSEIDON_WIDTH).enumerate() {
+      for (element, constant) in state.iter_mut().zip(constants) {
+        *element = (&*element + constant).mod_floor(&self.modulus);
+      }
+      let full = round < half_full || round >= half_full + POSEIDON_PARTIAL_ROUNDS;
+      let sboxed = if full { POSEIDON_WIDTH } else { 1 };
+      for element in &mut state[..sboxed] {
+        *element = element.modpow(&BigInt::from(5), &self.modulus);
+      }
+      let mixed: Vec<BigInt> = self.mds.iter()
+        .map(|row| row.iter().zip(state.iter()).map(|(m, element)| m * element).sum::<BigInt>().mod_floor(&self.modulus))
+        .collect();
+      state.clone_from_slice(&mixed);
+    }
+  }
+}
+
+impl Default for Poseidon {
+  fn default() -> Self {
+    Poseidon::new()
+  }
+}
+
+impl HashFunction for Poseidon {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let mut state = vec![BigInt::from(0), a.mod_floor(&self.modulus), b.mod_floor(&self.modulus)];
+    self.permute(&mut state);
+    let out = state.swap_remove(0);
+    debug_assert!(self.validate_output(&out, &self.modulus));
+    out
+  }
+}
+
+/// Generates `rounds` MiMC round constants modulo `modulus`: c_0 = 0 as in the MiMC paper, and
+/// c_i = SHA-256("mimc-" || i) reduced modulo `modulus` for every later round.
+pub fn mimc_round_constants(modulus: &BigInt, rounds: usize) -> Vec<BigInt> {
+  (0..rounds)
+    .map(|i| match i {
+      0 => BigInt::from(0),
+      _ => BigInt::from_bytes_be(Sign::Plus, &Sha256::digest(format!("mimc-{}", i))).mod_floor(modulus)
+    })
+    .collect()
+}
+
+/**
+ * MiMC with the cube S-box, the simplest field-friendly hash: `hash(a, b)` encrypts the message `a` under the key `b`.
+ *
+ * Starting from x = a, every round i computes x = (x + k + c_i)^3 mod p, and the output is x + k mod p after the last
+ * round, with `c_i` the round constants of `mimc_round_constants`. Cubing is only a permutation when gcd(3, p - 1) = 1,
+ * so pick such a prime (BN254's scalar field is not one). The usual round count is ceil(log_3(p)).
+ */
+pub struct Mimc {
+  modulus: BigInt,
+  round_constants: Vec<BigInt> // one per round, in round order
+}
+
+impl Mimc {
+  pub fn new(modulus: BigInt, rounds: usize) -> Self {
+    let round_constants = mimc_round_constants(&modulus, rounds);
+    Mimc { modulus, round_constants }
+  }
+
+  /// Returns the round constants, one per round.
+  pub fn round_constants(&self) -> &[BigInt] {
+    &self.round_constants
+  }
+}
+
+impl HashFunction for Mimc {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let key = b.mod_floor(&self.modulus);
+    let encrypted = self.round_constants.iter().fold(a.mod_floor(&self.modulus), |x, constant| {
+      (x + &key + constant).modpow(&BigInt::from(3), &self.modulus)
+    });
+    let out = (encrypted + key).mod_floor(&self.modulus);
+    debug_assert!(self.validate_output(&out, &self.modulus));
+    out
+  }
+
+  fn identity(&self) -> String {
+    format!("Mimc({}, {})", self.modulus, self.round_constants.len())
+  }
+}
+
+/**
+ * A Pedersen hash over the BN254 G1 curve: `hash(a, b)` is the x-coordinate of a*G_0 + b*G_1, with a and b reduced
+ * modulo the group order (the BN254 scalar field) and 0 standing in for the point at infinity.
+ *
+ * The generators come from `Curve::hash_to_point` on "pedersen-bn254-0" and "pedersen-bn254-1", so nobody knows the
+ * discrete log of one relative to the other, which is what makes finding collisions as hard as computing it. The
+ * output lives in the base field, not the scalar field, and about 512 affine additions per hash make it slow.
+ */
+pub struct Pedersen {
+  curve: Curve,
+  generators: [Point; 2] // multiplied by a and b respectively
+}
+
+impl Pedersen {
+  pub fn new() -> Self {
+    let curve = Curve::bn254();
+    let generators = [curve.hash_to_point("pedersen-bn254-0"), curve.hash_to_point("pedersen-bn254-1")];
+    Pedersen { curve, generators }
+  }
+
+  /// Returns the two generator points.
+  pub fn generators(&self) -> &[Point; 2] {
+    &self.generators
+  }
+}
+
+impl Default for Pedersen {
+  fn default() -> Self {
+    Pedersen::new()
+  }
+}
+
+impl HashFunction for Pedersen {
+  fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+    let commitment = self.curve.add(
+      &self.curve.mul(&self.generators[0], a),
+      &self.curve.mul(&self.generators[1], b)
+    );
+    let out = commitment.x().cloned().unwrap_or_else(|| BigInt::from(0));
+    debug_assert!(self.validate_output(&out, &self.curve.p));
+    out
+  }
+}
+
+/// Looks up one of the library's hash functions by the name used in circuit specs.
+/// Known names: `simple_add`, `custom` (both with their default configuration), `mod_square` (with the default modulus), `poseidon`, `pedersen`, `keccak256`.
+pub fn by_name(name: &str) -> Option<Box<dyn HashFunction>> {
+  match name {
+    "simple_add" => Some(Box::new(SimpleAddHash::default())),
+    "custom" => Some(Box::new(CustomHash::default())),
+    "mod_square" => Some(Box::new(ModSquareHash::default())),
+    "poseidon" => Some(Box::new(Poseidon::new())),
+    "pedersen" => Some(Box::new(Pedersen::new())),
+    "keccak256" => Some(Box::new(Keccak256)),
+    _ => None
+  }
+}
+
+/// Maps an integer to a natural number injectively: 0, -1, 1, -2, 2, ... -> 0, 1, 2, 3, 4, ...
+fn zigzag(n: &BigInt) -> BigInt {
+  let doubled: BigInt = n * 2;
+  if n.sign() == Sign::Minus { -doubled - 1 } else { doubled }
+}
+
+/**
+ * Encodes an ordered pair as a single integer, injectively, so distinct pairs never collide before hashing.
+ *
+ * Both values are first zigzag-mapped to naturals x and y, then:
+ * - small pairs (x, y < 2^64) use the Szudzik pairing x*x + x + y (x >= y) or y*y + x (x < y), tagged as 2*z;
+ * - larger pairs use a length-prefixed concatenation (x << L | y) << 64 | L with L the bit length of y, tagged as 2*z + 1.
+ *
+ * The tag bit keeps the two regimes disjoint; each regime is injective on its own, so the whole encoding is.
+ * Order matters: (0, 1) and (1, 0) encode differently.
+ */
+pub fn pair_encode(a: &BigInt, b: &BigInt) -> BigInt {
+  let x = zigzag(a);
+  let y = zigzag(b);
+
+  if x.bits() <= 64 && y.bits() <= 64 {
+    let z = if x >= y { &x * &x + &x + &y } else { &y * &y + &x };
+    z * 2
+  } else {
+    let length = y.bits();
+    let z = (((x << length) | y) << 64) | BigInt::from(length);
+    z * 2 + 1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn int(value: i64) -> BigInt {
+    BigInt::from(value)
+  }
+
+  // Forgets to reduce its result, landing one past the modulus
+  struct UnreducedHash {
+    modulus: BigInt
+  }
+
+  impl HashFunction for UnreducedHash {
+    fn hash(&self, _a: &BigInt, _b: &BigInt) -> BigInt {
+      &self.modulus + 1
+    }
+  }
+
+  #[test]
+  fn validate_output_rejects_an_unreduced_hash() {
+    let modulus = int(101);
+    let broken = UnreducedHash { modulus: modulus.clone() };
+    assert!(!broken.validate_output(&broken.hash(&int(1), &int(2)), &modulus));
+    assert!(!broken.validate_output(&modulus, &modulus));
+    assert!(!broken.validate_output(&int(-1), &modulus));
+    assert!(broken.validate_output(&int(100), &modulus));
+
+    let poseidon = Poseidon::new();
+    assert!(poseidon.validate_output(&poseidon.hash(&int(1), &int(2)), &field::bn254_scalar_modulus()));
+  }
+
+  #[test]
+  fn pair_encode_is_injective_on_edge_cases() {
+    let large: BigInt = BigInt::from(1) << 100;
+    let pairs = [
+      (int(0), int(0)), (int(0), int(1)), (int(1), int(0)), (int(1), int(1)), (int(-1), int(0)), (int(0), int(-1)),
+      (int(2), int(3)), (int(3), int(2)), (large.clone(), int(0)), (int(0), large.clone()), (-&large, large.clone())
+    ];
+    let encoded: Vec<BigInt> = pairs.iter().map(|(a, b)| pair_encode(a, b)).collect();
+    for (i, first) in encoded.iter().enumerate() {
+      for (j, second) in encoded.iter().enumerate().skip(i + 1) {
+        assert_ne!(first, second, "{:?} and {:?} collide", pairs[i], pairs[j]);
+      }
+    }
+    assert_eq!(pair_encode(&int(2), &int(3)), pair_encode(&int(2), &int(3)));
+  }
+
+  #[test]
+  fn salted_hashes_with_different_salts_differ() {
+    let first = Salted::new(int(1), ModSquareHash::default());
+    let second = Salted::new(int(2), ModSquareHash::default());
+    for (a, b) in [(int(0), int(0)), (int(3), int(4)), (int(123456789), int(987654321))] {
+      assert_ne!(first.hash(&a, &b), second.hash(&a, &b));
+      assert_eq!(first.hash(&a, &b), Salted::new(int(1), ModSquareHash::default()).hash(&a, &b));
+    }
+  }
+
+  #[test]
+  fn mod_square_hash_is_deterministic_reduced_and_order_sensitive() {
+    let hash = ModSquareHash::default();
+    let large: BigInt = BigInt::from(1) << 200;
+    for (a, b) in [(int(3), int(4)), (large.clone(), int(-7)), (int(-5), large)] {
+      let out = hash.hash(&a, &b);
+      assert_eq!(hash.hash(&a, &b), out);
+      assert!(out >= int(0) && out < hash.modulus);
+    }
+    assert_eq!(ModSquareHash::new(int(11)).hash(&int(3), &int(4)), int(2));
+    assert_ne!(hash.hash(&int(3), &int(4)), hash.hash(&int(4), &int(3)));
+  }
+
+  #[test]
+  fn hash_chain_feeds_each_stage_into_the_next() {
+    let chain = HashChain::new(Box::new(Poseidon::new())).then(Box::new(Keccak256));
+    let expected = Keccak256.hash(&Poseidon::new().hash(&int(1), &int(2)), &int(0));
+    assert_eq!(chain.hash(&int(1), &int(2)), expected);
+    assert_eq!(chain.hash(&int(1), &int(2)), expected);
+
+    let padded = HashChain::new(Box::new(Poseidon::new())).then(Box::new(Keccak256)).padding(int(5));
+    assert_ne!(padded.hash(&int(1), &int(2)), expected);
+  }
+
+  #[test]
+  fn poseidon_matches_known_answers() {
+    let poseidon = Poseidon::new();
+    let known = [
+      (0, 0, "18319546027205606373618911331077427557769790941779756276969034985622078938745"),
+      (1, 2, "386750271168318971634721660731172368573685872707600919000623175324088596355"),
+      (-1, 3, "2017764895502807273945326066442944867642263611609572398741694204180090528616")
+    ];
+    for (a, b, expected) in known {
+      assert_eq!(poseidon.hash(&int(a), &int(b)).to_string(), expected, "poseidon({}, {})", a, b);
+    }
+    assert_ne!(poseidon.hash(&int(1), &int(2)), poseidon.hash(&int(2), &int(1)));
+  }
+
+  #[test]
+  fn configured_hashes_apply_their_parameters_and_defaults_add() {
+    let custom = CustomHash::new(int(3), int(5), Some(int(11)));
+    assert_eq!(custom.hash(&int(4), &int(2)), int(8)); // (4 * 3 + 2 + 5) mod 11
+    assert_eq!(CustomHash::new(int(3), int(5), None).hash(&int(4), &int(2)), int(19));
+
+    assert_eq!(CustomHash::default().hash(&int(4), &int(2)), int(6));
+    assert_eq!(SimpleAddHash::default().hash(&int(4), &int(2)), int(6));
+    assert_eq!(SimpleAddHash::new(Some(int(5))).hash(&int(4), &int(2)), int(1));
+  }
+
+  #[test]
+  fn mimc_matches_known_answers_and_builds_a_merkle_tree() {
+    // 2^64 - 59 is prime with gcd(3, p - 1) = 1, and ceil(log_3(p)) = 41 rounds
+    let modulus: BigInt = (BigInt::from(1) << 64) - 59;
+    let mimc = Mimc::new(modulus.clone(), 41);
+    assert_eq!(mimc.round_constants().len(), 41);
+    assert_eq!(mimc.hash(&int(0), &int(0)), "5852313115299880550".parse::<BigInt>().unwrap());
+    assert_eq!(mimc.hash(&int(1), &int(2)), "8709744116355394896".parse::<BigInt>().unwrap());
+    assert_eq!(mimc.hash(&int(-1), &int(3)), "1057098214439513706".parse::<BigInt>().unwrap());
+
+    let leaves: Vec<BigInt> = (1..=5).map(int).collect();
+    let tree = crate::merkle::MerkleTree::new(leaves.clone(), Mimc::new(modulus, 41));
+    for (index, leaf) in leaves.iter().enumerate() {
+      let path = tree.merkle_path(index);
+      assert!(crate::merkle::MerkleTree::<Mimc>::verify_proof_at(&tree.root, leaf, index, leaves.len(), &path, &mimc).unwrap());
+    }
+  }
+
+  #[test]
+  fn pedersen_is_deterministic_and_not_commutative() {
+    let pedersen = Pedersen::new();
+    let first = pedersen.hash(&int(3), &int(5));
+    assert_eq!(Pedersen::new().hash(&int(3), &int(5)), first);
+    assert_ne!(pedersen.hash(&int(5), &int(3)), first);
+    assert_ne!(pedersen.hash(&int(3), &int(6)), first);
+  }
+
+  #[test]
+  fn keccak256_matches_known_answers_and_rejects_unencodable_inputs() {
+    let hex = |digest: [u8; 32]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    assert_eq!(hex(keccak256(b"")), "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+    assert_eq!(hex(keccak256(b"abc")), "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45");
+    // keccak256(abi.encodePacked(uint256(1), uint256(2)))
+    let expected = BigInt::parse_bytes(b"e90b7bceb6e7df5418fb78d8ee546e97c83a08bbccc01a0644d599ccd2a7c2e0", 16).unwrap();
+    assert_eq!(Keccak256.hash(&int(1), &int(2)), expected);
+
+    let too_large = BigInt::from(1) << 256;
+    assert!(Keccak256.check_input(&(&too_large - 1)).is_ok());
+    assert!(matches!(Keccak256.check_input(&too_large), Err(ZkMockError::FieldEncoding(_))));
+    assert!(matches!(Keccak256.check_input(&int(-1)), Err(ZkMockError::FieldEncoding(_))));
+    assert!(matches!(Keccak256.try_hash(&int(-1), &int(2)), Err(ZkMockError::FieldEncoding(_))));
+    let boxed: Box<dyn HashFunction> = Box::new(Salted::new(int(7), Keccak256));
+    assert!(boxed.check_input(&int(-1)).is_err());
+  }
+
+  #[test]
+  fn simple_add_hash_wraps_near_the_modulus_and_keeps_merkle_nodes_in_the_field() {
+    let modulus = field::bn254_scalar_modulus();
+    let hash = SimpleAddHash::new(Some(modulus.clone()));
+    assert_eq!(hash.hash(&(&modulus - 1), &int(1)), int(0));
+    assert_eq!(hash.hash(&(&modulus - 2), &(&modulus - 3)), &modulus - 5);
+    assert_eq!(hash.hash(&(&modulus - 1), &int(-1)), &modulus - 2);
+
+    let leaves: Vec<BigInt> = (1..=7).map(|offset| &modulus - offset).collect();
+    let tree = crate::merkle::MerkleTree::new(leaves, SimpleAddHash::new(Some(modulus.clone())));
+    assert!(tree.root.sign() != Sign::Minus && tree.root < modulus);
+    assert_eq!(tree.root, &modulus - 28);
+  }
+}
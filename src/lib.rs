@@ -0,0 +1,13 @@
+pub mod circuit;
+pub mod builder;
+pub mod r1cs;
+pub mod field;
+pub mod ec;
+pub mod schnorr;
+pub mod hash_functions;
+pub mod merkle;
+pub mod error;
+pub mod proof;
+pub mod transcript;
+#[cfg(feature = "testing")]
+pub mod testing;
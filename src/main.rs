@@ -1,10 +1,5 @@
-mod circuit;
-mod r1cs;
-mod hash_functions;
-mod merkle;
-
 use num_bigint::{ToBigInt};
-use circuit::Circuit;
+use zk_mock_rust::circuit::{self, Circuit};
 
 /// A simple addition proof using the Circuit and a basic addition hash 
 fn addition_proof() {
@@ -13,35 +8,57 @@ fn addition_proof() {
     let input1 = circuit.add_input(10.to_bigint().unwrap());
     let input2 = circuit.add_input(20.to_bigint().unwrap());
 
-    let output_index = circuit.add_input(30.to_bigint().unwrap()); // This will be the expected output of the addition
-    circuit.add_gate(circuit::Gate::Add(input1, input2, output_index));
+    // the output wire is allocated and filled with the computed sum, no need to supply 30 by hand
+    circuit.add_gate_with_output(|output_index| circuit::Gate::Add(input1, input2, output_index));
     circuit.add_output(30.to_bigint().unwrap());
 
     // generating the verifying the addition-proof
     println!("Generating addition-proof...");
-    circuit.generate_proof("addition_proof.bin");
-    let is_valid = circuit.verify_proof("addition_proof.bin");
+    let proof = circuit.generate_proof("addition_proof.bin").expect("Unable to generate proof");
+    println!("Proof-generation completed. Proof is valid: {}", proof.valid);
+    let is_valid = circuit.verify_proof("addition_proof.bin").expect("Unable to verify proof");
     println!("Addition-proof is valid: {}", is_valid);
 }
 
+/// The subtraction counterpart of `addition_proof`, proving 30 - 20 = 10
+fn subtraction_proof() {
+    let mut circuit = Circuit::new(None);
+
+    let input1 = circuit.add_input(30.to_bigint().unwrap());
+    let input2 = circuit.add_input(20.to_bigint().unwrap());
+
+    // the output wire is allocated and filled with the computed difference, no need to supply 10 by hand
+    circuit.add_gate_with_output(|output_index| circuit::Gate::Sub(input1, input2, output_index));
+    circuit.add_output(10.to_bigint().unwrap());
+
+    // generating the verifying the subtraction-proof
+    println!("Generating subtraction-proof...");
+    let proof = circuit.generate_proof("subtraction_proof.bin").expect("Unable to generate proof");
+    println!("Proof-generation completed. Proof is valid: {}", proof.valid);
+    let is_valid = circuit.verify_proof("subtraction_proof.bin").expect("Unable to verify proof");
+    println!("Subtraction-proof is valid: {}", is_valid);
+}
+
 fn multiplication_proof() {
     let mut circuit = Circuit::new(None);
 
     let input1 = circuit.add_input(5.to_bigint().unwrap());
     let input2 = circuit.add_input(4.to_bigint().unwrap());
 
-    let output_index = circuit.add_input(20.to_bigint().unwrap()); // This will be the expected output of the multiplication
-    circuit.add_gate(circuit::Gate::Mul(input1, input2, output_index));
+    // the output wire is allocated and filled with the computed product, no need to supply 20 by hand
+    circuit.add_gate_with_output(|output_index| circuit::Gate::Mul(input1, input2, output_index));
     circuit.add_output(20.to_bigint().unwrap());
 
     // generating the verifying the multiplication-proof
     println!("Generating multiplication-proof...");
-    circuit.generate_proof("multiplication_proof.bin");
-    let is_valid = circuit.verify_proof("multiplication_proof.bin");
+    let proof = circuit.generate_proof("multiplication_proof.bin").expect("Unable to generate proof");
+    println!("Proof-generation completed. Proof is valid: {}", proof.valid);
+    let is_valid = circuit.verify_proof("multiplication_proof.bin").expect("Unable to verify proof");
     println!("Multiplication-proof is valid: {}", is_valid);
 }
 
 fn main() {
     addition_proof();
+    subtraction_proof();
     multiplication_proof();
 }
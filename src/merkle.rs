@@ -1,37 +1,318 @@
-use num_bigint::BigInt;
+use std::collections::HashMap;
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::error::ZkMockError;
 use crate::hash_functions::HashFunction;
 
+/// Maps a leaf into the canonical range `[0, modulus)`.
+///
+/// Byte-oriented hashes encode a `BigInt` through its magnitude and sign, so a negative
+/// leaf and its positive field equivalent would otherwise hash differently (or, with a
+/// two's-complement encoding, collide with an unrelated positive value).
+pub fn canonicalize_leaf(leaf: &BigInt, modulus: &BigInt) -> BigInt {
+    leaf.mod_floor(modulus)
+}
+
+/// How a level with an odd number of nodes treats its last, unpaired node.
+///
+/// Trees built with different strategies over the same leaves (of a non-power-of-two count)
+/// have different roots, so a proof from one never verifies against the other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OddNodeStrategy {
+    /// Move the lone node up to the next level unchanged
+    #[default]
+    Promote,
+
+    /// Hash the lone node with a copy of itself, as Bitcoin-style trees do
+    DuplicateLast
+}
+
+/// One level of an N-ary authentication path, see `MerkleTree::merkle_path_nary`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NaryPathLevel<N = BigInt> {
+    /// The other children of the node's parent, in order, without the node itself
+    pub siblings: Vec<N>,
+
+    /// Where the node sits among its parent's children, i.e. where to insert it into `siblings`
+    pub position: usize
+}
+
+/// An inclusion proof: a leaf value plus its authentication path.
+///
+/// The path has the same shape as `MerkleTree::merkle_path` returns:
+/// (sibling_hash, is_right_sibling) pairs from the leaf level upwards.
+/// Generic over the node type `N` like `MerkleTree`; the byte serialization is only available for `BigInt` nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof<N = BigInt> {
+    /// The leaf value being proven
+    pub leaf: N,
+
+    /// Sibling hashes and direction bits from the leaf up to just below the root
+    pub path: Vec<(N, bool)>
+}
+
+impl<N: Clone + PartialEq> MerkleProof<N> {
+    /// Checks the proof against `root` (see `MerkleTree::verify_proof`).
+    pub fn verify<H: HashFunction<N>>(&self, root: &N, hash_function: &H) -> bool {
+        MerkleTree::verify_proof(root, &self.leaf, &self.path, hash_function)
+    }
+
+    /// Checks a proof from `MerkleTree::prove_not_equal`: the proven leaf belongs to the tree
+    /// committed to by `root` and differs from `claimed`.
+    pub fn verify_not_equal<H: HashFunction<N>>(&self, root: &N, claimed: &N, hash_function: &H) -> bool {
+        self.leaf != *claimed && self.verify(root, hash_function)
+    }
+}
+
+/// A batch inclusion proof for several leaves of one binary tree, see `MerkleTree::merkle_multiproof`.
+///
+/// Siblings shared by the paths of several proven leaves, and nodes the proven leaves derive themselves,
+/// appear only once or not at all, so it is never larger than the separate proofs together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiProof<N = BigInt> {
+    /// Indices of the proven leaves, ascending and without duplicates
+    pub indices: Vec<usize>,
+
+    /// The proven leaf values, in the order of `indices`
+    pub leaves: Vec<N>,
+
+    /// Number of leaves in the tree, which fixes its shape
+    pub leaf_count: usize,
+
+    /// What the tree does with the last node of odd-sized levels
+    pub odd_node_strategy: OddNodeStrategy,
+
+    /// The nodes the proven leaves cannot derive, level by level from the leaves upwards and left to right within a level
+    pub siblings: Vec<N>
+}
+
+impl<N: Clone + PartialEq> MultiProof<N> {
+    /// Checks the proof against `root` (see `MerkleTree::verify_multiproof`).
+    pub fn verify<H: HashFunction<N>>(&self, root: &N, hash_function: &H) -> bool {
+        MerkleTree::verify_multiproof(root, self, hash_function)
+    }
+}
+
+impl MerkleProof {
+    /// Serializes the proof into a compact byte layout for external verifiers.
+    ///
+    /// Layout (integers big-endian):
+    /// - leaf: u32 byte length, then the value as signed big-endian bytes
+    /// - u32 number of siblings
+    /// - direction bitmap: one bit per sibling, bit `i % 8` of byte `i / 8`,
+    ///   set when the sibling is on the right (`is_right_sibling`)
+    /// - siblings, each as u32 byte length then signed big-endian bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_length_prefixed(&mut bytes, &self.leaf);
+
+        bytes.extend_from_slice(&(self.path.len() as u32).to_be_bytes());
+        let mut bitmap = vec![0u8; self.path.len().div_ceil(8)];
+        for (i, (_, is_right_sibling)) in self.path.iter().enumerate() {
+            if *is_right_sibling {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes.extend_from_slice(&bitmap);
+
+        for (sibling, _) in &self.path {
+            write_length_prefixed(&mut bytes, sibling);
+        }
+        bytes
+    }
+
+    /// Deserializes a proof written by `to_bytes`.
+    ///
+    /// # Errors
+    /// `ZkMockError::MalformedProof` if the data is truncated or has trailing bytes.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ZkMockError> {
+        let mut cursor = data;
+        let leaf = read_length_prefixed(&mut cursor)?;
+
+        let count = read_u32(&mut cursor)? as usize;
+        let bitmap = take(&mut cursor, count.div_ceil(8))?.to_vec();
+
+        let mut path = Vec::with_capacity(count);
+        for i in 0..count {
+            let sibling = read_length_prefixed(&mut cursor)?;
+            path.push((sibling, bitmap[i / 8] & (1 << (i % 8)) != 0));
+        }
+
+        if !cursor.is_empty() {
+            return Err(ZkMockError::MalformedProof("trailing bytes after Merkle proof".to_string()));
+        }
+        Ok(MerkleProof { leaf, path })
+    }
+}
+
+/// Appends `value` as a u32 big-endian length followed by its signed big-endian bytes.
+fn write_length_prefixed(bytes: &mut Vec<u8>, value: &BigInt) {
+    let encoded = value.to_signed_bytes_be();
+    bytes.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&encoded);
+}
+
+/// Splits `len` bytes off the front of `cursor`.
+fn take<'a>(cursor: &mut &'a [u8], len: usize) -> Result<&'a [u8], ZkMockError> {
+    if cursor.len() < len {
+        return Err(ZkMockError::MalformedProof("Merkle proof is truncated".to_string()));
+    }
+    let (head, rest) = cursor.split_at(len);
+    *cursor = rest;
+    Ok(head)
+}
+
+fn read_u32(cursor: &mut &[u8]) -> Result<u32, ZkMockError> {
+    Ok(u32::from_be_bytes(take(cursor, 4)?.try_into().expect("took 4 bytes")))
+}
+
+fn read_length_prefixed(cursor: &mut &[u8]) -> Result<BigInt, ZkMockError> {
+    let len = read_u32(cursor)? as usize;
+    Ok(BigInt::from_signed_bytes_be(take(cursor, len)?))
+}
+
 /// A Merkle tree for cryptographic data verification.
 /// 
 /// Merkle trees allow efficient proof that data is part of a larger dataset
 /// by hashing pairs of nodes recursively to produce a single root hash.
 /// 
-/// Generic over `H`: any type implementing the `HashFunction` trait.
-pub struct MerkleTree<H: HashFunction> {
+/// Generic over `H`: any type implementing the `HashFunction` trait, and over the node
+/// type `N` it hashes, `BigInt` by default. Byte-oriented hashes can use `[u8; 32]` nodes
+/// (see `Sha256Hash`) to avoid the `BigInt` overhead.
+pub struct MerkleTree<H: HashFunction<N>, N = BigInt> {
     /// The root hash - cryptographic commitment to all leaf values
-    pub root: BigInt,
+    pub root: N,
     
     /// The original leaf values at the bottom of the tree
-    pub leaves: Vec<BigInt>,
+    pub leaves: Vec<N>,
     
     /// Hash function used to compute internal node hashes
-    hash_function: H
+    hash_function: H,
+
+    /// What happens to the last node of an odd-sized level
+    odd_node_strategy: OddNodeStrategy,
+
+    /// Number of children hashed into each internal node, 2 unless built with `new_with_arity`
+    arity: usize,
+
+    /// Every level above the leaves, bottom-up, the last one holding only the root (none for a single leaf);
+    /// kept so paths are read off directly and `update_leaf` only rehashes one node per level
+    levels: Vec<Vec<N>>
 }
 
+/// A Merkle tree over `BigInt` leaves whose hash function is chosen at runtime, e.g. from `hash_functions::by_name`,
+/// the same way `Circuit` holds its hash. Boxed hash functions implement `HashFunction` themselves, so it works exactly
+/// like any other `MerkleTree`: `DynMerkleTree::new(leaves, Box::new(CustomHash::default()))`.
+pub type DynMerkleTree = MerkleTree<Box<dyn HashFunction>>;
+
 impl<H: HashFunction> MerkleTree<H> {
+    /// Constructs a new Merkle tree, rejecting any negative leaf.
+    ///
+    /// Strict counterpart of `new` for trees whose leaves are later encoded as bytes:
+    /// callers are expected to run leaves through `canonicalize_leaf` first.
+    ///
+    /// # Errors
+    /// `ZkMockError::NegativeLeaf` naming the first negative leaf.
+    pub fn new_strict(leaves: Vec<BigInt>, hash_function: H) -> Result<Self, ZkMockError> {
+        if let Some((index, value)) = leaves.iter().enumerate().find(|(_, leaf)| leaf.sign() == Sign::Minus) {
+            return Err(ZkMockError::NegativeLeaf { index, value: value.clone() });
+        }
+
+        Ok(MerkleTree::new(leaves, hash_function))
+    }
+}
+
+impl<H: HashFunction<N>, N: Clone + PartialEq> MerkleTree<H, N> {
     /// Constructs a new Merkle tree from leaf values.
     /// 
     /// Computes the root hash by recursively hashing pairs of nodes
-    /// from the bottom up until a single root remains.
+    /// from the bottom up until a single root remains. A lone odd node is
+    /// promoted unchanged, see `new_with_strategy` for the alternative.
     /// 
     /// # Arguments
     /// * `leaves` - Vector of leaf node values
     /// * `hash_function` - Hash function instance to use
-    pub fn new(leaves: Vec<BigInt>, hash_function: H) -> Self {
-        // Compute root by passing references (& prevents moving ownership)
-        let root = MerkleTree::compute_root(&leaves, &hash_function);
-        
-        MerkleTree { root, leaves, hash_function }
+    pub fn new(leaves: Vec<N>, hash_function: H) -> Self {
+        MerkleTree::new_with_strategy(leaves, hash_function, OddNodeStrategy::Promote)
+    }
+
+    /// Constructs a new Merkle tree like `new`, treating the last node of odd-sized levels
+    /// according to `odd_node_strategy`. `merkle_path` follows the same strategy.
+    pub fn new_with_strategy(leaves: Vec<N>, hash_function: H, odd_node_strategy: OddNodeStrategy) -> Self {
+        // Compute the levels by passing references (& prevents moving ownership)
+        let levels = MerkleTree::compute_levels(&leaves, &hash_function, odd_node_strategy);
+
+        MerkleTree::from_levels(leaves, levels, hash_function, odd_node_strategy, 2)
+    }
+
+    /// Constructs an N-ary Merkle tree: every internal node hashes up to `arity` children with `HashFunction::hash_many`.
+    ///
+    /// Leaves are grouped `arity` at a time level by level; a trailing group of two or more nodes is hashed as is
+    /// and a lone trailing node is promoted unchanged. With the default `hash_many`, arity 2 gives the same root as `new`.
+    /// Prove membership with `merkle_path_nary` and `verify_proof_nary`. Panics if `arity` is below 2.
+    pub fn new_with_arity(leaves: Vec<N>, hash_function: H, arity: usize) -> Self {
+        assert!(arity >= 2, "a Merkle tree needs an arity of at least 2, got {}", arity);
+        let mut levels: Vec<Vec<N>> = Vec::new();
+        let mut nodes = &leaves;
+        while nodes.len() > 1 {
+            levels.push(MerkleTree::hash_level_nary(nodes, &hash_function, arity));
+            nodes = levels.last().expect("a level was just pushed");
+        }
+
+        MerkleTree::from_levels(leaves, levels, hash_function, OddNodeStrategy::Promote, arity)
+    }
+
+    /// Assembles a tree from its leaves and the levels computed above them, taking the root from the top level.
+    fn from_levels(leaves: Vec<N>, levels: Vec<Vec<N>>, hash_function: H, odd_node_strategy: OddNodeStrategy, arity: usize) -> Self {
+        let root = levels.last().unwrap_or(&leaves)[0].clone();
+
+        MerkleTree { root, leaves, hash_function, odd_node_strategy, arity, levels }
+    }
+
+    /// Replaces the leaf at `index` with `value`, rehashing only the nodes on its path to the root
+    /// (one per level) and updating `root`. The tree afterwards equals one rebuilt with the new leaf.
+    /// Panics if `index` is out of range, see `try_update_leaf`.
+    pub fn update_leaf(&mut self, index: usize, value: N) {
+        self.try_update_leaf(index, value).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Fallible counterpart of `update_leaf`.
+    ///
+    /// # Errors
+    /// `ZkMockError::IndexOutOfBounds` if `index` is not below the number of leaves.
+    pub fn try_update_leaf(&mut self, index: usize, value: N) -> Result<(), ZkMockError> {
+        self.check_index(index)?;
+        self.leaves[index] = value;
+
+        let mut current_index = index;
+        for level in 0..self.levels.len() {
+            let below = if level == 0 { &self.leaves } else { &self.levels[level - 1] };
+            let group_start = current_index - current_index % self.arity;
+            let group = &below[group_start..(group_start + self.arity).min(below.len())];
+            let parent = if self.arity == 2 {
+                MerkleTree::hash_level(group, &self.hash_function, self.odd_node_strategy)
+            } else {
+                MerkleTree::hash_level_nary(group, &self.hash_function, self.arity)
+            };
+
+            current_index /= self.arity;
+            self.levels[level][current_index] = parent.into_iter().next().expect("a group hashes to one node");
+        }
+
+        self.root = self.levels.last().unwrap_or(&self.leaves)[0].clone();
+        Ok(())
+    }
+
+    /// Returns the number of children hashed into each internal node.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Returns how the tree treats the last node of odd-sized levels.
+    pub fn odd_node_strategy(&self) -> OddNodeStrategy {
+        self.odd_node_strategy
     }
 
     /// Generates a Merkle proof (authentication path) for a specific leaf.
@@ -46,78 +327,885 @@ impl<H: HashFunction> MerkleTree<H> {
     /// Vector of (sibling_hash, is_right_sibling) tuples:
     /// - `sibling_hash`: The hash of the sibling node
     /// - `is_right_sibling`: true if sibling is on the right (you're left child)
-    pub fn merkle_path(&self, index: usize) -> Vec<(BigInt, bool)> {
+    ///
+    /// Binary trees only, panics for a tree built with an arity above 2 (use `merkle_path_nary`).
+    /// Panics if `index` is out of range, see `try_merkle_path`.
+    pub fn merkle_path(&self, index: usize) -> Vec<(N, bool)> {
+        self.try_merkle_path(index).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Fallible counterpart of `merkle_path`.
+    ///
+    /// # Errors
+    /// `ZkMockError::IndexOutOfBounds` if `index` is not below the number of leaves.
+    pub fn try_merkle_path(&self, index: usize) -> Result<Vec<(N, bool)>, ZkMockError> {
+        assert!(self.arity == 2, "merkle_path needs a binary tree, use merkle_path_nary for arity {}", self.arity);
+        self.check_index(index)?;
         let mut path = Vec::new();
         let mut current_index = index;
-        let mut nodes = self.leaves.clone();  // Working copy of nodes
-
-        // Traverse up the tree level by level until we reach the root
-        while nodes.len() > 1 {
-            // Build the next level up by hashing pairs of nodes
-            let next_level: Vec<BigInt> = nodes
-                .chunks(2)  // Split current level into pairs
-                .map(|chunk| {
-                    if chunk.len() == 2 {
-                        // Hash the pair together
-                        self.hash_function.hash(&chunk[0], &chunk[1])
-                    } else {
-                        // Odd node (no pair), promote it unchanged
-                        chunk[0].clone()
-                    }
-                })
-                .collect();
 
+        // Traverse up the cached levels until we reach the root
+        for nodes in std::iter::once(&self.leaves).chain(&self.levels).take_while(|nodes| nodes.len() > 1) {
             // Calculate sibling index: if we're even, sibling is +1; if odd, sibling is -1
-            let sibling_index = if current_index % 2 == 0 { 
+            let sibling_index = if current_index.is_multiple_of(2) { 
                 current_index + 1 
             } else { 
                 current_index - 1 
             };
             
-            // Add sibling to proof path if it exists
+            // Add sibling to proof path if it exists; a duplicated lone node is its own (right) sibling
             if sibling_index < nodes.len() {
                 path.push((
                     nodes[sibling_index].clone(),
-                    current_index % 2 == 0  // true means we're left child
+                    current_index.is_multiple_of(2)  // true means we're left child
                 ));
+            } else if self.odd_node_strategy == OddNodeStrategy::DuplicateLast {
+                path.push((nodes[current_index].clone(), true));
             }
 
             // Move to parent index (integer division by 2)
             current_index /= 2;
-            nodes = next_level;
         }
         
+        Ok(path)
+    }
+
+    /// Checks that `index` names one of the tree's leaves.
+    fn check_index(&self, index: usize) -> Result<(), ZkMockError> {
+        if index >= self.leaves.len() {
+            return Err(ZkMockError::IndexOutOfBounds { index, leaf_count: self.leaves.len() });
+        }
+        Ok(())
+    }
+
+    /// Generates the authentication path of the leaf at `index` in a tree of any arity.
+    ///
+    /// Returns one entry per level where the node has siblings, from the leaf level upwards: the siblings
+    /// (up to `arity - 1` of them) and the node's position among them. Levels where the node is a lone
+    /// trailing node contribute nothing, since it is promoted unchanged. Check it with `verify_proof_nary`.
+    /// Panics if `index` is out of range.
+    pub fn merkle_path_nary(&self, index: usize) -> Vec<NaryPathLevel<N>> {
+        self.check_index(index).unwrap_or_else(|error| panic!("{}", error));
+        let mut path = Vec::new();
+        let mut current_index = index;
+
+        for nodes in std::iter::once(&self.leaves).chain(&self.levels).take_while(|nodes| nodes.len() > 1) {
+            let group_start = current_index - current_index % self.arity;
+            let group = &nodes[group_start..(group_start + self.arity).min(nodes.len())];
+            if group.len() > 1 {
+                let position = current_index - group_start;
+                let siblings = group.iter().enumerate().filter(|(i, _)| *i != position).map(|(_, node)| node.clone()).collect();
+                path.push(NaryPathLevel { siblings, position });
+            }
+
+            current_index /= self.arity;
+        }
+
         path
     }
 
-    /// Computes the Merkle root by building the tree bottom-up.
+    /// Verifies that `leaf` belongs to the N-ary tree committed to by `root`, folding it up through a path
+    /// as returned by `merkle_path_nary`: on each level the node is put back at its position among the
+    /// siblings and the whole group is hashed with `hash_many`.
+    pub fn verify_proof_nary(root: &N, leaf: &N, path: &[NaryPathLevel<N>], hash_function: &H) -> bool {
+        let mut node = leaf.clone();
+        for level in path {
+            if level.position > level.siblings.len() {
+                return false;
+            }
+            let mut children = level.siblings.clone();
+            children.insert(level.position, node);
+            node = hash_function.hash_many(&children);
+        }
+
+        node == *root
+    }
+
+    /// Builds the inclusion proof (leaf plus authentication path) for the leaf at `index`.
+    /// Panics if `index` is out of range, see `try_merkle_proof`.
+    pub fn merkle_proof(&self, index: usize) -> MerkleProof<N> {
+        self.try_merkle_proof(index).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Fallible counterpart of `merkle_proof`.
+    ///
+    /// # Errors
+    /// `ZkMockError::IndexOutOfBounds` if `index` is not below the number of leaves.
+    pub fn try_merkle_proof(&self, index: usize) -> Result<MerkleProof<N>, ZkMockError> {
+        let path = self.try_merkle_path(index)?;
+        Ok(MerkleProof { leaf: self.leaves[index].clone(), path })
+    }
+
+    /// Builds one proof for all the leaves at `indices` (duplicates allowed, in any order), holding each sibling
+    /// needed by any of their paths once and leaving out nodes computable from the proven leaves themselves.
+    /// Check it with `verify_multiproof`.
+    ///
+    /// Binary trees only, panics for a tree built with an arity above 2.
+    /// Panics if an index is out of range, see `try_merkle_multiproof`.
+    pub fn merkle_multiproof(&self, indices: &[usize]) -> MultiProof<N> {
+        self.try_merkle_multiproof(indices).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Fallible counterpart of `merkle_multiproof`.
+    ///
+    /// # Errors
+    /// `ZkMockError::IndexOutOfBounds` naming the first index that is not below the number of leaves.
+    pub fn try_merkle_multiproof(&self, indices: &[usize]) -> Result<MultiProof<N>, ZkMockError> {
+        assert!(self.arity == 2, "merkle_multiproof needs a binary tree, arity {} is not supported", self.arity);
+        for &index in indices {
+            self.check_index(index)?;
+        }
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut siblings = Vec::new();
+        let mut known = indices.clone(); // nodes of the current level the verifier can compute
+        for nodes in std::iter::once(&self.leaves).chain(&self.levels).take_while(|nodes| nodes.len() > 1) {
+            for (position, &current_index) in known.iter().enumerate() {
+                let sibling_index = current_index ^ 1;
+                // A known left node's right sibling may be known too; a lone odd node needs no sibling
+                let sibling_known = known.get(position + 1) == Some(&sibling_index)
+                    || (position > 0 && known[position - 1] == sibling_index);
+                if sibling_index < nodes.len() && !sibling_known {
+                    siblings.push(nodes[sibling_index].clone());
+                }
+            }
+
+            known = known.iter().map(|index| index / 2).collect();
+            known.dedup();
+        }
+
+        let leaves = indices.iter().map(|&index| self.leaves[index].clone()).collect();
+        Ok(MultiProof { indices, leaves, leaf_count: self.leaves.len(), odd_node_strategy: self.odd_node_strategy, siblings })
+    }
+
+    /// Proves the leaf at `index` is not `claimed`, by returning the membership proof of the actual leaf:
+    /// the verifier checks it against the root and sees it differs, see `MerkleProof::verify_not_equal`.
+    ///
+    /// Returns None if `index` is out of range or the leaf there does equal `claimed`.
+    pub fn prove_not_equal(&self, index: usize, claimed: &N) -> Option<MerkleProof<N>> {
+        let leaf = self.leaves.get(index)?;
+        if leaf == claimed {
+            return None;
+        }
+        Some(self.merkle_proof(index))
+    }
+
+    /// Verifies that `leaf` is part of the tree committed to by `root`.
+    ///
+    /// Folds the leaf up through an authentication path as returned by `merkle_path`:
+    /// when `is_right_sibling` is true the current node is the left input to the hash,
+    /// otherwise it is the right input. The result must equal `root`.
+    ///
+    /// The path's length and bits are not checked against any leaf index here;
+    /// use `verify_proof_at` when the index and tree size are known.
+    ///
+    /// # Arguments
+    /// * `root` - The root the leaf is claimed to belong to
+    /// * `leaf` - The leaf value being proven
+    /// * `path` - Sibling hashes from the leaf level up to just below the root
+    /// * `hash_function` - Hash function the tree was built with
+    pub fn verify_proof(root: &N, leaf: &N, path: &[(N, bool)], hash_function: &H) -> bool {
+        let computed_root = path.iter().fold(leaf.clone(), |node, (sibling, is_right_sibling)| {
+            if *is_right_sibling {
+                hash_function.hash(&node, sibling)
+            } else {
+                hash_function.hash(sibling, &node)
+            }
+        });
+
+        computed_root == *root
+    }
+
+    /// Verifies `leaf` against `root` like `verify_proof`, after checking the path really leads to leaf `index`
+    /// of a tree with `leaf_count` leaves.
+    ///
+    /// The direction bits are otherwise only implied by `merkle_path`; this makes them an explicit invariant.
+    /// Walking up from `index`, each level where the node has a sibling contributes exactly one path entry,
+    /// and its bit must be true exactly when the node's index on that level is even (it is the left child).
+    /// Levels where the node is a lone odd node contribute nothing, since it is promoted unchanged.
+    /// For trees built with `OddNodeStrategy::DuplicateLast` use `verify_proof_at_with_strategy`.
+    ///
+    /// # Errors
+    /// `ZkMockError::InvalidMerklePath` if `index` is out of range, the path has more or fewer
+    /// siblings than the tree depth allows, or a direction bit disagrees with `index`.
+    pub fn verify_proof_at(root: &N, leaf: &N, index: usize, leaf_count: usize, path: &[(N, bool)], hash_function: &H) -> Result<bool, ZkMockError> {
+        MerkleTree::verify_proof_at_with_strategy(root, leaf, index, leaf_count, path, hash_function, OddNodeStrategy::Promote)
+    }
+
+    /// Like `verify_proof_at`, for a tree built with `odd_node_strategy`: under `DuplicateLast` a lone odd node
+    /// contributes an entry too, itself as the right sibling.
+    pub fn verify_proof_at_with_strategy(
+        root: &N, leaf: &N, index: usize, leaf_count: usize, path: &[(N, bool)], hash_function: &H, odd_node_strategy: OddNodeStrategy
+    ) -> Result<bool, ZkMockError> {
+        if index >= leaf_count {
+            return Err(ZkMockError::InvalidMerklePath(format!("leaf index {} is out of range for {} leaves", index, leaf_count)));
+        }
+
+        let mut expected_bits = Vec::new();
+        let (mut current_index, mut level_len) = (index, leaf_count);
+        while level_len > 1 {
+            if current_index.is_multiple_of(2) {
+                if current_index + 1 < level_len || odd_node_strategy == OddNodeStrategy::DuplicateLast {
+                    expected_bits.push(true);
+                }
+            } else {
+                expected_bits.push(false);
+            }
+            current_index /= 2;
+            level_len = level_len.div_ceil(2);
+        }
+
+        if path.len() != expected_bits.len() {
+            return Err(ZkMockError::InvalidMerklePath(format!(
+                "leaf {} of {} needs {} siblings, the path has {}", index, leaf_count, expected_bits.len(), path.len()
+            )));
+        }
+        if let Some(level) = path.iter().zip(&expected_bits).position(|((_, bit), expected)| bit != expected) {
+            return Err(ZkMockError::InvalidMerklePath(format!("direction bit {} does not match leaf index {}", level, index)));
+        }
+
+        Ok(MerkleTree::verify_proof(root, leaf, path, hash_function))
+    }
+
+    /// Verifies that every leaf of a proof from `merkle_multiproof` belongs to the tree committed to by `root`.
+    ///
+    /// Rebuilds the tree level by level from the proven leaves: a node whose sibling is also known is hashed
+    /// with it, any other node takes its sibling from the proof in order, and a lone odd node is promoted or
+    /// hashed with itself per the proof's strategy. The proof must use up every sibling and end at `root`.
+    /// Returns false for a proof of no leaves and for one whose indices are unsorted, repeated or out of range.
+    pub fn verify_multiproof(root: &N, proof: &MultiProof<N>, hash_function: &H) -> bool {
+        let well_formed = !proof.indices.is_empty()
+            && proof.indices.len() == proof.leaves.len()
+            && proof.indices.windows(2).all(|pair| pair[0] < pair[1])
+            && proof.indices.last().is_some_and(|&last| last < proof.leaf_count);
+        if !well_formed {
+            return false;
+        }
+
+        let mut siblings = proof.siblings.iter();
+        let mut known: Vec<(usize, N)> = proof.indices.iter().copied().zip(proof.leaves.iter().cloned()).collect();
+        let mut level_len = proof.leaf_count;
+        while level_len > 1 {
+            let mut parents = Vec::new();
+            let mut nodes = known.into_iter().peekable();
+            while let Some((current_index, node)) = nodes.next() {
+                let parent = if !current_index.is_multiple_of(2) {
+                    // The left sibling would have been consumed with it if it were known
+                    let Some(sibling) = siblings.next() else { return false };
+                    hash_function.hash(sibling, &node)
+                } else if current_index + 1 == level_len {
+                    match proof.odd_node_strategy {
+                        OddNodeStrategy::Promote => node,
+                        OddNodeStrategy::DuplicateLast => hash_function.hash(&node, &node)
+                    }
+                } else if let Some((_, sibling)) = nodes.next_if(|(next_index, _)| *next_index == current_index + 1) {
+                    hash_function.hash(&node, &sibling)
+                } else {
+                    let Some(sibling) = siblings.next() else { return false };
+                    hash_function.hash(&node, sibling)
+                };
+                parents.push((current_index / 2, parent));
+            }
+
+            known = parents;
+            level_len = level_len.div_ceil(2);
+        }
+
+        siblings.next().is_none() && known.first().is_some_and(|(_, node)| node == root)
+    }
+
+    /// Computes the levels above the leaves by building the tree bottom-up.
     /// 
     /// Repeatedly hashes pairs of nodes to create parent nodes,
-    /// continuing until only a single root node remains.
+    /// continuing until only a single root node remains; that level is the last one returned.
     /// 
     /// # Arguments
     /// * `leaves` - Reference to leaf values
     /// * `hash_function` - Reference to hash function
-    fn compute_root(leaves: &Vec<BigInt>, hash_function: &H) -> BigInt {
-        let mut nodes = leaves.clone();
-        
+    /// * `odd_node_strategy` - What to do with the last node of odd-sized levels
+    fn compute_levels(leaves: &[N], hash_function: &H, odd_node_strategy: OddNodeStrategy) -> Vec<Vec<N>> {
+        let mut levels: Vec<Vec<N>> = Vec::new();
+        let mut nodes = leaves;
+
         // Build successive levels by hashing pairs until one node remains
         while nodes.len() > 1 {
-            nodes = nodes
-                .chunks(2)  // Process in pairs
-                .map(|chunk| {
-                    if chunk.len() == 2 {
-                        // Hash the pair
-                        hash_function.hash(&chunk[0], &chunk[1])
-                    } else {
-                        // Single node (odd count), promote unchanged
-                        chunk[0].clone()
-                    }
-                })
-                .collect();
+            levels.push(MerkleTree::hash_level(nodes, hash_function, odd_node_strategy));
+            nodes = levels.last().expect("a level was just pushed");
         }
-        
-        // Return the final root node
-        nodes[0].clone()
+
+        levels
     }
-}
\ No newline at end of file
+
+    /// Hashes one level of an N-ary tree into the level above it: groups of `arity` nodes (the last one
+    /// possibly smaller) are hashed with `hash_many`, a lone trailing node is promoted unchanged.
+    fn hash_level_nary(nodes: &[N], hash_function: &H, arity: usize) -> Vec<N> {
+        nodes
+            .chunks(arity)
+            .map(|group| if group.len() == 1 { group[0].clone() } else { hash_function.hash_many(group) })
+            .collect()
+    }
+
+    /// Hashes one level of nodes into the level above it.
+    ///
+    /// Nodes are processed in pairs; a single trailing node (odd count)
+    /// is promoted unchanged or hashed with itself, per `odd_node_strategy`.
+    fn hash_level(nodes: &[N], hash_function: &H, odd_node_strategy: OddNodeStrategy) -> Vec<N> {
+        nodes
+            .chunks(2)  // Process in pairs
+            .map(|chunk| MerkleTree::hash_chunk(chunk, hash_function, odd_node_strategy))
+            .collect()
+    }
+
+    /// Hashes one chunk of a level, a pair or a single trailing node, into its parent.
+    fn hash_chunk(chunk: &[N], hash_function: &H, odd_node_strategy: OddNodeStrategy) -> N {
+        match (chunk, odd_node_strategy) {
+            // Hash the pair
+            ([left, right], _) => hash_function.hash(left, right),
+            // Single node (odd count), promote unchanged
+            ([node], OddNodeStrategy::Promote) => node.clone(),
+            // Single node (odd count), pair it with a copy of itself
+            ([node], OddNodeStrategy::DuplicateLast) => hash_function.hash(node, node),
+            _ => unreachable!("chunks(2) yields one or two nodes")
+        }
+    }
+}
+
+/// Smallest level (in nodes) `MerkleTree::new_parallel` spreads across threads.
+///
+/// Handing work to the thread pool costs far more than hashing a few hundred pairs, so smaller levels,
+/// including the top levels of every large tree, are hashed on the calling thread.
+#[cfg(feature = "parallel")]
+pub const PARALLEL_THRESHOLD: usize = 1024;
+
+#[cfg(feature = "parallel")]
+impl<H: HashFunction<N> + Sync, N: Clone + PartialEq + Send + Sync> MerkleTree<H, N> {
+    /// Constructs a new Merkle tree, hashing each level's pairs across rayon's thread pool.
+    ///
+    /// Produces exactly the same root as `new`: the level is split with `par_chunks(2)`, so pairs are
+    /// never split between threads and only the last chunk can be an odd node to promote. Levels
+    /// smaller than `PARALLEL_THRESHOLD` are hashed sequentially.
+    ///
+    /// # Arguments
+    /// * `leaves` - Vector of leaf node values
+    /// * `hash_function` - Hash function instance to use, shared by the threads
+    pub fn new_parallel(leaves: Vec<N>, hash_function: H) -> Self {
+        let levels = MerkleTree::compute_levels_parallel(&leaves, &hash_function);
+
+        MerkleTree::from_levels(leaves, levels, hash_function, OddNodeStrategy::Promote, 2)
+    }
+
+    /// Parallel counterpart of `compute_levels`.
+    fn compute_levels_parallel(leaves: &[N], hash_function: &H) -> Vec<Vec<N>> {
+        let mut levels: Vec<Vec<N>> = Vec::new();
+        let mut nodes = leaves;
+
+        while nodes.len() > 1 {
+            if nodes.len() < PARALLEL_THRESHOLD {
+                levels.push(MerkleTree::hash_level(nodes, hash_function, OddNodeStrategy::Promote));
+            } else {
+                levels.push(
+                    nodes
+                        .par_chunks(2)
+                        .map(|chunk| MerkleTree::hash_chunk(chunk, hash_function, OddNodeStrategy::Promote))
+                        .collect()
+                );
+            }
+            nodes = levels.last().expect("a level was just pushed");
+        }
+
+        levels
+    }
+}
+
+/// An append-only Merkle tree that only stores its right frontier.
+///
+/// The frontier holds, for every level, the root of the pending perfect subtree of
+/// `2^level` leaves (if any). Appending works like incrementing a binary counter, so
+/// both `append` and `root` take O(log n) time and memory, without keeping the leaves.
+///
+/// Roots match `MerkleTree` built over the same leaves: promoting a lone odd node
+/// is equivalent to hashing the perfect subtrees together from the right.
+pub struct IncrementalMerkleTree<H: HashFunction<N>, N = BigInt> {
+    /// `frontier[level]` is the root of a complete subtree of `2^level` leaves awaiting a sibling
+    frontier: Vec<Option<N>>,
+
+    /// Number of leaves appended so far
+    leaf_count: usize,
+
+    /// Hash function used to compute internal node hashes
+    hash_function: H
+}
+
+impl<H: HashFunction<N>, N: Clone> IncrementalMerkleTree<H, N> {
+    /// Creates an empty incremental tree.
+    pub fn new(hash_function: H) -> Self {
+        IncrementalMerkleTree { frontier: Vec::new(), leaf_count: 0, hash_function }
+    }
+
+    /// Returns the number of leaves appended so far.
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Returns true if no leaf has been appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Appends a leaf and returns the new root.
+    ///
+    /// Merges the new leaf with every complete subtree of the same size on the
+    /// frontier (carry propagation), then stores the result at the first free level.
+    pub fn append(&mut self, leaf: N) -> N {
+        let mut carry = leaf;
+        let mut level = 0;
+
+        while let Some(Some(left)) = self.frontier.get_mut(level).map(Option::take) {
+            carry = self.hash_function.hash(&left, &carry);
+            level += 1;
+        }
+
+        if level == self.frontier.len() {
+            self.frontier.push(None);
+        }
+        self.frontier[level] = Some(carry);
+        self.leaf_count += 1;
+
+        self.root().expect("tree is non-empty after an append")
+    }
+
+    /// Returns the current root, or None if the tree is empty.
+    ///
+    /// Folds the frontier from the smallest subtree upwards, each larger subtree
+    /// being the left input of the hash.
+    pub fn root(&self) -> Option<N> {
+        self.frontier.iter().flatten().fold(None, |acc, node| match acc {
+            None => Some(node.clone()),
+            Some(right) => Some(self.hash_function.hash(node, &right))
+        })
+    }
+}
+
+/// Default depth of a `SparseMerkleTree`: one leaf per 256-bit key.
+pub const SPARSE_TREE_DEPTH: usize = 256;
+
+/// A key-value Merkle tree over every key in `[0, 2^depth)`, only storing the nodes that differ from empty.
+///
+/// Leaf hashes:
+/// - an empty key holds `hash(0, 0)`, the empty-value hash;
+/// - a key holding `value` holds `hash(value, 1)`, the 1 marking the slot as occupied (so a stored 0 is not empty).
+///
+/// An empty subtree of height `h` hashes to `empty[h]` with `empty[0] = hash(0, 0)` and
+/// `empty[h + 1] = hash(empty[h], empty[h])`, so the tree is implicitly full without materializing its
+/// `2^depth` leaves. Bit `l` of a key picks the child at level `l` (0 left, 1 right), counting from the leaves.
+pub struct SparseMerkleTree<H: HashFunction> {
+    /// Non-empty nodes, keyed by (level counted from the leaves, index on that level)
+    nodes: HashMap<(usize, BigInt), BigInt>,
+
+    /// The values stored per key
+    values: HashMap<BigInt, BigInt>,
+
+    /// `empty[h]` is the hash of an empty subtree of height `h`, for h in `0..=depth`
+    empty: Vec<BigInt>,
+
+    /// Number of levels below the root
+    depth: usize,
+
+    /// Hash function used for leaves and internal nodes
+    hash_function: H
+}
+
+/// A sparse Merkle proof: the siblings of a key's leaf from the leaf level up, see `SparseMerkleTree::prove`.
+/// The same proof shows membership (with the stored value) and non-membership (with no value).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMerkleProof {
+    /// The key the proof is for
+    pub key: BigInt,
+
+    /// One sibling hash per level, from the leaf level up to just below the root
+    pub siblings: Vec<BigInt>
+}
+
+impl SparseMerkleProof {
+    /// Checks that the tree committed to by `root` holds `value` at the proof's key,
+    /// or nothing at all when `value` is None.
+    /// A negative key, or one with more bits than the proof has levels, never verifies: the path would only
+    /// authenticate its low bits, so a proof for k would pass for k + 2^depth too.
+    pub fn verify<H: HashFunction>(&self, root: &BigInt, value: Option<&BigInt>, hash_function: &H) -> bool {
+        if self.key.sign() == Sign::Minus || self.key.bits() > self.siblings.len() as u64 {
+            return false;
+        }
+
+        let leaf = sparse_leaf_hash(value, hash_function);
+        let computed_root = self.siblings.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+            if (&self.key >> level).is_even() {
+                hash_function.hash(&node, sibling)
+            } else {
+                hash_function.hash(sibling, &node)
+            }
+        });
+
+        computed_root == *root
+    }
+}
+
+/// The leaf hash of a sparse tree slot holding `value`, or of an empty slot.
+fn sparse_leaf_hash<H: HashFunction>(value: Option<&BigInt>, hash_function: &H) -> BigInt {
+    match value {
+        Some(value) => hash_function.hash(value, &BigInt::from(1)),
+        None => hash_function.hash(&BigInt::from(0), &BigInt::from(0))
+    }
+}
+
+impl<H: HashFunction> SparseMerkleTree<H> {
+    /// Creates an empty tree of depth `SPARSE_TREE_DEPTH` (256).
+    pub fn new(hash_function: H) -> Self {
+        SparseMerkleTree::with_depth(hash_function, SPARSE_TREE_DEPTH)
+    }
+
+    /// Creates an empty tree with keys in `[0, 2^depth)`.
+    pub fn with_depth(hash_function: H, depth: usize) -> Self {
+        let mut empty = vec![sparse_leaf_hash(None, &hash_function)];
+        for height in 0..depth {
+            empty.push(hash_function.hash(&empty[height], &empty[height]));
+        }
+
+        SparseMerkleTree { nodes: HashMap::new(), values: HashMap::new(), empty, depth, hash_function }
+    }
+
+    /// Returns the number of levels below the root.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Stores `value` at `key`, replacing any previous value, and rehashes the key's path to the root.
+    /// Panics if `key` is negative or not below `2^depth`.
+    pub fn insert(&mut self, key: BigInt, value: BigInt) {
+        self.check_key(&key);
+        let mut node = sparse_leaf_hash(Some(&value), &self.hash_function);
+        self.values.insert(key.clone(), value);
+
+        let mut index = key;
+        for level in 0..self.depth {
+            self.nodes.insert((level, index.clone()), node.clone());
+            let sibling = self.node(level, &(&index ^ BigInt::from(1)));
+            node = if index.is_even() {
+                self.hash_function.hash(&node, &sibling)
+            } else {
+                self.hash_function.hash(&sibling, &node)
+            };
+            index >>= 1;
+        }
+        self.nodes.insert((self.depth, index), node);
+    }
+
+    /// Returns the value stored at `key`, if any.
+    pub fn get(&self, key: &BigInt) -> Option<&BigInt> {
+        self.values.get(key)
+    }
+
+    /// Returns the root hash, `empty[depth]` for an empty tree.
+    pub fn root(&self) -> BigInt {
+        self.node(self.depth, &BigInt::from(0))
+    }
+
+    /// Builds the proof for `key`: a membership proof if it holds a value, a non-membership proof otherwise.
+    /// Verify it with `SparseMerkleProof::verify`, passing `get(key)`. Panics if `key` is out of range.
+    pub fn prove(&self, key: &BigInt) -> SparseMerkleProof {
+        self.check_key(key);
+        let siblings = (0..self.depth)
+            .map(|level| self.node(level, &((key >> level) ^ BigInt::from(1))))
+            .collect();
+
+        SparseMerkleProof { key: key.clone(), siblings }
+    }
+
+    /// Returns the node at `index` on `level`, falling back to the empty-subtree hash.
+    fn node(&self, level: usize, index: &BigInt) -> BigInt {
+        self.nodes.get(&(level, index.clone())).cloned().unwrap_or_else(|| self.empty[level].clone())
+    }
+
+    /// Panics unless `key` is in `[0, 2^depth)`.
+    fn check_key(&self, key: &BigInt) {
+        assert!(
+            key.sign() != Sign::Minus && key.bits() <= self.depth as u64,
+            "sparse Merkle key {} is outside [0, 2^{})", key, self.depth
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_functions::{CustomHash, ModSquareHash, Sha256Hash, SimpleAddHash};
+
+    fn int(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    #[test]
+    fn strict_trees_reject_negative_leaves_but_accept_their_canonical_form() {
+        let modulus = int(97);
+        let result = MerkleTree::new_strict(vec![int(1), int(-5)], SimpleAddHash::default());
+        assert!(matches!(result, Err(ZkMockError::NegativeLeaf { index: 1, .. })));
+
+        let reduced = canonicalize_leaf(&int(-5), &modulus);
+        assert_eq!(reduced, int(92));
+        let tree = MerkleTree::new_strict(vec![int(1), reduced], SimpleAddHash::default()).unwrap();
+        assert_eq!(tree.root, int(93));
+    }
+
+    #[test]
+    fn incremental_roots_match_full_trees_of_every_size() {
+        let mut incremental = IncrementalMerkleTree::new(ModSquareHash::default());
+        assert!(incremental.is_empty() && incremental.root().is_none());
+
+        let leaves: Vec<BigInt> = (1..=17).map(int).collect();
+        for size in 1..=leaves.len() {
+            let root = incremental.append(leaves[size - 1].clone());
+            assert_eq!(root, MerkleTree::new(leaves[..size].to_vec(), ModSquareHash::default()).root, "size {}", size);
+            assert_eq!(incremental.len(), size);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_construction_matches_serial_for_every_size_up_to_1024() {
+        let leaves: Vec<BigInt> = (0..4099).map(|leaf| int(leaf * 7 + 3)).collect();
+        for size in (1..=1024).chain([1025, 2047, 4099]) {
+            let serial = MerkleTree::new(leaves[..size].to_vec(), ModSquareHash::default());
+            let parallel = MerkleTree::new_parallel(leaves[..size].to_vec(), ModSquareHash::default());
+            assert_eq!(parallel.root, serial.root, "size {}", size);
+            assert_eq!(parallel.levels, serial.levels, "size {}", size);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_and_serial_roots_match_on_1024_random_leaves() {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let leaves: Vec<[u8; 32]> = (0..1024).map(|_| {
+            let mut leaf = [0u8; 32];
+            rng.fill(&mut leaf);
+            leaf
+        }).collect();
+        let serial = MerkleTree::new(leaves.clone(), Sha256Hash);
+        let parallel = MerkleTree::new_parallel(leaves, Sha256Hash);
+        assert_eq!(parallel.root, serial.root);
+    }
+
+    #[test]
+    fn merkle_proof_bytes_round_trip_and_still_verify() {
+        // 11 leaves, some negative, give paths with siblings on both sides
+        let leaves: Vec<BigInt> = (-3..8).map(int).collect();
+        let tree = MerkleTree::new(leaves, ModSquareHash::default());
+        for index in [0, 6, 10] {
+            let proof = tree.merkle_proof(index);
+            let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(decoded.verify(&tree.root, &ModSquareHash::default()));
+        }
+
+        let bytes = tree.merkle_proof(6).to_bytes();
+        assert!(matches!(MerkleProof::from_bytes(&bytes[..bytes.len() - 1]), Err(ZkMockError::MalformedProof(_))));
+    }
+
+    #[test]
+    fn verify_proof_at_rejects_paths_that_do_not_fit_the_index() {
+        let hash = ModSquareHash::default();
+        let tree = MerkleTree::new((1..=5).map(int).collect(), ModSquareHash::default());
+        let mut path = tree.merkle_path(1);
+        assert!(MerkleTree::verify_proof_at(&tree.root, &int(2), 1, 5, &path, &hash).unwrap());
+
+        path.push((int(7), true));
+        assert!(matches!(MerkleTree::verify_proof_at(&tree.root, &int(2), 1, 5, &path, &hash), Err(ZkMockError::InvalidMerklePath(_))));
+
+        let mut flipped = tree.merkle_path(1);
+        flipped[0].1 = !flipped[0].1;
+        assert!(matches!(MerkleTree::verify_proof_at(&tree.root, &int(2), 1, 5, &flipped, &hash), Err(ZkMockError::InvalidMerklePath(_))));
+        assert!(matches!(MerkleTree::verify_proof_at(&tree.root, &int(2), 5, 5, &path, &hash), Err(ZkMockError::InvalidMerklePath(_))));
+    }
+
+    #[test]
+    fn byte_trees_build_consistent_roots_and_proofs() {
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        let tree = MerkleTree::new(leaves.clone(), Sha256Hash);
+        assert_eq!(MerkleTree::new(leaves.clone(), Sha256Hash).root, tree.root);
+
+        let left = Sha256Hash.hash(&leaves[0], &leaves[1]);
+        let right = Sha256Hash.hash(&leaves[2], &leaves[3]);
+        let expected = Sha256Hash.hash(&Sha256Hash.hash(&left, &right), &leaves[4]);
+        assert_eq!(tree.root, expected);
+
+        for index in 0..leaves.len() {
+            assert!(tree.merkle_proof(index).verify(&tree.root, &Sha256Hash));
+        }
+        assert!(!MerkleTree::verify_proof(&tree.root, &[9; 32], &tree.merkle_path(2), &Sha256Hash));
+    }
+
+    #[test]
+    fn verify_proof_rejects_a_tampered_sibling_and_a_wrong_leaf() {
+        let hash = ModSquareHash::default();
+        let tree = MerkleTree::new((1..=8).map(int).collect(), ModSquareHash::default());
+        let path = tree.merkle_path(5);
+        assert!(MerkleTree::verify_proof(&tree.root, &int(6), &path, &hash));
+
+        let mut tampered = path.clone();
+        tampered[1].0 += 1;
+        assert!(!MerkleTree::verify_proof(&tree.root, &int(6), &tampered, &hash));
+        assert!(!MerkleTree::verify_proof(&tree.root, &int(5), &path, &hash));
+    }
+
+    #[test]
+    fn prove_not_equal_shows_the_real_leaf_differs_from_the_claim() {
+        let hash = ModSquareHash::default();
+        let tree = MerkleTree::new((1..=6).map(int).collect(), ModSquareHash::default());
+        let proof = tree.prove_not_equal(2, &int(9)).unwrap();
+        assert_eq!(proof.leaf, int(3));
+        assert!(proof.verify(&tree.root, &hash));
+        assert!(proof.verify_not_equal(&tree.root, &int(9), &hash));
+
+        assert!(tree.prove_not_equal(2, &int(3)).is_none());
+        assert!(!proof.verify_not_equal(&tree.root, &int(3), &hash));
+    }
+
+    #[test]
+    fn odd_node_strategies_give_different_roots_and_separate_proofs() {
+        let hash = ModSquareHash::default();
+        let leaves: Vec<BigInt> = (1..=3).map(int).collect();
+        let promote = MerkleTree::new_with_strategy(leaves.clone(), ModSquareHash::default(), OddNodeStrategy::Promote);
+        let duplicate = MerkleTree::new_with_strategy(leaves, ModSquareHash::default(), OddNodeStrategy::DuplicateLast);
+
+        let left = hash.hash(&int(1), &int(2));
+        assert_eq!(promote.root, hash.hash(&left, &int(3)));
+        assert_eq!(duplicate.root, hash.hash(&left, &hash.hash(&int(3), &int(3))));
+        assert_ne!(promote.root, duplicate.root);
+
+        for (tree, other) in [(&promote, &duplicate), (&duplicate, &promote)] {
+            for index in 0..3 {
+                let path = tree.merkle_path(index);
+                assert!(MerkleTree::verify_proof(&tree.root, &int(index as i64 + 1), &path, &hash));
+                assert!(!MerkleTree::verify_proof(&other.root, &int(index as i64 + 1), &path, &hash));
+            }
+        }
+    }
+
+    #[test]
+    fn nary_trees_of_arity_two_and_four_round_trip_through_the_verifier() {
+        let hash = ModSquareHash::default();
+        let leaves: Vec<BigInt> = (1..=11).map(int).collect();
+        for arity in [2, 4] {
+            let tree = MerkleTree::new_with_arity(leaves.clone(), ModSquareHash::default(), arity);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let path = tree.merkle_path_nary(index);
+                assert!(path.iter().all(|level| level.siblings.len() < arity && level.position <= level.siblings.len()));
+                assert!(MerkleTree::verify_proof_nary(&tree.root, leaf, &path, &hash), "arity {} leaf {}", arity, index);
+                assert!(!MerkleTree::verify_proof_nary(&tree.root, &(leaf + 1), &path, &hash));
+            }
+        }
+
+        // arity 2 is the binary tree, and a full group of 4 hashes its children together
+        assert_eq!(MerkleTree::new_with_arity(leaves.clone(), ModSquareHash::default(), 2).root, MerkleTree::new(leaves, ModSquareHash::default()).root);
+        let quad = MerkleTree::new_with_arity((1..=4).map(int).collect(), ModSquareHash::default(), 4);
+        assert_eq!(quad.root, hash.hash_many(&[int(1), int(2), int(3), int(4)]));
+    }
+
+    #[test]
+    fn out_of_range_leaf_index_is_a_clean_error() {
+        let tree = MerkleTree::new((1..=4).map(int).collect(), ModSquareHash::default());
+        let error = tree.try_merkle_path(10).unwrap_err();
+        assert!(matches!(error, ZkMockError::IndexOutOfBounds { index: 10, leaf_count: 4 }));
+        assert_eq!(error.to_string(), "Leaf index 10 is out of bounds for a tree with 4 leaves");
+        assert!(matches!(tree.try_merkle_proof(4), Err(ZkMockError::IndexOutOfBounds { index: 4, leaf_count: 4 })));
+        assert_eq!(tree.try_merkle_path(3).unwrap(), tree.merkle_path(3));
+    }
+
+    #[test]
+    fn sparse_tree_proves_membership_and_non_membership() {
+        let hash = ModSquareHash::default();
+        let mut tree = SparseMerkleTree::new(ModSquareHash::default());
+        let empty_root = tree.root();
+        tree.insert(int(5), int(50));
+        tree.insert(int(1) << 200, int(0));
+        assert_ne!(tree.root(), empty_root);
+
+        for key in [int(5), int(1) << 200] {
+            let proof = tree.prove(&key);
+            assert_eq!(proof.siblings.len(), 256);
+            assert!(proof.verify(&tree.root(), tree.get(&key), &hash));
+            assert!(!proof.verify(&tree.root(), None, &hash)); // a stored 0 is not an empty slot
+        }
+
+        let absent = tree.prove(&int(6));
+        assert!(tree.get(&int(6)).is_none());
+        assert!(absent.verify(&tree.root(), None, &hash));
+        assert!(!absent.verify(&tree.root(), Some(&int(50)), &hash));
+        assert!(!absent.verify(&empty_root, None, &hash));
+    }
+
+    #[test]
+    fn update_leaf_matches_a_rebuilt_tree() {
+        for size in [1, 2, 5, 8, 13] {
+            let mut leaves: Vec<BigInt> = (0..size).map(|leaf| int(leaf * 3 + 1)).collect();
+            let mut tree = MerkleTree::new(leaves.clone(), ModSquareHash::default());
+            for index in 0..leaves.len() {
+                leaves[index] = int(100 + index as i64);
+                tree.update_leaf(index, leaves[index].clone());
+                let rebuilt = MerkleTree::new(leaves.clone(), ModSquareHash::default());
+                assert_eq!(tree.root, rebuilt.root, "size {} index {}", size, index);
+                assert_eq!(tree.levels, rebuilt.levels, "size {} index {}", size, index);
+            }
+        }
+    }
+
+    #[test]
+    fn multiproof_of_two_sibling_leaves_shares_their_path() {
+        let leaves: Vec<BigInt> = (1..=8).map(int).collect();
+        let tree = MerkleTree::new(leaves.clone(), ModSquareHash::default());
+        let proof = tree.merkle_multiproof(&[0, 1]);
+        let separate = tree.merkle_path(0).len() + tree.merkle_path(1).len();
+        assert_eq!(proof.siblings.len(), 2);
+        assert!(proof.siblings.len() < separate);
+        assert!(MerkleTree::verify_multiproof(&tree.root, &proof, &ModSquareHash::default()));
+
+        let mut tampered = proof.clone();
+        tampered.leaves[1] = int(99);
+        assert!(!MerkleTree::verify_multiproof(&tree.root, &tampered, &ModSquareHash::default()));
+
+        let scattered = tree.merkle_multiproof(&[6, 1, 3]);
+        assert_eq!(scattered.indices, vec![1, 3, 6]);
+        assert!(MerkleTree::verify_multiproof(&tree.root, &scattered, &ModSquareHash::default()));
+    }
+
+    #[test]
+    fn dyn_tree_with_a_boxed_custom_hash_matches_the_concrete_one() {
+        let leaves: Vec<BigInt> = (1..=6).map(int).collect();
+        let custom = || CustomHash::new(int(3), int(5), Some(int(1_000_003)));
+        let boxed = DynMerkleTree::new(leaves.clone(), Box::new(custom()));
+        let concrete = MerkleTree::new(leaves.clone(), custom());
+        assert_eq!(boxed.root, concrete.root);
+        assert_eq!(boxed.merkle_path(4), concrete.merkle_path(4));
+        assert!(DynMerkleTree::verify_proof_at(&boxed.root, &leaves[4], 4, leaves.len(), &boxed.merkle_path(4), &boxed.hash_function).unwrap());
+
+        let by_name = DynMerkleTree::new(leaves, crate::hash_functions::by_name("custom").expect("custom is a known hash"));
+        assert_eq!(by_name.root, MerkleTree::new((1..=6).map(int).collect(), CustomHash::default()).root);
+    }
+
+    #[test]
+    fn sparse_proofs_do_not_verify_for_aliased_or_negative_keys() {
+        let hash = ModSquareHash::default();
+        let mut tree = SparseMerkleTree::new(ModSquareHash::default());
+        tree.insert(int(5), int(50));
+        let proof = tree.prove(&int(5));
+        assert!(proof.verify(&tree.root(), Some(&int(50)), &hash));
+
+        // the same path for k + 2^256 would only check the low 256 bits
+        let aliased = SparseMerkleProof { key: int(5) + (int(1) << 256), ..proof.clone() };
+        assert!(!aliased.verify(&tree.root(), Some(&int(50)), &hash));
+        let negative = SparseMerkleProof { key: int(-5), ..proof };
+        assert!(!negative.verify(&tree.root(), Some(&int(50)), &hash));
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use num_bigint::{BigInt, Sign};
+use serde::{Deserialize, Serialize};
+use crate::circuit::Circuit;
+use crate::error::{ConstraintError, ZkMockError};
+use crate::hash_functions::HashFunction;
+use crate::r1cs::{Operation, R1CS};
+use crate::transcript::Transcript;
+
+/// The contents of a proof file, written by `Circuit::generate_proof` and read back by the verifier.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Proof{
+  pub valid: bool, // whether the witness satisfied every constraint at proving time
+  pub circuit_hash: BigInt, // hash of the circuit structure the proof was generated for
+  pub challenge: BigInt, // Fiat-Shamir challenge squeezed from the rest of the proof, see `fiat_shamir_transcript`
+  pub public_inputs: Vec<BigInt>, // values of the public inputs the proof was generated with, in the order they were added; empty if committed to
+  pub public_inputs_root: Option<BigInt>, // Merkle root over the public input values, carried instead of them if the circuit commits to them
+  pub witness_root: Option<BigInt>, // Merkle root over every wire value, if the circuit has a hash function
+  pub outputs: BTreeMap<String, BigInt>, // values of the named output wires, ordered by name so the bytes are deterministic
+  pub r1cs: Vec<u8>, // bincode-encoded R1CS with the full witness, which the verifier re-checks (see `R1CS::to_bytes`)
+  #[serde(skip)]
+  pub transcript: Transcript, // prover-side log of the values absorbed while proving, not part of the proof file
+  #[serde(skip)]
+  pub warnings: Vec<String> // likely mistakes noticed while proving, e.g. an empty circuit outside strict mode; not part of the proof file
+}
+
+// Proofs are equal when their files would be: the prover-side transcript does not survive a save and load
+impl PartialEq for Proof {
+  fn eq(&self, other: &Self) -> bool {
+    self.to_bytes() == other.to_bytes()
+  }
+}
+
+impl Proof {
+  /// Returns the transcript recorded while proving; empty for a proof loaded from a file.
+  pub fn transcript(&self) -> &Transcript {
+    &self.transcript
+  }
+
+  /**
+   * Records what the verifier gets to see (circuit hash, public inputs, public input root, witness root and named
+   * outputs, in that order) in a SHA-256 transcript and squeezes the proof's challenge from it, returning both.
+   *
+   * The prover stores the challenge in the proof and the verifier re-derives it (see `challenge_matches`), so a proof
+   * whose statement was altered after proving no longer carries the challenge it would have been given.
+   */
+  pub fn fiat_shamir_transcript(&self) -> (Transcript, BigInt) {
+    let mut transcript = Transcript::new();
+    transcript.append("circuit_hash", &self.circuit_hash);
+    for value in &self.public_inputs {
+      transcript.append("public_input", value);
+    }
+    if let Some(root) = &self.public_inputs_root {
+      transcript.append("public_inputs_root", root);
+    }
+    if let Some(root) = &self.witness_root {
+      transcript.append("witness_root", root);
+    }
+    for (name, value) in &self.outputs {
+      transcript.append(name, value);
+    }
+    let challenge = transcript.challenge("challenge");
+    (transcript, challenge)
+  }
+
+  /// Returns true if the proof carries the challenge its own transcript derives, see `fiat_shamir_transcript`.
+  pub fn challenge_matches(&self) -> bool {
+    self.fiat_shamir_transcript().1 == self.challenge
+  }
+
+  /// Returns the warnings raised while proving, e.g. that the circuit has no gates; empty for a proof loaded from a file.
+  pub fn warnings(&self) -> &[String] {
+    &self.warnings
+  }
+
+  /// Returns the number of constraints in the R1CS the proof carries, 0 if it does not decode.
+  pub fn constraint_count(&self) -> usize {
+    R1CS::from_bytes(&self.r1cs).map_or(0, |r1cs| r1cs.constraints.len())
+  }
+
+  /// Serializes the proof, the bytes `save` writes: a `ProofHeader` followed by the bincode-encoded proof.
+  /// Panics if the proof has more constraints than the header can count, see `try_to_bytes`.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    self.try_to_bytes().unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `to_bytes`: fails with `ZkMockError::ProofTooLarge` if the constraint count does not fit the header's u32.
+  pub fn try_to_bytes(&self) -> Result<Vec<u8>, ZkMockError> {
+    let constraint_count = u32_count(self.constraint_count(), "constraints")?;
+    let mut bytes = ProofHeader { version: PROOF_FORMAT_VERSION, constraint_count }.to_bytes();
+    bytes.extend(bincode::serialize(self).expect("Unable to serialize proof"));
+    Ok(bytes)
+  }
+
+  /// Decodes a proof written by `to_bytes`, checking its header first.
+  ///
+  /// # Errors
+  /// - `ZkMockError::MalformedProof` if the header is truncated or lacks the magic number, the payload does not
+  ///   decode, or the payload's constraint count differs from the header's;
+  /// - `ZkMockError::UnsupportedProofVersion` if the file is in another format version.
+  pub fn from_bytes(data: &[u8]) -> Result<Proof, ZkMockError> {
+    let header = ProofHeader::from_bytes(data)?;
+    if header.version != PROOF_FORMAT_VERSION {
+      return Err(ZkMockError::UnsupportedProofVersion { found: header.version, supported: PROOF_FORMAT_VERSION });
+    }
+
+    let proof: Proof = bincode::deserialize(&data[PROOF_HEADER_BYTES..]).map_err(|e| ZkMockError::MalformedProof(e.to_string()))?;
+    if proof.constraint_count() != header.constraint_count as usize {
+      return Err(ZkMockError::MalformedProof(format!(
+        "header announces {} constraints, the proof carries {}", header.constraint_count, proof.constraint_count()
+      )));
+    }
+    Ok(proof)
+  }
+
+  /// Serializes the proof (see `to_bytes`) and writes it to `proof_file`.
+  /// Panics if the file cannot be written, see `try_save`.
+  pub fn save(&self, proof_file: &str) {
+    self.try_save(proof_file).unwrap_or_else(|error| panic!("{}", error));
+  }
+
+  /// Fallible counterpart of `save`, also failing like `try_to_bytes`.
+  pub fn try_save(&self, proof_file: &str) -> Result<(), ZkMockError> {
+    let bytes = self.try_to_bytes()?;
+    let mut file = File::create(proof_file)?;
+    file.write_all(&bytes)?;
+    Ok(())
+  }
+
+  /// Reads and deserializes a proof previously written by `save`.
+  pub fn load(proof_file: &str) -> Proof {
+    Proof::try_load(proof_file).unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Fallible counterpart of `load`.
+  pub fn try_load(proof_file: &str) -> Result<Proof, ZkMockError> {
+    Proof::from_bytes(&std::fs::read(proof_file)?)
+  }
+}
+
+/// Magic number opening every proof file, "ZKMP".
+pub const PROOF_MAGIC: [u8; 4] = *b"ZKMP";
+
+/// Version of the proof file format written by `Proof::to_bytes`; files of any other version are rejected.
+pub const PROOF_FORMAT_VERSION: u16 = 3;
+
+/// Size in bytes of the `ProofHeader`.
+pub const PROOF_HEADER_BYTES: usize = 10;
+
+/**
+ * The header in front of every proof file, so a file of another format (or no proof at all) is rejected
+ * instead of misread. Layout (integers big-endian):
+ * - magic: the 4 bytes of `PROOF_MAGIC`;
+ * - version: u16 format version;
+ * - constraint count: u32 number of constraints in the proof's R1CS.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofHeader {
+  pub version: u16,
+  pub constraint_count: u32
+}
+
+impl ProofHeader {
+  /// Encodes the header, magic number included.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = PROOF_MAGIC.to_vec();
+    bytes.extend_from_slice(&self.version.to_be_bytes());
+    bytes.extend_from_slice(&self.constraint_count.to_be_bytes());
+    bytes
+  }
+
+  /// Reads the header at the start of `data`, without checking the version.
+  ///
+  /// # Errors
+  /// `ZkMockError::MalformedProof` if `data` is shorter than a header or does not start with `PROOF_MAGIC`.
+  pub fn from_bytes(data: &[u8]) -> Result<ProofHeader, ZkMockError> {
+    if data.len() < PROOF_HEADER_BYTES {
+      return Err(ZkMockError::MalformedProof(format!("{} bytes is too short for a proof header", data.len())));
+    }
+    if data[..4] != PROOF_MAGIC {
+      return Err(ZkMockError::MalformedProof("missing proof header, this is not a proof file".to_string()));
+    }
+    Ok(ProofHeader {
+      version: u16::from_be_bytes([data[4], data[5]]),
+      constraint_count: u32::from_be_bytes(data[6..PROOF_HEADER_BYTES].try_into().expect("took 4 bytes"))
+    })
+  }
+}
+
+/// Converts a length or count into the u32 the proof formats store it as.
+fn u32_count(count: usize, what: &'static str) -> Result<u32, ZkMockError> {
+  u32::try_from(count).map_err(|_| ZkMockError::ProofTooLarge { what, count })
+}
+
+/// Size in bytes of a field element in the fixed-width proof encoding.
+pub const FIELD_BYTES: usize = 32;
+
+/// Encodes a value as a 32-byte big-endian field element, the layout most external tools expect.
+/// Fails for negative values and values that need more than 32 bytes.
+pub fn to_field_bytes(value: &BigInt) -> Result<[u8; FIELD_BYTES], ZkMockError> {
+  let (sign, magnitude) = value.to_bytes_be();
+  if sign == Sign::Minus {
+    return Err(ZkMockError::FieldEncoding(format!("{} is negative", value)));
+  }
+  if magnitude.len() > FIELD_BYTES {
+    return Err(ZkMockError::FieldEncoding(format!("{} needs {} bytes, more than {}", value, magnitude.len(), FIELD_BYTES)));
+  }
+
+  let mut bytes = [0u8; FIELD_BYTES];
+  bytes[FIELD_BYTES - magnitude.len()..].copy_from_slice(&magnitude);
+  Ok(bytes)
+}
+
+/// Decodes a 32-byte big-endian field element.
+pub fn from_field_bytes(bytes: &[u8; FIELD_BYTES]) -> BigInt {
+  BigInt::from_bytes_be(Sign::Plus, bytes)
+}
+
+/// Sequential reader over a fixed-width encoded proof.
+struct FixedWidthReader<'a> {
+  data: &'a [u8]
+}
+
+impl FixedWidthReader<'_> {
+  fn take(&mut self, len: usize) -> Result<&[u8], ZkMockError> {
+    if self.data.len() < len {
+      return Err(ZkMockError::MalformedProof("fixed-width proof is truncated".to_string()));
+    }
+    let (head, rest) = self.data.split_at(len);
+    self.data = rest;
+    Ok(head)
+  }
+
+  fn byte(&mut self) -> Result<u8, ZkMockError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<u32, ZkMockError> {
+    Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("took 4 bytes")))
+  }
+
+  fn field(&mut self) -> Result<BigInt, ZkMockError> {
+    Ok(from_field_bytes(self.take(FIELD_BYTES)?.try_into().expect("took 32 bytes")))
+  }
+}
+
+impl Proof {
+  /**
+   * Encodes the proof with every value as a fixed 32-byte big-endian field element, for tools that
+   * expect field-sized values instead of bincode's variable-length `BigInt` form.
+   *
+   * Layout (integers big-endian):
+   * - valid: 1 byte (0 or 1)
+   * - circuit_hash: 32 bytes
+   * - challenge: 32 bytes
+   * - public_inputs: u32 count, then a 32-byte value per public input
+   * - public_inputs_root: 1 presence byte, followed by 32 bytes if present
+   * - witness_root: 1 presence byte, followed by 32 bytes if present
+   * - outputs: u32 count, then per output a u32 name length, the UTF-8 name and a 32-byte value
+   * - r1cs: u32 length, then the bincode-encoded R1CS as is
+   *
+   * Fails with `ZkMockError::FieldEncoding` if any value is negative or wider than 32 bytes, and with
+   * `ZkMockError::ProofTooLarge` if a count or length does not fit its u32.
+   */
+  pub fn to_fixed_width_bytes(&self) -> Result<Vec<u8>, ZkMockError> {
+    let mut bytes = vec![self.valid as u8];
+    bytes.extend_from_slice(&to_field_bytes(&self.circuit_hash)?);
+    bytes.extend_from_slice(&to_field_bytes(&self.challenge)?);
+    bytes.extend_from_slice(&u32_count(self.public_inputs.len(), "public inputs")?.to_be_bytes());
+    for value in &self.public_inputs {
+      bytes.extend_from_slice(&to_field_bytes(value)?);
+    }
+    for root in [&self.public_inputs_root, &self.witness_root] {
+      match root {
+        Some(root) => {
+          bytes.push(1);
+          bytes.extend_from_slice(&to_field_bytes(root)?);
+        }
+        None => bytes.push(0)
+      }
+    }
+    bytes.extend_from_slice(&u32_count(self.outputs.len(), "outputs")?.to_be_bytes());
+    for (name, value) in &self.outputs {
+      bytes.extend_from_slice(&u32_count(name.len(), "bytes in an output name")?.to_be_bytes());
+      bytes.extend_from_slice(name.as_bytes());
+      bytes.extend_from_slice(&to_field_bytes(value)?);
+    }
+    bytes.extend_from_slice(&u32_count(self.r1cs.len(), "R1CS bytes")?.to_be_bytes());
+    bytes.extend_from_slice(&self.r1cs);
+    Ok(bytes)
+  }
+
+  /// Decodes a proof written by `to_fixed_width_bytes`.
+  pub fn from_fixed_width_bytes(data: &[u8]) -> Result<Proof, ZkMockError> {
+    let mut reader = FixedWidthReader { data };
+    let valid = reader.byte()? == 1;
+    let circuit_hash = reader.field()?;
+    let challenge = reader.field()?;
+    let public_inputs = (0..reader.u32()?).map(|_| reader.field()).collect::<Result<_, _>>()?;
+    let public_inputs_root = if reader.byte()? == 1 { Some(reader.field()?) } else { None };
+    let witness_root = if reader.byte()? == 1 { Some(reader.field()?) } else { None };
+
+    let mut outputs = BTreeMap::new();
+    for _ in 0..reader.u32()? {
+      let name_len = reader.u32()? as usize;
+      let name = String::from_utf8(reader.take(name_len)?.to_vec()).map_err(|e| ZkMockError::MalformedProof(e.to_string()))?;
+      outputs.insert(name, reader.field()?);
+    }
+    let r1cs_len = reader.u32()? as usize;
+    let r1cs = reader.take(r1cs_len)?.to_vec();
+
+    if !reader.data.is_empty() {
+      return Err(ZkMockError::MalformedProof("trailing bytes after fixed-width proof".to_string()));
+    }
+    Ok(Proof { valid, circuit_hash, challenge, public_inputs, public_inputs_root, witness_root, outputs, r1cs, transcript: Transcript::new(), warnings: Vec::new() })
+  }
+}
+
+/// Detailed outcome of `Circuit::verify_proof_detailed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationReport{
+  pub valid: bool, // overall verdict: prover's claim, circuit hash, statement, public inputs, named outputs, challenge and every constraint all check out
+  pub claimed_valid: bool, // the validity recorded by the prover
+  pub circuit_hash: BigInt, // the circuit hash carried by the proof
+  pub circuit_hash_matches: bool, // whether it equals the verifier's own circuit hash
+  pub statement_matches: bool, // whether the proof's R1CS has the verifier's constraints and public values
+  pub public_inputs_match: bool, // whether the proof's public input vector equals the verifier's public input values
+  pub outputs_match: bool, // whether the proof names exactly the verifier's named outputs, each with the value its wire holds in the witness
+  pub challenge_matches: bool, // whether the proof carries the Fiat-Shamir challenge its transcript derives
+  pub constraint_results: Vec<Result<(), ConstraintError>>, // one entry per constraint, in order
+  pub public_inputs: Vec<(usize, BigInt)> // (index, value) of the public inputs the constraints were checked with
+}
+
+impl VerificationReport {
+  /// Returns the total number of constraints checked.
+  pub fn total_constraints(&self) -> usize {
+    self.constraint_results.len()
+  }
+
+  /// Returns the number of constraints that held.
+  pub fn passed_constraints(&self) -> usize {
+    self.constraint_results.iter().filter(|result| result.is_ok()).count()
+  }
+
+  /// Returns the indices of the constraints that did not hold.
+  pub fn failing_constraints(&self) -> Vec<usize> {
+    self.constraint_results.iter().enumerate().filter(|(_, result)| result.is_err()).map(|(index, _)| index).collect()
+  }
+}
+
+/// A proving backend: turns a circuit (with its witness) into a proof and checks proofs.
+/// The mock backend is the only implementation for now, real backends can be plugged in behind the same interface.
+pub trait ProofSystem {
+  fn prove(&self, circuit: &Circuit) -> Proof;
+  fn verify(&self, proof: &Proof) -> bool;
+}
+
+/**
+ * The crate's own backend: checks the R1CS directly and records the outcome in the proof.
+ *
+ * Verifying does not trust that recorded outcome: it decodes the R1CS the proof carries and re-checks every
+ * constraint over the witness in it. Hash constraints are re-checked with the backend's hash function, which
+ * must be the circuit's; without one, proofs containing them are rejected.
+ */
+#[derive(Default)]
+pub struct MockProofSystem {
+  hash_function: Option<Box<dyn HashFunction>>
+}
+
+impl MockProofSystem {
+  /// Creates the backend with the hash function Hash and Commit constraints are re-checked with, if any.
+  pub fn new(hash_function: Option<Box<dyn HashFunction>>) -> Self {
+    MockProofSystem { hash_function }
+  }
+}
+
+impl ProofSystem for MockProofSystem {
+  fn prove(&self, circuit: &Circuit) -> Proof {
+    circuit.prove()
+  }
+
+  fn verify(&self, proof: &Proof) -> bool {
+    let Ok(r1cs) = R1CS::from_bytes(&proof.r1cs) else {
+      return false;
+    };
+    match &self.hash_function {
+      Some(hash_function) => proof.valid && proof.challenge_matches() && r1cs.is_satisfied_with_hash3(|a, b| hash_function.hash(a, b), |a, b, c| hash_function.hash3(a, b, c)),
+      None if r1cs.constraints.iter().any(|constraint| matches!(constraint.operation, Operation::Hash | Operation::Hash3(_))) => false,
+      None => proof.valid && proof.challenge_matches() && r1cs.is_satisfied(|_, _| unreachable!("the system has no Hash constraint"))
+    }
+  }
+}
+
+/**
+ * On-disk cache of verification results, so re-verifying an unchanged proof (e.g. in CI) skips the constraint checks.
+ *
+ * Each result is stored as a file in the cache directory named after its key, see `Circuit::verify_proof_cached`.
+ * Keys are content hashes, so changing a single proof byte yields a new key: stale results are never returned,
+ * they are only left behind until `clear`.
+ */
+pub struct VerificationCache {
+  dir: PathBuf,
+  hits: AtomicUsize, // lookups answered from disk since the cache was opened
+  misses: AtomicUsize // lookups that had to be verified
+}
+
+impl VerificationCache {
+  /// Opens (creating it if needed) the cache stored in `dir`.
+  pub fn open(dir: impl AsRef<Path>) -> Result<VerificationCache, ZkMockError> {
+    std::fs::create_dir_all(dir.as_ref())?;
+    Ok(VerificationCache { dir: dir.as_ref().to_path_buf(), hits: AtomicUsize::new(0), misses: AtomicUsize::new(0) })
+  }
+
+  fn entry_path(&self, key: &[u8]) -> PathBuf {
+    self.dir.join(key.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+  }
+
+  /// Returns the cached result for `key`, counting the lookup as a hit or a miss.
+  pub fn get(&self, key: &[u8]) -> Option<bool> {
+    let cached = match std::fs::read(self.entry_path(key)).as_deref() {
+      Ok(b"1") => Some(true),
+      Ok(b"0") => Some(false),
+      _ => None
+    };
+    let counter = if cached.is_some() { &self.hits } else { &self.misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+    cached
+  }
+
+  /// Stores the result for `key`.
+  pub fn insert(&self, key: &[u8], valid: bool) -> Result<(), ZkMockError> {
+    std::fs::write(self.entry_path(key), if valid { "1" } else { "0" })?;
+    Ok(())
+  }
+
+  /// Removes every cached result.
+  pub fn clear(&self) -> Result<(), ZkMockError> {
+    for entry in std::fs::read_dir(&self.dir)? {
+      std::fs::remove_file(entry?.path())?;
+    }
+    Ok(())
+  }
+
+  /// Returns the number of lookups answered from the cache.
+  pub fn hits(&self) -> usize {
+    self.hits.load(Ordering::Relaxed)
+  }
+
+  /// Returns the number of lookups that found no cached result.
+  pub fn misses(&self) -> usize {
+    self.misses.load(Ordering::Relaxed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::circuit::Gate;
+  use crate::hash_functions::ModSquareHash;
+
+  fn int(value: i64) -> BigInt {
+    BigInt::from(value)
+  }
+
+  fn addition_circuit() -> Circuit {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(int(10));
+    let b = circuit.add_input(int(20));
+    circuit.add_gate_with_output(|sum| Gate::Add(a, b, sum));
+    circuit
+  }
+
+  #[test]
+  fn mock_proof_system_round_trips_the_addition_circuit() {
+    fn round_trip(system: &dyn ProofSystem, circuit: &Circuit) -> bool {
+      system.verify(&system.prove(circuit))
+    }
+    let system = MockProofSystem::default();
+    assert!(round_trip(&system, &addition_circuit()));
+
+    // a proof claiming validity for a witness that does not satisfy its constraints is rejected
+    let mut proof = system.prove(&addition_circuit());
+    let mut r1cs = R1CS::from_bytes(&proof.r1cs).unwrap();
+    r1cs.variables[2].value = Some(int(31));
+    proof.r1cs = r1cs.to_bytes();
+    assert!(proof.valid && !system.verify(&proof));
+  }
+
+  #[test]
+  fn mock_proof_system_rechecks_hash_constraints_with_its_hash_function() {
+    let mut circuit = Circuit::new(Some(Box::new(ModSquareHash::default())));
+    let a = circuit.add_input(int(3));
+    let b = circuit.add_input(int(4));
+    circuit.add_gate_with_output(|out| Gate::Hash(a, b, out));
+
+    let proof = MockProofSystem::default().prove(&circuit);
+    assert!(!MockProofSystem::default().verify(&proof));
+    assert!(MockProofSystem::new(Some(Box::new(ModSquareHash::default()))).verify(&proof));
+  }
+
+  #[test]
+  fn field_bytes_round_trip_and_reject_oversized_values() {
+    let value = (BigInt::from(1) << 255) + int(12345);
+    let bytes = to_field_bytes(&value).unwrap();
+    assert_eq!(bytes.len(), FIELD_BYTES);
+    assert_eq!(from_field_bytes(&bytes), value);
+    assert_eq!(to_field_bytes(&int(1)).unwrap()[FIELD_BYTES - 1], 1);
+
+    assert!(matches!(to_field_bytes(&(BigInt::from(1) << 256)), Err(ZkMockError::FieldEncoding(_))));
+    assert!(matches!(to_field_bytes(&int(-1)), Err(ZkMockError::FieldEncoding(_))));
+
+    let proof = addition_circuit().prove();
+    assert_eq!(Proof::from_fixed_width_bytes(&proof.to_fixed_width_bytes().unwrap()).unwrap(), proof);
+  }
+
+  #[test]
+  fn proofs_of_the_same_statement_record_identical_transcripts() {
+    let first = addition_circuit().prove();
+    let second = addition_circuit().prove();
+    assert!(!first.transcript().entries().is_empty());
+    assert_eq!(first.transcript(), second.transcript());
+
+    let mut other = addition_circuit();
+    other.add_input(int(1));
+    assert_ne!(other.prove().transcript(), first.transcript());
+  }
+
+  #[test]
+  fn header_is_read_back_and_bad_files_are_rejected() {
+    let proof = addition_circuit().prove();
+    let bytes = proof.try_to_bytes().unwrap();
+    assert_eq!(bytes, proof.to_bytes());
+    assert_eq!(ProofHeader::from_bytes(&bytes).unwrap(), ProofHeader { version: PROOF_FORMAT_VERSION, constraint_count: 1 });
+    assert_eq!(Proof::from_bytes(&bytes).unwrap(), proof);
+
+    assert!(matches!(Proof::from_bytes(&bytes[..PROOF_HEADER_BYTES - 1]), Err(ZkMockError::MalformedProof(_))));
+    assert!(matches!(Proof::from_bytes(&bytes[..bytes.len() - 1]), Err(ZkMockError::MalformedProof(_))));
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert!(matches!(Proof::from_bytes(&wrong_magic), Err(ZkMockError::MalformedProof(_))));
+    let mut future = bytes.clone();
+    future[4..6].copy_from_slice(&(PROOF_FORMAT_VERSION + 1).to_be_bytes());
+    assert!(matches!(Proof::from_bytes(&future), Err(ZkMockError::UnsupportedProofVersion { .. })));
+
+    assert_eq!(u32_count(u32::MAX as usize, "constraints").unwrap(), u32::MAX);
+    if let Some(too_many) = (u32::MAX as usize).checked_add(1) {
+      assert!(matches!(u32_count(too_many, "constraints"), Err(ZkMockError::ProofTooLarge { what: "constraints", .. })));
+    }
+  }
+
+
+  #[test]
+  fn mock_proof_system_checks_hash3_constraints_with_the_hash_functions_hash3() {
+    struct WeightedHash3;
+    impl HashFunction for WeightedHash3 {
+      fn hash(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        a * 31 + b
+      }
+
+      fn hash3(&self, a: &BigInt, b: &BigInt, c: &BigInt) -> BigInt {
+        a * 7 + b * 5 + c
+      }
+    }
+    let proof_with_output = |output: BigInt| {
+      let mut r1cs = R1CS::new();
+      let [a, b, c] = [1, 2, 3].map(|value| r1cs.append_variable(Some(int(value))));
+      let out = r1cs.append_variable(Some(output));
+      r1cs.add_hash3_constraint(vec![(a, int(1))], vec![(b, int(1))], vec![(c, int(1))], vec![(out, int(1))]);
+      let mut proof = addition_circuit().prove();
+      proof.r1cs = r1cs.to_bytes();
+      proof.challenge = proof.fiat_shamir_transcript().1;
+      proof
+    };
+
+    let system = MockProofSystem::new(Some(Box::new(WeightedHash3)));
+    assert!(system.verify(&proof_with_output(WeightedHash3.hash3(&int(1), &int(2), &int(3)))));
+    // the nested default hash(hash(a, b), c) is not what this hash function computes
+    assert!(!system.verify(&proof_with_output(WeightedHash3.hash(&WeightedHash3.hash(&int(1), &int(2)), &int(3)))));
+  }
+}
@@ -1,7 +1,12 @@
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
 use serde::{Deserialize, Serialize};  
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Write};
+use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use crate::error::{ConstraintError, ZkMockError};
 
 
 /// Implements serialization, deserialization, and debug printing for Variable.
@@ -11,14 +16,113 @@ use std::io::{Write};
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Variable{
   pub index: usize,
-  pub value: BigInt
+  pub value: Option<BigInt> // None until a witness is assigned, e.g. in the structure-only system a verifier holds
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum Operation{
   Add,
   Mul,
-  Hash
+  Hash,
+  Sub,
+  Hash3(Vec<(usize, BigInt)>) // hash3(left, right, third) = output, carrying the third operand's terms
+}
+
+/// A dense coefficient matrix, one row per constraint, see `R1CS::to_matrices`.
+pub type Matrix = Vec<Vec<BigInt>>;
+
+/// Reduces `value` into `[0, modulus)` when a modulus is given, otherwise returns it unchanged.
+pub fn reduce(value: &BigInt, modulus: Option<&BigInt>) -> BigInt {
+  match modulus {
+    Some(modulus) => value.mod_floor(modulus),
+    None => value.clone()
+  }
+}
+
+/// Turns the fraction `numerator / denominator` into the field element numerator * denominator^-1 mod `modulus`,
+/// so derivations with fractional coefficients (e.g. 1/2) can be written as ordinary constraint coefficients.
+/// Returns None if the denominator has no inverse modulo `modulus` (it is 0 or shares a factor with it).
+pub fn fraction_coefficient(numerator: &BigInt, denominator: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+  let inverse = denominator.mod_floor(modulus).modinv(modulus)?;
+  Some((numerator * inverse).mod_floor(modulus))
+}
+
+/// Writes an integer as an SMT-LIB2 term; negative literals have to be spelled `(- n)`.
+fn smt_int(value: &BigInt) -> String {
+  if value.sign() == Sign::Minus { format!("(- {})", -value) } else { value.to_string() }
+}
+
+/// hash(hash(a, b), c), the default `HashFunction::hash3`, for the entry points that only take a two-argument hash.
+fn nested_hash3<F>(apply_hash: &F) -> impl Fn(&BigInt, &BigInt, &BigInt) -> BigInt + '_
+where F: Fn(&BigInt, &BigInt) -> BigInt
+{
+  move |a, b, c| apply_hash(&apply_hash(a, b), c)
+}
+
+/// Writes a side's weighted terms as an SMT-LIB2 sum, `0` for an empty side.
+fn smt_sum(terms: &[(usize, BigInt)]) -> String {
+  match terms {
+    [] => "0".to_string(),
+    [(index, coeff)] => format!("(* {} v{})", smt_int(coeff), index),
+    _ => format!("(+ {})", terms.iter().map(|(index, coeff)| format!("(* {} v{})", smt_int(coeff), index)).collect::<Vec<_>>().join(" "))
+  }
+}
+
+impl Operation {
+  /// Checks that the already-summed `left`, `right` and `output` values satisfy this operation.
+  /// With a modulus the comparison happens in the field, i.e. both sides are reduced first.
+  /// The apply_hash closure is only called for Hash operations.
+  /// Hash3 needs its third operand's value, so it is checked through `evaluate_with_hash3` and errors here.
+  pub fn evaluate<F>(&self, left: &BigInt, right: &BigInt, output: &BigInt, modulus: Option<&BigInt>, apply_hash: &F) -> Result<(), ConstraintError>
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    if let Operation::Hash3(_) = self {
+      return Err(ConstraintError("Hash3 constraint needs its third operand, check it with Operation::evaluate_with_hash3".to_string()));
+    }
+    self.evaluate_with_hash3(left, right, &BigInt::from(0), output, modulus, apply_hash, &|_, _, _| unreachable!("not a Hash3 operation"))
+  }
+
+  /// Like `evaluate`, with the summed value of a Hash3's third operand and the three-argument hash it is checked with,
+  /// normally the hash function's `HashFunction::hash3`. `third` is ignored by the other operations.
+  #[allow(clippy::too_many_arguments)]
+  pub fn evaluate_with_hash3<F, G>(&self, left: &BigInt, right: &BigInt, third: &BigInt, output: &BigInt, modulus: Option<&BigInt>, apply_hash: &F, apply_hash3: &G) -> Result<(), ConstraintError>
+  where
+    F: Fn(&BigInt, &BigInt) -> BigInt,
+    G: Fn(&BigInt, &BigInt, &BigInt) -> BigInt
+  {
+    let equal = |computed: BigInt| reduce(&computed, modulus) == reduce(output, modulus);
+    match self {
+      Operation::Add => {
+        if !equal(left + right) {
+          return Err(ConstraintError(format!("Add constraint not satisfied: {:?} + {:?} != {:?}", left, right, output)));
+        }
+      },
+      Operation::Sub => {
+        if !equal(left - right) {
+          return Err(ConstraintError(format!("Sub constraint not satisfied: {:?} - {:?} != {:?}", left, right, output)));
+        }
+      },
+      Operation::Mul => {
+        if !equal(left * right) {
+          return Err(ConstraintError(format!("Mul constraint not satisfied: {:?} * {:?} != {:?}", left, right, output)));
+        }
+      },
+      Operation::Hash => {
+        // For a hash operation, we expect the output to be the result of applying the hash function
+        let expected_output = apply_hash(left, right);
+        if !equal(expected_output.clone()) {
+          return Err(ConstraintError(format!("Hash constraint not satisfied: expected {:?}, got {:?}", expected_output, output)));
+        }
+      },
+      Operation::Hash3(_) => {
+        let expected_output = apply_hash3(left, right, third);
+        if !equal(expected_output.clone()) {
+          return Err(ConstraintError(format!("Hash3 constraint not satisfied: expected {:?}, got {:?}", expected_output, output)));
+        }
+      }
+    }
+    Ok(())
+  }
 }
 
 /**
@@ -30,20 +134,110 @@ pub enum Operation{
  * -> right is y
  * -> operation is '+'
  * -> output is z
+ *
+ * Each side is a list of (variable-index, coefficient) terms, the values themselves live once in `R1CS::variables`.
  */
 
 #[derive(Serialize, Deserialize)]
 pub struct Constraint{
-  pub left: Vec<(Variable, BigInt)>,
-  pub right: Vec<(Variable, BigInt)>,
-  pub output: Vec<(Variable, BigInt)>,
-  pub operation: Operation
+  pub left: Vec<(usize, BigInt)>,
+  pub right: Vec<(usize, BigInt)>,
+  pub output: Vec<(usize, BigInt)>,
+  pub operation: Operation,
+  #[serde(default)]
+  pub selector: Option<usize> // variable gating the constraint: enforced as selector * (left op right - output) = 0
 }
 
-#[derive(Serialize, Deserialize)]
+impl Constraint {
+  /// Serializes the constraint with the terms of every linear combination sorted, and the left and right sides
+  /// of an Add or Mul in ascending order, so equivalent constraints such as a + b = c and b + a = c encode alike.
+  pub fn canonical_bytes(&self) -> Vec<u8> {
+    let sorted = |terms: &[(usize, BigInt)]| {
+      let mut terms = terms.to_vec();
+      terms.sort();
+      terms
+    };
+    let (mut left, mut right) = (sorted(&self.left), sorted(&self.right));
+    if matches!(self.operation, Operation::Add | Operation::Mul) && right < left {
+      std::mem::swap(&mut left, &mut right);
+    }
+    let third = match &self.operation {
+      Operation::Hash3(third) => Some(Operation::Hash3(sorted(third))),
+      _ => None
+    };
+    let operation = third.as_ref().unwrap_or(&self.operation);
+    bincode::serialize(&(&left, &right, sorted(&self.output), operation, self.selector)).expect("Unable to serialize constraint")
+  }
+
+  /// Sums each side's weighted terms, looking the values up in `variables`, and checks them against the constraint's operation.
+  /// Hash3 constraints use hash(hash(a, b), c), the default of `HashFunction::hash3`; a hash function that overrides
+  /// `hash3` has to be checked through `check_with_hash3`.
+  pub fn check<F>(&self, variables: &[Variable], modulus: Option<&BigInt>, apply_hash: &F) -> Result<(), ConstraintError>
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    self.check_with_hash3(variables, modulus, apply_hash, &nested_hash3(apply_hash))
+  }
+
+  /// Like `check`, with an explicit three-argument hash closure for Hash3 constraints, normally `HashFunction::hash3`.
+  pub fn check_with_hash3<F, G>(&self, variables: &[Variable], modulus: Option<&BigInt>, apply_hash: &F, apply_hash3: &G) -> Result<(), ConstraintError>
+  where
+    F: Fn(&BigInt, &BigInt) -> BigInt,
+    G: Fn(&BigInt, &BigInt, &BigInt) -> BigInt
+  {
+    // selector * (left op right - output) = 0 holds trivially when the selector is 0, and reduces to the plain check otherwise
+    if let Some(selector) = self.selector {
+      let selector_value = Constraint::sum_terms(&[(selector, BigInt::from(1))], variables)?;
+      if reduce(&selector_value, modulus) == BigInt::from(0) {
+        return Ok(());
+      }
+    }
+
+    let left_value = Constraint::sum_terms(&self.left, variables)?;
+    let right_value = Constraint::sum_terms(&self.right, variables)?;
+    let output_value = Constraint::sum_terms(&self.output, variables)?;
+
+    let third_value = match &self.operation {
+      Operation::Hash3(third) => Constraint::sum_terms(third, variables)?,
+      _ => BigInt::from(0)
+    };
+    self.operation.evaluate_with_hash3(&left_value, &right_value, &third_value, &output_value, modulus, apply_hash, apply_hash3)
+  }
+
+  /// Computes Σ coefficient * value over a side's terms.
+  fn sum_terms(terms: &[(usize, BigInt)], variables: &[Variable]) -> Result<BigInt, ConstraintError> {
+    terms.iter().map(|(index, coeff)| {
+      match variables.get(*index) {
+        Some(Variable { value: Some(value), .. }) => Ok(value * coeff),
+        Some(Variable { value: None, .. }) => Err(ConstraintError(format!("Variable {} has no value assigned (structure-only system)", index))),
+        None => Err(ConstraintError(format!("Variable {} is referenced by a constraint but does not exist", index)))
+      }
+    }).sum()
+  }
+}
+
+/**
+ * Receives a circuit's constraint system piece by piece, see `Circuit::synthesize`.
+ *
+ * Modeled on arkworks' `ConstraintSynthesizer` / bellman's `Circuit`: implement it over a real backend's
+ * constraint system to prove a circuit defined here with that backend, allocating a backend variable in
+ * `alloc` and translating each constraint's operation in `enforce`.
+ */
+pub trait ConstraintSynthesizer {
+  /// Called once per variable, in index order and before any constraint refers to it.
+  fn alloc(&mut self, variable: &Variable, public: bool);
+
+  /// Called once per constraint, in order, with the index of the gate it was lowered from.
+  fn enforce(&mut self, gate: usize, constraint: &Constraint);
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct R1CS{
-  pub variables: Vec<Variable>,
-  pub constraints: Vec<Constraint>
+  pub variables: Vec<Variable>, // variables[i] holds the variable with index i, constraints refer to it by that index
+  pub constraints: Vec<Constraint>,
+  pub public: Vec<usize>, // indices of the variables whose values the verifier knows
+  pub modulus: Option<BigInt>, // prime of the field the constraints are checked in, plain integers when None
+  #[serde(skip)]
+  satisfaction_cache: Option<Mutex<HashMap<Vec<u8>, bool>>> // witness commitment -> is_satisfied result, when enabled
 }
 
 impl R1CS {
@@ -51,63 +245,662 @@ impl R1CS {
   pub fn new() -> Self {
     R1CS{
       variables: Vec::new(),
-      constraints: Vec::new()
+      constraints: Vec::new(),
+      public: Vec::new(),
+      modulus: None,
+      satisfaction_cache: None
     }
   }
 
+  /// Creates an empty R1CS instance whose constraints are checked modulo the prime `modulus`,
+  /// e.g. `field::bn254_scalar_modulus()`, instead of over plain integers.
+  pub fn new_in_field(modulus: BigInt) -> Self {
+    R1CS { modulus: Some(modulus), ..R1CS::new() }
+  }
+
+  /**
+   * Enables memoizing `is_satisfied` results.
+   *
+   * Results are keyed by a SHA-256 commitment over the variables, constraints and modulus, so
+   * any mutation of the system (e.g. a changed variable value) produces a new key and a fresh check.
+   * The hash closure is not part of the key: only enable this when every call uses the same hash function.
+   */
+  pub fn enable_satisfaction_cache(&mut self) {
+    if self.satisfaction_cache.is_none() {
+      self.satisfaction_cache = Some(Mutex::new(HashMap::new()));
+    }
+  }
+
+  /// Commits to the whole system (witness values included) for the satisfaction cache.
+  fn witness_commitment(&self) -> Vec<u8> {
+    let data = bincode::serialize(&(&self.variables, &self.constraints, &self.modulus)).expect("Unable to serialize R1CS");
+    Sha256::digest(&data).to_vec()
+  }
+
+  /// Appends a new variable holding `value`, marks it as public and returns its index.
+  pub fn append_public_input(&mut self, value: BigInt) -> usize {
+    let index = self.variables.len();
+    self.variables.push(Variable { index, value: Some(value) });
+    self.public.push(index);
+    index
+  }
+
+  /// Drops the values of every non-public variable, leaving the structure-only system a verifier holds.
+  /// Checking it afterwards fails on the first constraint that needs a cleared value.
+  pub fn clear_witness(&mut self) {
+    for variable in &mut self.variables {
+      if !self.public.contains(&variable.index) {
+        variable.value = None;
+      }
+    }
+  }
+
+  /// Appends a new private (witness) variable holding `value` and returns its index.
+  pub fn append_variable(&mut self, value: Option<BigInt>) -> usize {
+    let index = self.variables.len();
+    self.variables.push(Variable { index, value });
+    index
+  }
+
+  /// Returns true if the variable at `index` is a public input.
+  pub fn is_public(&self, index: usize) -> bool {
+    self.public.contains(&index)
+  }
+
   /// Adds a constraint to the R1CS with the given left, right, output, and operation.
-  pub fn add_constraints(&mut self, left: Vec<(Variable, BigInt)>, right: Vec<(Variable, BigInt)>, output: Vec<(Variable, BigInt)>, operation: Operation) {
+  pub fn add_constraints(&mut self, left: Vec<(usize, BigInt)>, right: Vec<(usize, BigInt)>, output: Vec<(usize, BigInt)>, operation: Operation) {
+    let constraint = Constraint{
+      left,
+      right,
+      output,
+      operation,
+      selector: None
+    };
+    self.constraints.push(constraint);
+  }
+
+  /// Adds a constraint enforcing hash3(a, b, c) = output, e.g. a Merkle node hashed with a domain tag.
+  pub fn add_hash3_constraint(&mut self, a: Vec<(usize, BigInt)>, b: Vec<(usize, BigInt)>, c: Vec<(usize, BigInt)>, output: Vec<(usize, BigInt)>) {
+    self.add_constraints(a, b, output, Operation::Hash3(c));
+  }
+
+  /// Adds a constraint that is only enforced when the `selector` variable is nonzero (conditional execution).
+  pub fn add_selected_constraint(&mut self, left: Vec<(usize, BigInt)>, right: Vec<(usize, BigInt)>, output: Vec<(usize, BigInt)>, operation: Operation, selector: usize) {
     let constraint = Constraint{
       left,
       right,
       output,
-      operation
+      operation,
+      selector: Some(selector)
     };
     self.constraints.push(constraint);
   }
 
+  /**
+   * Estimates proving cost as the number of nonlinear (Mul) constraints.
+   *
+   * In real SNARKs the prover's work is dominated by multiplication constraints, linear ones are
+   * nearly free, so this is the number to minimize for proving time. It differs from
+   * `constraints.len()`, which also counts the Add constraints. Hash constraints are not counted:
+   * the mock checks them natively, a real circuit would expand them into Mul constraints first.
+   */
+  pub fn estimate_proving_work(&self) -> usize {
+    self.constraints.iter().filter(|constraint| matches!(constraint.operation, Operation::Mul)).count()
+  }
+
   /// Checks if all constraints are satisfied by the current variable assignments.
   /// Returns true if all constraints hold, false otherwise.
   /// The apply_hash closure is used for Hash operations.
+  /// Consults (and fills) the satisfaction cache when it is enabled.
+  /// Hash3 constraints use hash(hash(a, b), c), see `is_satisfied_with_hash3` for hash functions overriding `hash3`.
   pub fn is_satisfied<F>(&self, apply_hash: F) -> bool
   where F: Fn(&BigInt, &BigInt) -> BigInt // a closure that applies the hash
   {
+    self.is_satisfied_with_hash3(&apply_hash, nested_hash3(&apply_hash))
+  }
+
+  /// Checks only the constraints with index in `[start, end)`, so a failing system can be bisected.
+  /// Bypasses the satisfaction cache. Panics if the range is out of bounds, like slicing.
+  pub fn is_satisfied_range<F>(&self, start: usize, end: usize, apply_hash: F) -> bool
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    self.check_constraints(&self.constraints[start..end], self.modulus.as_ref(), &apply_hash)
+  }
+
+  /// Checks every constraint over the field of `modulus` instead of the system's own modulus,
+  /// so the same constraints can be tried across field sizes. Bypasses the satisfaction cache.
+  pub fn is_satisfied_mod<F>(&self, modulus: &BigInt, apply_hash: F) -> bool
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    self.check_constraints(&self.constraints, Some(modulus), &apply_hash)
+  }
+
+  /// Like `is_satisfied`, with an explicit three-argument hash closure for Hash3 constraints, normally `HashFunction::hash3`.
+  /// Consults (and fills) the satisfaction cache when it is enabled.
+  pub fn is_satisfied_with_hash3<F, G>(&self, apply_hash: F, apply_hash3: G) -> bool
+  where
+    F: Fn(&BigInt, &BigInt) -> BigInt,
+    G: Fn(&BigInt, &BigInt, &BigInt) -> BigInt
+  {
+    let check = || self.check_constraints_with_hash3(&self.variables, &self.constraints, self.modulus.as_ref(), &apply_hash, &apply_hash3);
+    let Some(cache) = &self.satisfaction_cache else {
+      return check();
+    };
+
+    let key = self.witness_commitment();
+    if let Some(&cached) = cache.lock().expect("satisfaction cache poisoned").get(&key) {
+      return cached;
+    }
+    let result = check();
+    cache.lock().expect("satisfaction cache poisoned").insert(key, result);
+    result
+  }
+
+  /**
+   * Checks a batch of witnesses against the system's one constraint structure, returning one result per assignment.
+   *
+   * Each assignment maps variable indices to values and overrides the system's own values for those variables,
+   * the rest keep their current value. The constraints are shared rather than rebuilt per witness, which makes
+   * this the cheap way to benchmark many witnesses. Bypasses the satisfaction cache.
+   */
+  pub fn is_satisfied_batch<F>(&self, assignments: &[HashMap<usize, BigInt>], apply_hash: F) -> Vec<bool>
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    let apply_hash3 = nested_hash3(&apply_hash);
+    assignments.iter().map(|assignment| {
+      let variables: Vec<Variable> = self.variables.iter()
+        .map(|variable| Variable {
+          index: variable.index,
+          value: assignment.get(&variable.index).or(variable.value.as_ref()).cloned()
+        })
+        .collect();
+      self.check_constraints_with_hash3(&variables, &self.constraints, self.modulus.as_ref(), &apply_hash, &apply_hash3)
+    }).collect()
+  }
+
+  /// Checks the given constraints in order under `modulus`, stopping at the first failure.
+  /// Use `Constraint::check` (or `Circuit::verify_proof_detailed`) to find out why a constraint fails.
+  fn check_constraints<F>(&self, constraints: &[Constraint], modulus: Option<&BigInt>, apply_hash: &F) -> bool
+  where F: Fn(&BigInt, &BigInt) -> BigInt
+  {
+    self.check_constraints_with_hash3(&self.variables, constraints, modulus, apply_hash, &nested_hash3(apply_hash))
+  }
+
+  /// Checks `constraints` in order against `variables` rather than the system's own, see `check_constraints`.
+  fn check_constraints_with_hash3<F, G>(&self, variables: &[Variable], constraints: &[Constraint], modulus: Option<&BigInt>, apply_hash: &F, apply_hash3: &G) -> bool
+  where
+    F: Fn(&BigInt, &BigInt) -> BigInt,
+    G: Fn(&BigInt, &BigInt, &BigInt) -> BigInt
+  {
+    constraints.iter().all(|constraint| constraint.check_with_hash3(variables, modulus, apply_hash, apply_hash3).is_ok())
+  }
+
+  /**
+   * Exports the constraint system as an SMT-LIB2 script, for checking satisfiability with an external solver such as Z3.
+   *
+   * Every variable becomes an `Int` constant `v<index>`; public variables with a value are pinned to it (the statement),
+   * the rest are left free for the solver to find (the witness). Each constraint becomes an assertion of
+   * `left op right = output`, both sides taken modulo the system's modulus when it has one, and guarded by
+   * `selector = 0 or ...` when selected. Hash constraints use an uninterpreted `(hash Int Int) Int` function,
+   * Hash3 constraints an uninterpreted `(hash3 Int Int Int) Int`, declared only when one is present, since a hash function
+   * may override `HashFunction::hash3`. The script ends with `(check-sat)`.
+   */
+  pub fn to_smtlib(&self) -> String {
+    let mut script = String::from("(set-logic QF_UFNIA)\n(declare-fun hash (Int Int) Int)\n");
+    if self.constraints.iter().any(|constraint| matches!(constraint.operation, Operation::Hash3(_))) {
+      script.push_str("(declare-fun hash3 (Int Int Int) Int)\n");
+    }
+    for variable in &self.variables {
+      script.push_str(&format!("(declare-const v{} Int)\n", variable.index));
+    }
+    for variable in &self.variables {
+      if let (true, Some(value)) = (self.is_public(variable.index), &variable.value) {
+        script.push_str(&format!("(assert (= v{} {}))\n", variable.index, smt_int(value)));
+      }
+    }
+
+    let field = |term: String| match &self.modulus {
+      Some(modulus) => format!("(mod {} {})", term, smt_int(modulus)),
+      None => term
+    };
     for constraint in &self.constraints {
-      let left_value: BigInt = constraint.left.iter().map(|(var, coeff)| &var.value * coeff).sum();
-      let right_value: BigInt = constraint.right.iter().map(|(var, coeff)| &var.value * coeff).sum();
-      let output_value: BigInt = constraint.output.iter().map(|(var, coeff)| &var.value * coeff).sum();
-
-      match constraint.operation {
-        Operation::Add => {
-          if left_value.clone() + right_value.clone() != output_value.clone() {
-            print!("Add constraint not satisfied: {:?} + {:?} != {:?}", left_value, right_value, output_value);
-            return false;
-          }
-        },
-        Operation::Mul => {
-          if left_value.clone() * right_value.clone() != output_value.clone() {
-            print!("Mul constraint not satisfied: {:?} * {:?} != {:?}", left_value, right_value, output_value);
-            return false;
-          }
-        },
-        Operation::Hash => {
-          // For a hash operation, we expect the output to be the result of applying the hash function
-          let expected_output = apply_hash(&left_value, &right_value);
-          if expected_output != output_value {
-            print!("Hash constraint not satisfied: expected {:?}, got {:?}", expected_output, output_value);
-            return false;
-          }
+      let (left, right) = (smt_sum(&constraint.left), smt_sum(&constraint.right));
+      let computed = match &constraint.operation {
+        Operation::Add => format!("(+ {} {})", left, right),
+        Operation::Sub => format!("(- {} {})", left, right),
+        Operation::Mul => format!("(* {} {})", left, right),
+        Operation::Hash => format!("(hash {} {})", left, right),
+        Operation::Hash3(third) => format!("(hash3 {} {} {})", left, right, smt_sum(third))
+      };
+      let mut assertion = format!("(= {} {})", field(computed), field(smt_sum(&constraint.output)));
+      if let Some(selector) = constraint.selector {
+        assertion = format!("(or (= {} 0) {})", field(format!("v{}", selector)), assertion);
+      }
+      script.push_str(&format!("(assert {})\n", assertion));
+    }
+    script.push_str("(check-sat)\n");
+    script
+  }
+
+  /**
+   * Builds the classic matrix form `(A·z) ∘ (B·z) = C·z`, with one dense row per constraint.
+   *
+   * `z` is `[1, variables[0], variables[1], ...]` (see `assignment_vector`): column 0 is the constant one,
+   * column i + 1 holds variable i. Each constraint is linearized into a single rank-1 row:
+   * - Mul: A = left, B = right, C = output;
+   * - Add: A = left + right, B = 1, C = output;
+   * - Sub: A = left - right, B = 1, C = output;
+   * - a selected Add or Sub: A = selector, B = left ± right - output, C = 0.
+   *
+   * Hash and Hash3 constraints, and selected Mul constraints, have no rank-1 form: their rows are all zero,
+   * so they hold trivially here and must be checked with `is_satisfied`. Coefficients are not reduced.
+   */
+  pub fn to_matrices(&self) -> (Matrix, Matrix, Matrix) {
+    let width = self.variables.len() + 1;
+    let row = |terms: &[(&[(usize, BigInt)], i32)]| {
+      let mut row = vec![BigInt::from(0); width];
+      for (side, sign) in terms {
+        for (index, coeff) in side.iter() {
+          row[index + 1] += coeff * sign;
         }
       }
+      row
+    };
+    let one = || {
+      let mut row = vec![BigInt::from(0); width];
+      row[0] = BigInt::from(1);
+      row
+    };
+
+    let (mut a, mut b, mut c) = (Vec::new(), Vec::new(), Vec::new());
+    for constraint in &self.constraints {
+      let (left, right, output) = (constraint.left.as_slice(), constraint.right.as_slice(), constraint.output.as_slice());
+      let sign = match constraint.operation {
+        Operation::Add => Some(1),
+        Operation::Sub => Some(-1),
+        _ => None
+      };
+      let (a_row, b_row, c_row) = match (&constraint.operation, sign, constraint.selector) {
+        (_, Some(sign), None) => (row(&[(left, 1), (right, sign)]), one(), row(&[(output, 1)])),
+        (_, Some(sign), Some(selector)) => {
+          (row(&[(&[(selector, BigInt::from(1))], 1)]), row(&[(left, 1), (right, sign), (output, -1)]), row(&[]))
+        }
+        (Operation::Mul, _, None) => (row(&[(left, 1)]), row(&[(right, 1)]), row(&[(output, 1)])),
+        _ => (row(&[]), row(&[]), row(&[]))
+      };
+      a.push(a_row);
+      b.push(b_row);
+      c.push(c_row);
     }
-    true
+    (a, b, c)
+  }
+
+  /// Returns the vector `z = [1, variables...]` the `to_matrices` rows are taken over, or None if a variable
+  /// has no value assigned.
+  pub fn assignment_vector(&self) -> Option<Vec<BigInt>> {
+    std::iter::once(Some(BigInt::from(1)))
+      .chain(self.variables.iter().map(|variable| variable.value.clone()))
+      .collect()
   }
 
   /// Serializes and saves the entire R1CS structure to a binary file.
-  pub fn save_to_binary(&self, filename: &str){
-    let mut file = File::create(filename).expect("Unable to create the file");
-    let data = bincode::serialize(self).expect("Here failed to serialize R1CS");
-    file.write_all(&data).expect("Unable to write data to the file");
+  /// Fails with `ZkMockError::Io` if the file cannot be created or written.
+  pub fn save_to_binary(&self, filename: &str) -> Result<(), ZkMockError> {
+    let mut file = File::create(filename)?;
+    file.write_all(&self.to_bytes())?;
+    Ok(())
+  }
+
+  /// Reads back an R1CS written by `save_to_binary`. The satisfaction cache starts disabled.
+  ///
+  /// Fails with the crate's `ZkMockError` rather than a `Box<dyn Error>`, so callers can match on the cause like
+  /// everywhere else: `ZkMockError::Io` if the file cannot be read, `ZkMockError::MalformedR1cs` if it does not decode.
+  pub fn load_from_binary(filename: &str) -> Result<R1CS, ZkMockError> {
+    let data = std::fs::read(filename)?;
+    R1CS::from_bytes(&data)
+  }
+
+  /// Serializes the system (variables, constraints, public indices and modulus) with bincode, the bytes `save_to_binary` writes.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    bincode::serialize(self).expect("Here failed to serialize R1CS")
+  }
+
+  /// Decodes a system serialized by `to_bytes`. The satisfaction cache starts disabled.
+  pub fn from_bytes(data: &[u8]) -> Result<R1CS, ZkMockError> {
+    bincode::deserialize(data).map_err(|e| ZkMockError::MalformedR1cs(e.to_string()))
+  }
+
+  /// Returns true if `other` has the same constraints, public variables (with the same values), variable count
+  /// and modulus, i.e. it is this system with possibly different private witness values.
+  /// Constraints and public variables are compared as sets, each constraint in its `canonical_bytes` form, so a
+  /// system built in another gate order, or with the operands of an addition or multiplication swapped, still matches.
+  pub fn same_statement(&self, other: &R1CS) -> bool {
+    let structure = |r1cs: &R1CS| {
+      let mut constraints: Vec<Vec<u8>> = r1cs.constraints.iter().map(Constraint::canonical_bytes).collect();
+      constraints.sort();
+      let mut public = r1cs.public.clone();
+      public.sort_unstable();
+      (constraints, public)
+    };
+    self.variables.len() == other.variables.len()
+      && self.modulus == other.modulus
+      && structure(self) == structure(other)
+      && self.public.iter().all(|&index| self.variables[index].value == other.variables[index].value)
   }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn int(value: i64) -> BigInt {
+    BigInt::from(value)
+  }
+
+  /// Returns a temporary file path unique to this test process and `name`.
+  fn temp_file(name: &str) -> String {
+    std::env::temp_dir().join(format!("zk-mock-r1cs-{}-{}.bin", std::process::id(), name))
+      .to_str().expect("temporary directory path is valid UTF-8").to_string()
+  }
+
+  #[test]
+  fn public_indices_survive_a_save_and_load() {
+    let mut r1cs = R1CS::new();
+    let secret = r1cs.append_variable(Some(int(3)));
+    let public = r1cs.append_public_input(int(9));
+    r1cs.add_constraints(vec![(secret, int(1))], vec![(secret, int(1))], vec![(public, int(1))], Operation::Mul);
+
+    let file = temp_file("public-indices");
+    r1cs.save_to_binary(&file).unwrap();
+    let loaded = R1CS::load_from_binary(&file);
+    let _ = std::fs::remove_file(&file);
+    let loaded = loaded.unwrap();
+
+    assert_eq!(loaded.public, vec![public]);
+    assert!(loaded.is_public(public));
+    assert!(!loaded.is_public(secret));
+
+    let unwritable = std::env::temp_dir().join("zk-mock-missing-dir").join("r1cs.bin");
+    assert!(matches!(r1cs.save_to_binary(unwritable.to_str().unwrap()), Err(ZkMockError::Io(_))));
+  }
+
+  #[test]
+  fn operation_evaluate_matches_the_arithmetic_of_every_operation() {
+    let hash = |a: &BigInt, b: &BigInt| a * 31 + b;
+    let cases = [
+      (Operation::Add, int(7), int(5), int(12)),
+      (Operation::Sub, int(7), int(5), int(2)),
+      (Operation::Mul, int(7), int(5), int(35)),
+      (Operation::Hash, int(7), int(5), int(222))
+    ];
+    for (operation, left, right, output) in cases {
+      assert!(operation.evaluate(&left, &right, &output, None, &hash).is_ok());
+      assert!(operation.evaluate(&left, &right, &(&output + 1), None, &hash).is_err());
+      // in the field of 11 the output only has to match modulo 11
+      assert!(operation.evaluate(&left, &right, &(&output + 11), Some(&int(11)), &hash).is_ok());
+    }
+    assert!(Operation::Hash3(vec![]).evaluate(&int(1), &int(2), &int(3), None, &hash).is_err());
+  }
+
+  #[test]
+  fn constraints_reference_values_by_index_only() {
+    // a chain of squarings x_{i+1} = x_i * x_i, each value stored once in `variables`
+    let chain = |start: BigInt| {
+      let mut r1cs = R1CS::new();
+      let mut previous = r1cs.append_public_input(start.clone());
+      let mut value = start;
+      for _ in 0..500 {
+        value = &value * &value % int(1_000_003);
+        let next = r1cs.append_variable(Some(value.clone()));
+        r1cs.add_constraints(vec![(previous, int(1))], vec![(previous, int(1))], vec![(next, int(1))], Operation::Mul);
+        previous = next;
+      }
+      r1cs.modulus = Some(int(1_000_003));
+      r1cs
+    };
+    let small = chain(int(2));
+    let large = chain(int(999_999));
+    let no_hash = |_: &BigInt, _: &BigInt| unreachable!("no Hash constraints");
+    assert!(small.is_satisfied(no_hash) && large.is_satisfied(no_hash));
+
+    // the constraints are the same bytes whatever the witness, the values only live in `variables`
+    let constraints = |r1cs: &R1CS| bincode::serialize(&r1cs.constraints).unwrap();
+    assert_eq!(constraints(&small), constraints(&large));
+    let mut tampered = chain(int(2));
+    tampered.variables[250].value = Some(int(5));
+    assert!(!tampered.is_satisfied(no_hash));
+  }
+
+  #[test]
+  fn satisfaction_cache_skips_rechecking_an_unchanged_system() {
+    let mut r1cs = R1CS::new();
+    let a = r1cs.append_variable(Some(int(3)));
+    let b = r1cs.append_variable(Some(int(4)));
+    let out = r1cs.append_public_input(int(7));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(out, int(1))], Operation::Hash);
+    r1cs.enable_satisfaction_cache();
+
+    let calls = std::cell::Cell::new(0);
+    let counting_hash = |x: &BigInt, y: &BigInt| {
+      calls.set(calls.get() + 1);
+      x + y
+    };
+    assert!(r1cs.is_satisfied(counting_hash));
+    assert!(r1cs.is_satisfied(counting_hash));
+    assert_eq!(calls.get(), 1);
+
+    r1cs.variables[b].value = Some(int(5));
+    assert!(!r1cs.is_satisfied(counting_hash));
+    assert_eq!(calls.get(), 2);
+  }
+
+  #[test]
+  fn selected_constraint_is_only_enforced_when_its_selector_is_set() {
+    let system = |selector: i64| {
+      let mut r1cs = R1CS::new();
+      let a = r1cs.append_variable(Some(int(2)));
+      let b = r1cs.append_variable(Some(int(3)));
+      let wrong = r1cs.append_variable(Some(int(7)));
+      let selector = r1cs.append_variable(Some(int(selector)));
+      r1cs.add_selected_constraint(vec![(a, int(1))], vec![(b, int(1))], vec![(wrong, int(1))], Operation::Mul, selector);
+      r1cs
+    };
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+
+    assert!(system(0).is_satisfied(no_hash));
+    assert!(!system(1).is_satisfied(no_hash));
+  }
+
+  #[test]
+  fn proving_work_counts_only_multiplications() {
+    let mut r1cs = R1CS::new();
+    let x = r1cs.append_variable(Some(int(2)));
+    for operation in [Operation::Mul, Operation::Add, Operation::Add, Operation::Mul, Operation::Add] {
+      r1cs.add_constraints(vec![(x, int(1))], vec![(x, int(1))], vec![(x, int(1))], operation);
+    }
+    assert_eq!(r1cs.constraints.len(), 5);
+    assert_eq!(r1cs.estimate_proving_work(), 2);
+  }
+
+  #[test]
+  fn structure_only_system_serializes_and_refuses_to_be_checked() {
+    let mut r1cs = R1CS::new();
+    let a = r1cs.append_variable(Some(int(3)));
+    let b = r1cs.append_public_input(int(9));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(a, int(1))], vec![(b, int(1))], Operation::Mul);
+    r1cs.clear_witness();
+    assert!(r1cs.variables[a].value.is_none());
+
+    let decoded = R1CS::from_bytes(&r1cs.to_bytes()).unwrap();
+    assert!(decoded.variables[a].value.is_none());
+    assert_eq!(decoded.variables[b].value, Some(int(9)));
+
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+    let error = decoded.constraints[0].check(&decoded.variables, None, &no_hash).unwrap_err();
+    assert!(error.to_string().contains("no value assigned"), "{}", error);
+    assert!(!decoded.is_satisfied(no_hash));
+  }
+
+  #[test]
+  fn fraction_coefficient_halves_in_the_field() {
+    let modulus = int(97);
+    let half = fraction_coefficient(&int(1), &int(2), &modulus).unwrap();
+    assert_eq!(half, int(49));
+    assert_eq!(fraction_coefficient(&int(1), &int(0), &modulus), None);
+
+    let system = |claimed: i64| {
+      let mut r1cs = R1CS::new_in_field(modulus.clone());
+      let x = r1cs.append_variable(Some(int(4)));
+      let out = r1cs.append_variable(Some(int(claimed)));
+      r1cs.add_constraints(vec![(x, half.clone())], vec![], vec![(out, int(1))], Operation::Add);
+      r1cs
+    };
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+    assert!(system(2).is_satisfied(no_hash));
+    assert!(!system(3).is_satisfied(no_hash));
+  }
+
+  #[test]
+  fn satisfaction_range_isolates_the_bad_constraint() {
+    let mut r1cs = R1CS::new();
+    let a = r1cs.append_variable(Some(int(3)));
+    let b = r1cs.append_variable(Some(int(4)));
+    let sum = r1cs.append_variable(Some(int(7)));
+    let product = r1cs.append_variable(Some(int(13)));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(sum, int(1))], Operation::Add);
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(product, int(1))], Operation::Mul);
+    r1cs.add_constraints(vec![(sum, int(1))], vec![(a, int(1))], vec![(b, int(1))], Operation::Sub);
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+
+    assert!(!r1cs.is_satisfied(no_hash));
+    assert!(r1cs.is_satisfied_range(0, 1, no_hash));
+    assert!(r1cs.is_satisfied_range(2, 3, no_hash));
+    assert!(!r1cs.is_satisfied_range(1, 3, no_hash));
+  }
+
+  #[test]
+  fn satisfaction_can_be_checked_modulo_a_supplied_prime() {
+    let mut r1cs = R1CS::new();
+    let a = r1cs.append_variable(Some(int(3)));
+    let b = r1cs.append_variable(Some(int(4)));
+    let product = r1cs.append_variable(Some(int(5))); // 12 = 5 mod 7
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(product, int(1))], Operation::Mul);
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+
+    assert!(r1cs.is_satisfied_mod(&int(7), no_hash));
+    assert!(!r1cs.is_satisfied_mod(&int(11), no_hash));
+    assert!(!r1cs.is_satisfied(no_hash));
+  }
+
+  #[test]
+  fn hash3_constraint_checks_all_three_operands() {
+    let hash = |a: &BigInt, b: &BigInt| a * 31 + b;
+    let hash3 = |a: &BigInt, b: &BigInt, c: &BigInt| a * 961 + b * 31 + c;
+    let system = |values: [i64; 3]| {
+      let mut r1cs = R1CS::new();
+      let [a, b, c] = values.map(|value| r1cs.append_variable(Some(int(value))));
+      let out = r1cs.append_variable(Some(hash3(&int(1), &int(2), &int(3))));
+      r1cs.add_hash3_constraint(vec![(a, int(1))], vec![(b, int(1))], vec![(c, int(1))], vec![(out, int(1))]);
+      r1cs
+    };
+
+    assert!(system([1, 2, 3]).is_satisfied_with_hash3(hash, hash3));
+    for changed in [[9, 2, 3], [1, 9, 3], [1, 2, 9]] {
+      assert!(!system(changed).is_satisfied_with_hash3(hash, hash3));
+    }
+  }
+
+  #[test]
+  fn add_and_mul_system_survives_a_save_and_load() {
+    let mut r1cs = R1CS::new();
+    let [a, b, sum, product] = [2, 5, 7, 10].map(|value| r1cs.append_variable(Some(int(value))));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(sum, int(1))], Operation::Add);
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(product, int(1))], Operation::Mul);
+
+    let file = temp_file("add-mul");
+    r1cs.save_to_binary(&file).unwrap();
+    let loaded = R1CS::load_from_binary(&file);
+    let _ = std::fs::remove_file(&file);
+    let loaded = loaded.unwrap();
+
+    assert_eq!(loaded.to_bytes(), r1cs.to_bytes());
+    assert_eq!(loaded.variables[product].value, Some(int(10)));
+    assert!(matches!(loaded.constraints[..], [Constraint { operation: Operation::Add, .. }, Constraint { operation: Operation::Mul, .. }]));
+    assert!(loaded.is_satisfied(|_, _| unreachable!("no hash constraints")));
+  }
+
+  #[test]
+  fn batch_check_reports_each_assignment() {
+    let mut r1cs = R1CS::new();
+    let [a, b, product] = [2, 3, 6].map(|value| r1cs.append_variable(Some(int(value))));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(product, int(1))], Operation::Mul);
+
+    let assignments = [
+      HashMap::from([(a, int(4)), (product, int(12))]),
+      HashMap::from([(b, int(5))]),
+      HashMap::new()
+    ];
+    let no_hash = |_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") };
+    assert_eq!(r1cs.is_satisfied_batch(&assignments, no_hash), vec![true, false, true]);
+    assert_eq!(r1cs.variables[a].value, Some(int(2)));
+  }
+
+  #[test]
+  fn smtlib_export_of_an_add_constraint_is_well_formed() {
+    let mut r1cs = R1CS::new();
+    let a = r1cs.append_variable(Some(int(3)));
+    let b = r1cs.append_variable(Some(int(4)));
+    let sum = r1cs.append_public_input(int(7));
+    r1cs.add_constraints(vec![(a, int(1))], vec![(b, int(1))], vec![(sum, int(1))], Operation::Add);
+
+    let script = r1cs.to_smtlib();
+    assert_eq!(script, "(set-logic QF_UFNIA)\n(declare-fun hash (Int Int) Int)\n\
+      (declare-const v0 Int)\n(declare-const v1 Int)\n(declare-const v2 Int)\n\
+      (assert (= v2 7))\n\
+      (assert (= (+ (* 1 v0) (* 1 v1)) (* 1 v2)))\n\
+      (check-sat)\n");
+    for line in script.lines() {
+      assert_eq!(line.matches('(').count(), line.matches(')').count(), "unbalanced: {}", line);
+    }
+
+    // Feed it to Z3 when it is installed
+    let z3 = std::process::Command::new("z3").args(["-in"])
+      .stdin(std::process::Stdio::piped()).stdout(std::process::Stdio::piped()).spawn();
+    if let Ok(mut z3) = z3 {
+      z3.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+      let output = z3.wait_with_output().unwrap();
+      assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "sat");
+    }
+  }
+
+  #[test]
+  fn matrices_reproduce_satisfaction_of_a_known_witness() {
+    let mut r1cs = R1CS::new();
+    let x = r1cs.append_variable(Some(int(3)));
+    let y = r1cs.append_variable(Some(int(4)));
+    let product = r1cs.append_variable(Some(int(12)));
+    let sum = r1cs.append_variable(Some(int(15)));
+    let difference = r1cs.append_variable(Some(int(11)));
+    r1cs.add_constraints(vec![(x, int(1))], vec![(y, int(1))], vec![(product, int(1))], Operation::Mul);
+    r1cs.add_constraints(vec![(product, int(1))], vec![(x, int(1))], vec![(sum, int(1))], Operation::Add);
+    r1cs.add_constraints(vec![(sum, int(1))], vec![(y, int(1))], vec![(difference, int(1))], Operation::Sub);
+
+    let (a, b, c) = r1cs.to_matrices();
+    assert_eq!((a.len(), b.len(), c.len()), (3, 3, 3));
+    assert!(a.iter().chain(&b).chain(&c).all(|row| row.len() == 6));
+    // the Add row is linearized as (product + x) · 1 = sum
+    assert_eq!(a[1], vec![int(0), int(1), int(0), int(1), int(0), int(0)]);
+    assert_eq!(b[1], vec![int(1), int(0), int(0), int(0), int(0), int(0)]);
+
+    let dot = |row: &[BigInt], z: &[BigInt]| row.iter().zip(z).map(|(coeff, value)| coeff * value).sum::<BigInt>();
+    let holds = |z: &[BigInt], i: usize| dot(&a[i], z) * dot(&b[i], z) == dot(&c[i], z);
+    let mut z = r1cs.assignment_vector().unwrap();
+    assert!((0..3).all(|i| holds(&z, i)));
+    assert!(r1cs.is_satisfied(|_: &BigInt, _: &BigInt| -> BigInt { unreachable!("no hash constraints") }));
+
+    z[sum + 1] = int(16);
+    assert!(!holds(&z, 1) && !holds(&z, 2));
+    assert!(holds(&z, 0));
+  }
+}
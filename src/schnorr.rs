@@ -0,0 +1,93 @@
+use num_bigint::{BigInt, RandBigInt};
+use num_integer::Integer;
+use crate::ec::{Curve, Point};
+use crate::transcript::Transcript;
+
+/// A non-interactive proof of knowledge of the secret scalar behind a public key, see `prove`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchnorrProof {
+  pub commitment: Point, // R = k * G for the prover's random nonce k
+  pub challenge: BigInt, // c, derived from G, the public key and R
+  pub response: BigInt // s = k + c * x mod the group order
+}
+
+/// Returns the public key y = x * G of the secret `x`, G being the BN254 G1 generator.
+pub fn public_key(x: &BigInt) -> Point {
+  Curve::bn254().mul(&Curve::bn254_generator(), x)
+}
+
+/**
+ * Proves knowledge of `x` with y = x * G (g^x = y in multiplicative notation) without revealing it,
+ * Schnorr's sigma protocol over the BN254 G1 curve of the `ec` module:
+ * 1. commit: pick a random nonce k and send R = k * G;
+ * 2. challenge: c is derived by Fiat-Shamir from a transcript of G, y and R, standing in for the verifier's random choice;
+ * 3. respond: s = k + c * x mod the group order.
+ *
+ * The nonce hides x in s, so a fresh one is drawn for every proof: two proofs sharing a nonce reveal x.
+ */
+pub fn prove(x: &BigInt) -> SchnorrProof {
+  let curve = Curve::bn254();
+  let generator = Curve::bn254_generator();
+  let nonce = rand::thread_rng().gen_bigint_range(&BigInt::from(1), &curve.order);
+  let commitment = curve.mul(&generator, &nonce);
+  let challenge = challenge(&curve, &public_key(x), &commitment);
+  let response = (nonce + &challenge * x).mod_floor(&curve.order);
+  SchnorrProof { commitment, challenge, response }
+}
+
+/// Checks a proof from `prove` against the public key `y`: the challenge must be the one the transcript
+/// derives and s * G must equal R + c * y, which only a prover knowing x can arrange for a challenge it does not control.
+/// Rejects a public key or commitment that is not on the curve.
+pub fn verify(y: &Point, proof: &SchnorrProof) -> bool {
+  let curve = Curve::bn254();
+  if !curve.contains(y) || !curve.contains(&proof.commitment) || proof.challenge != challenge(&curve, y, &proof.commitment) {
+    return false;
+  }
+
+  let generator = Curve::bn254_generator();
+  curve.mul(&generator, &proof.response) == curve.add(&proof.commitment, &curve.mul(y, &proof.challenge))
+}
+
+/// Derives the Fiat-Shamir challenge binding the generator, the public key and the commitment, reduced modulo the group order.
+fn challenge(curve: &Curve, public_key: &Point, commitment: &Point) -> BigInt {
+  let mut transcript = Transcript::new();
+  append_point(&mut transcript, "generator", &Curve::bn254_generator());
+  append_point(&mut transcript, "public_key", public_key);
+  append_point(&mut transcript, "commitment", commitment);
+  transcript.challenge("schnorr_challenge").mod_floor(&curve.order)
+}
+
+/// Absorbs a point's coordinates; the point at infinity is absorbed as (0, 0), which is on no curve with b != 0.
+fn append_point(transcript: &mut Transcript, label: &str, point: &Point) {
+  let (x, y) = match point {
+    Point::Infinity => (BigInt::from(0), BigInt::from(0)),
+    Point::Affine(x, y) => (x.clone(), y.clone())
+  };
+  transcript.append(&format!("{}.x", label), &x);
+  transcript.append(&format!("{}.y", label), &y);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn honest_proofs_verify_and_forgeries_do_not() {
+    let secret = BigInt::from(123_456_789);
+    let y = public_key(&secret);
+    let proof = prove(&secret);
+    assert!(verify(&y, &proof));
+    assert!(!verify(&public_key(&BigInt::from(987_654_321)), &proof));
+
+    let mut forged = proof.clone();
+    forged.response += 1;
+    assert!(!verify(&y, &forged));
+
+    let mut forged = proof.clone();
+    forged.challenge += 1;
+    assert!(!verify(&y, &forged));
+
+    // a fresh nonce gives a different commitment every time
+    assert_ne!(prove(&secret).commitment, proof.commitment);
+  }
+}
@@ -0,0 +1,116 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use num_bigint::BigInt;
+use crate::circuit::Circuit;
+use crate::error::ZkMockError;
+use crate::proof::Proof;
+
+/**
+ * Fluent assertions for tests, chaining proof generation and verification:
+ *
+ * expect(&circuit).to_prove().with_public(&[BigInt::from(20)]).and_verify();
+ *
+ * Every step panics with a message naming what did not hold, so a failing chain reads like a failed assertion.
+ */
+pub fn expect(circuit: &Circuit) -> Expectation<'_> {
+  Expectation { circuit }
+}
+
+/// The start of an assertion chain, see `expect`.
+pub struct Expectation<'a> {
+  circuit: &'a Circuit
+}
+
+impl<'a> Expectation<'a> {
+  /// Proves the circuit and asserts the proof is valid.
+  pub fn to_prove(self) -> ProofExpectation<'a> {
+    let proof = self.circuit.try_prove().unwrap_or_else(|error| panic!("expected the circuit to prove, but proving failed: {}", error));
+    assert!(proof.valid, "expected the circuit to prove, but its witness does not satisfy the constraints");
+    ProofExpectation { circuit: self.circuit, proof }
+  }
+
+  /// Proves the circuit and asserts the proof is invalid, or that strict mode refused to prove it.
+  pub fn to_fail(self) {
+    let proof = match self.circuit.try_prove() {
+      Ok(proof) => proof,
+      Err(ZkMockError::UnsatisfiedCircuit { .. }) => return,
+      Err(error) => panic!("expected an invalid proof, but proving failed: {}", error)
+    };
+    assert!(!proof.valid, "expected the circuit to fail, but its witness satisfies every constraint");
+  }
+}
+
+/// A proven circuit awaiting further expectations.
+pub struct ProofExpectation<'a> {
+  circuit: &'a Circuit,
+  proof: Proof
+}
+
+// Distinguishes the proof files of chains running concurrently in the same test binary
+static PROOF_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl ProofExpectation<'_> {
+  /// Asserts the circuit's public inputs hold exactly `values`, in the order they were added.
+  pub fn with_public(self, values: &[BigInt]) -> Self {
+    let public: Vec<&BigInt> = self.circuit.public_inputs().iter()
+      .map(|&index| self.circuit.get_input(index).expect("public input index is in range"))
+      .collect();
+    assert!(
+      public.iter().copied().eq(values.iter()),
+      "expected public inputs {:?}, but the circuit has {:?}", values, public
+    );
+    self
+  }
+
+  /// Writes the proof to a temporary file and asserts the circuit verifies it.
+  pub fn and_verify(self) {
+    let proof_file = std::env::temp_dir().join(format!(
+      "zk-mock-expect-{}-{}.bin", std::process::id(), PROOF_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let proof_file = proof_file.to_str().expect("temporary directory path is valid UTF-8");
+
+    self.proof.save(proof_file);
+    let verified = self.circuit.verify_proof(proof_file);
+    let _ = std::fs::remove_file(proof_file);
+    let verified = verified.unwrap_or_else(|error| panic!("expected the proof to verify, but verifying failed: {}", error));
+    assert!(verified, "expected the proof to verify, but the verifier rejected it");
+  }
+
+  /// Returns the generated proof for further inspection.
+  pub fn proof(&self) -> &Proof {
+    &self.proof
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::circuit::Gate;
+
+  fn addition_circuit(sum: i64) -> Circuit {
+    let mut circuit = Circuit::new(None);
+    let a = circuit.add_input(BigInt::from(10));
+    let b = circuit.add_input(BigInt::from(10));
+    let sum = circuit.add_public_input(BigInt::from(sum));
+    circuit.add_gate(Gate::Add(a, b, sum));
+    circuit
+  }
+
+  #[test]
+  fn chain_passes_for_a_valid_circuit() {
+    let circuit = addition_circuit(20);
+    expect(&circuit).to_prove().with_public(&[BigInt::from(20)]).and_verify();
+    expect(&addition_circuit(21)).to_fail();
+  }
+
+  #[test]
+  #[should_panic(expected = "expected the circuit to prove")]
+  fn chain_panics_for_an_invalid_circuit() {
+    expect(&addition_circuit(21)).to_prove();
+  }
+
+  #[test]
+  #[should_panic(expected = "expected public inputs")]
+  fn chain_panics_on_unexpected_public_inputs() {
+    expect(&addition_circuit(20)).to_prove().with_public(&[BigInt::from(21)]);
+  }
+}
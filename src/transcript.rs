@@ -0,0 +1,132 @@
+use num_bigint::{BigInt, Sign};
+use crate::hash_functions::{HashFunction, Sha256Hash};
+
+/// One step recorded by a `Transcript`, in the order it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEntry {
+  Append { label: String, value: BigInt }, // a value absorbed into the transcript
+  Challenge { label: String, value: BigInt } // a challenge squeezed out of it
+}
+
+/**
+ * A Fiat-Shamir style transcript: absorbs labelled values and squeezes deterministic challenges from them,
+ * chaining one of the crate's `HashFunction`s, SHA-256 (`Sha256Hash`) unless another is given.
+ *
+ * Every step folds a domain tag (append or challenge), the label's length in bytes, the label (its UTF-8 bytes
+ * read as a big-endian integer) and the value into the running state, which starts at 0:
+ * state = hash(hash(hash(hash(state, tag), length), label), value). The length keeps labels such as "a" and "\0a",
+ * which read as the same integer, apart, so identical sequences of appends always produce identical challenges and
+ * any difference (value, label or order) makes them diverge. With a field-friendly hash such as `Poseidon` the
+ * challenges are field elements that a circuit could recompute, which is what a recursive verifier needs.
+ *
+ * Every step is also logged, so a nondeterministic verification failure can be debugged by comparing the
+ * `entries` of two transcripts.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript<H: HashFunction = Sha256Hash> {
+  hash_function: H,
+  state: BigInt, // hash chain over everything absorbed so far
+  entries: Vec<TranscriptEntry>
+}
+
+impl Default for Transcript {
+  fn default() -> Self {
+    Transcript::with_hash_function(Sha256Hash)
+  }
+}
+
+impl Transcript {
+  /// Creates an empty transcript hashing with SHA-256.
+  pub fn new() -> Self {
+    Transcript::default()
+  }
+}
+
+impl<H: HashFunction> Transcript<H> {
+  /// Creates an empty transcript hashing with `hash_function`.
+  pub fn with_hash_function(hash_function: H) -> Self {
+    Transcript { hash_function, state: BigInt::from(0), entries: Vec::new() }
+  }
+
+  /// Absorbs `value` under `label`.
+  pub fn append(&mut self, label: &str, value: &BigInt) {
+    self.absorb(1, label, value);
+    self.entries.push(TranscriptEntry::Append { label: label.to_string(), value: value.clone() });
+  }
+
+  /// Squeezes a challenge derived from everything absorbed so far; the challenge is the new state,
+  /// so consecutive challenges differ.
+  pub fn challenge(&mut self, label: &str) -> BigInt {
+    self.absorb(2, label, &BigInt::from(0));
+    let value = self.state.clone();
+    self.entries.push(TranscriptEntry::Challenge { label: label.to_string(), value: value.clone() });
+    value
+  }
+
+  /// Returns every append and challenge so far, in order.
+  pub fn entries(&self) -> &[TranscriptEntry] {
+    &self.entries
+  }
+
+  fn absorb(&mut self, tag: u8, label: &str, value: &BigInt) {
+    let tagged = self.hash_function.hash(&self.state, &BigInt::from(tag));
+    let sized = self.hash_function.hash(&tagged, &BigInt::from(label.len()));
+    let labelled = self.hash_function.hash(&sized, &BigInt::from_bytes_be(Sign::Plus, label.as_bytes()));
+    self.state = self.hash_function.hash(&labelled, value);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hash_functions::Poseidon;
+
+  fn int(value: i64) -> BigInt {
+    BigInt::from(value)
+  }
+
+  #[test]
+  fn identical_appends_agree_and_different_appends_diverge() {
+    let run = |appends: &[(&str, i64)]| {
+      let mut transcript = Transcript::new();
+      for (label, value) in appends {
+        transcript.append(label, &int(*value));
+      }
+      transcript.challenge("c")
+    };
+    let base = run(&[("x", 1), ("y", 2)]);
+    assert_eq!(run(&[("x", 1), ("y", 2)]), base);
+    assert_ne!(run(&[("x", 1), ("y", 3)]), base);
+    assert_ne!(run(&[("x", 1), ("z", 2)]), base);
+    assert_ne!(run(&[("y", 2), ("x", 1)]), base);
+
+    let mut transcript = Transcript::new();
+    transcript.append("x", &int(1));
+    assert_ne!(transcript.challenge("c"), transcript.challenge("c"));
+  }
+
+  #[test]
+  fn labels_are_length_prefixed() {
+    let run = |label: &str| {
+      let mut transcript = Transcript::new();
+      transcript.append(label, &int(1));
+      transcript.challenge("c")
+    };
+    assert_ne!(run("a"), run("\0a"));
+  }
+
+  #[test]
+  fn poseidon_transcripts_agree_and_diverge_like_the_sha256_one() {
+    let run = |appends: &[(&str, i64)]| {
+      let mut transcript = Transcript::with_hash_function(Poseidon::new());
+      for (label, value) in appends {
+        transcript.append(label, &int(*value));
+      }
+      transcript.challenge("c")
+    };
+    let base = run(&[("x", 1), ("y", 2)]);
+    assert_eq!(run(&[("x", 1), ("y", 2)]), base);
+    assert_ne!(run(&[("x", 1), ("y", 3)]), base);
+    assert_ne!(run(&[("y", 2), ("x", 1)]), base);
+  }
+}